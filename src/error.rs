@@ -0,0 +1,181 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// What went wrong while reading a scenario
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// The scenario could not be opened or read
+    Io(io::Error),
+    /// The first token on the line is not a known opcode
+    UnknownOpcode,
+    /// The line ended before a required field was found
+    MissingField(String),
+    /// A field was present but could not be parsed
+    InvalidField(String),
+    /// A structured (JSON or TOML) scenario is malformed
+    Syntax(String),
+    /// An included file could not be read
+    Include(String),
+    /// A variable was used without being defined
+    Undefined(String),
+    /// A macro was defined or used incorrectly
+    Macro(String),
+}
+
+/// An error encountered while reading a scenario, along with
+/// where in the scenario it was found
+#[derive(Debug)]
+pub struct ScenarioError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub opcode: String,
+    pub token: String,
+    pub kind: ErrorKind,
+}
+
+impl ScenarioError {
+    pub fn io(file: &str, error: io::Error) -> Self {
+        ScenarioError {
+            file: String::from(file),
+            line: 0,
+            column: 0,
+            opcode: String::new(),
+            token: String::new(),
+            kind: ErrorKind::Io(error),
+        }
+    }
+}
+
+impl ScenarioError {
+    /// Where the error was found, as file:line:column when the
+    /// line is known and just the file otherwise
+    fn location(&self) -> String {
+        if self.line == 0 {
+            return self.file.clone();
+        }
+        format!("{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            ErrorKind::Io(e) => write!(f, "{}: {}", self.file, e),
+            ErrorKind::UnknownOpcode => {
+                write!(f, "{}: unknown opcode '{}'", self.location(), self.token)
+            }
+            ErrorKind::MissingField(expected) => write!(
+                f,
+                "{}: {}: expected {}, got end of line",
+                self.location(),
+                self.opcode,
+                expected
+            ),
+            ErrorKind::InvalidField(expected) => write!(
+                f,
+                "{}: {}: expected {}, got '{}'",
+                self.location(),
+                self.opcode,
+                expected,
+                self.token
+            ),
+            ErrorKind::Syntax(message) => write!(f, "{}: {}", self.location(), message),
+            ErrorKind::Include(message) | ErrorKind::Macro(message) => {
+                write!(f, "{}: {}: {}", self.location(), self.opcode, message)
+            }
+            ErrorKind::Undefined(name) => write!(
+                f,
+                "{}: {}: undefined variable '{}'",
+                self.location(),
+                self.opcode,
+                name
+            ),
+        }
+    }
+}
+
+impl Error for ScenarioError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            ErrorKind::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Where in a scenario something was defined
+#[derive(Debug, Clone, PartialEq)]
+pub struct Origin {
+    pub file: String,
+    pub line: usize,
+}
+
+impl Origin {
+    pub fn new(file: &str, line: usize) -> Self {
+        Origin {
+            file: String::from(file),
+            line,
+        }
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // structured scenarios don't have line numbers
+        if self.line == 0 {
+            return write!(f, "{}", self.file);
+        }
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A semantic problem found while validating a parsed scenario
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub origin: Option<Origin>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn warning(origin: Option<&Origin>, message: String) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            origin: origin.cloned(),
+            message,
+        }
+    }
+
+    pub fn error(origin: Option<&Origin>, message: String) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            origin: origin.cloned(),
+            message,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        match &self.origin {
+            Some(o) => write!(f, "{}: {}: {}", o, severity, self.message),
+            None => write!(f, "{}: {}", severity, self.message),
+        }
+    }
+}
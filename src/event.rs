@@ -0,0 +1,52 @@
+use crate::location::*;
+use std::fmt;
+
+/// Something notable that happened to a unit while the simulation ran
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventKind {
+    /// The unit reached the last waypoint of its route
+    RouteComplete,
+    /// The unit got within range of the target it was told to intercept
+    InterceptAchieved { target: String },
+    /// The aircraft set down on a ship
+    Landed { ship: String },
+    /// The aircraft got down to bingo fuel, and is heading back to its ship
+    BingoFuel { ship: String },
+    /// The aircraft ran out of fuel and was lost
+    FuelExhausted,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub t: Timestamp,
+    pub id: String,
+    pub kind: EventKind,
+}
+
+impl Event {
+    pub fn new(t: Timestamp, id: &str, kind: EventKind) -> Self {
+        Event {
+            t,
+            id: String::from(id),
+            kind,
+        }
+    }
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EventKind::RouteComplete => write!(f, "route complete"),
+            EventKind::InterceptAchieved { target } => write!(f, "intercepted {}", target),
+            EventKind::Landed { ship } => write!(f, "landed on {}", ship),
+            EventKind::BingoFuel { ship } => write!(f, "bingo fuel, returning to {}", ship),
+            EventKind::FuelExhausted => write!(f, "fuel exhausted, aircraft lost"),
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}: {}", self.t, self.id, self.kind)
+    }
+}
@@ -0,0 +1,1031 @@
+use crate::event::EventKind;
+use crate::location::*;
+use crate::movable::Kinematics;
+use crate::order::{Pattern, Waypoint};
+
+/// Calculate a new position using 'dead reckoning'
+pub fn calc_new_position(
+    loc: Location,
+    heading: f64,
+    speed: f64,
+    curr_tm: Timestamp,
+    prev_tm: Timestamp,
+) -> Location {
+    let time = hours(curr_tm - prev_tm);
+    let distance = speed * time;
+    let dx = distance * heading.to_radians().sin();
+    let dy = distance * heading.to_radians().cos();
+    Location::new(loc.x + dx, loc.y + dy, loc.z, curr_tm)
+}
+
+/// A duration as fractional hours, which speeds are given in
+pub fn hours(d: chrono::Duration) -> f64 {
+    d.num_milliseconds() as f64 / (60.0 * 60.0 * 1000.0)
+}
+
+/// Fractional hours as a duration, to the nearest millisecond
+fn duration(hours: f64) -> chrono::Duration {
+    chrono::Duration::milliseconds((hours * 60.0 * 60.0 * 1000.0).round() as i64)
+}
+
+/// The heading, speed and z a unit has been ordered to make, which it
+/// turns, accelerates and climbs towards as fast as it is able.
+/// z is below the surface for submarines, as for locations
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Helm {
+    pub heading: f64,
+    pub speed: f64,
+    pub z: f64,
+}
+
+impl Helm {
+    pub fn new(heading: f64, speed: f64, z: f64) -> Self {
+        Helm { heading, speed, z }
+    }
+}
+
+/// The turn from one heading to another the shorter way round, in
+/// degrees and clockwise when positive. Reversing course turns to starboard
+fn turn_between(from: f64, to: f64) -> f64 {
+    let turn = (to - from).rem_euclid(360.0);
+    if turn > 180.0 {
+        return turn - 360.0;
+    }
+    turn
+}
+
+/// Where a unit starting from `loc` on `heading` gets to in `time` hours
+/// at `speed`, turning at `rate` degrees an hour, clockwise when positive
+fn calc_arc_position(loc: &Location, heading: f64, rate: f64, speed: f64, time: f64) -> Location {
+    if rate == 0.0 {
+        let distance = speed * time;
+        let dx = distance * heading.to_radians().sin();
+        let dy = distance * heading.to_radians().cos();
+        return Location::new(loc.x + dx, loc.y + dy, loc.z, loc.get_time());
+    }
+    // the radius comes out negative for turns to port, which keeps
+    // the centre of the turn on the correct side
+    let radius = speed / rate.to_radians();
+    let (h0, h1) = (heading.to_radians(), (heading + rate * time).to_radians());
+    let dx = radius * (h0.cos() - h1.cos());
+    let dy = radius * (h1.sin() - h0.sin());
+    Location::new(loc.x + dx, loc.y + dy, loc.z, loc.get_time())
+}
+
+/// Move from `loc` at `prev` to time `t`, turning and changing speed
+/// towards what the helm has been ordered no faster than `kinematics`
+/// allow. While turning the unit flies a constant-rate arc, and once it
+/// is on the ordered heading it carries straight on. The z is left
+/// to the unit, which climbs by `Kinematics::climb`
+pub fn manoeuvre(
+    loc: &Location,
+    heading: f64,
+    speed: f64,
+    helm: &Helm,
+    kinematics: &Kinematics,
+    t: Timestamp,
+    prev: Timestamp,
+) -> Leg {
+    let time = hours(t - prev);
+    let turn = turn_between(heading, helm.heading);
+    let change = helm.speed - speed;
+    // rates per hour, signed the way the unit is going
+    let rate = kinematics.turn_rate * 60.0 * turn.signum();
+    let accel = kinematics.acceleration * 60.0 * change.signum();
+    let turning = if turn == 0.0 { 0.0 } else { turn / rate };
+    let accelerating = if change == 0.0 { 0.0 } else { change / accel };
+
+    // the update splits into pieces where the turn and the
+    // change of speed stop, each flown at a constant turn rate
+    let mut ends = [turning.min(time), accelerating.min(time), time];
+    ends.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let mut leg = Leg {
+        loc: loc.clone(),
+        heading,
+        speed,
+        altitude: None,
+        ordered_speed: None,
+        completed: None,
+    };
+    let mut done = 0.0;
+    for end in ends {
+        let span = end - done;
+        if span <= 0.0 {
+            continue;
+        }
+        let rate = if done < turning { rate } else { 0.0 };
+        let accel = if done < accelerating { accel } else { 0.0 };
+        let end_speed = leg.speed + accel * span;
+        let mean_speed = (leg.speed + end_speed) / 2.0;
+        leg.loc = calc_arc_position(&leg.loc, leg.heading, rate, mean_speed, span);
+        leg.heading = (leg.heading + rate * span).rem_euclid(360.0);
+        leg.speed = end_speed;
+        done = end;
+    }
+    // finish exactly on what was ordered, rather than nearly
+    if turning <= time {
+        leg.heading = helm.heading;
+    }
+    if accelerating <= time {
+        leg.speed = helm.speed;
+    }
+    leg.loc = Location::new(leg.loc.x, leg.loc.y, loc.z, t);
+    leg
+}
+
+/// Move a unit from `loc` at `prev` to time `t`, as every unit moves.
+/// Any guidance only decides what the helm is set to, and the unit then
+/// manoeuvres towards that no faster than `kinematics` allow. Only where
+/// the unit actually got to completes the guidance, which is then
+/// cleared, and the leg says when and how it did
+#[allow(clippy::too_many_arguments)]
+pub fn steer(
+    loc: &Location,
+    heading: f64,
+    speed: f64,
+    helm: &mut Helm,
+    guidance: &mut Option<Guidance>,
+    kinematics: &Kinematics,
+    max_speed: f64,
+    t: Timestamp,
+    prev: Timestamp,
+    loc_map: &LocationMap,
+) -> Leg {
+    let mut aim = None;
+    if let Some(g) = guidance.as_ref() {
+        // plan on a copy, as the unit may not get as far as planned
+        let leg = g
+            .clone()
+            .advance(loc, heading, speed, t, prev, loc_map, max_speed, kinematics);
+        // steer for where the guidance would have the unit by `t`, which
+        // on a curving path is the chord across it rather than the
+        // tangent at its far end
+        helm.heading = if loc.distance(&leg.loc) > 1e-9 {
+            loc.bearing_to(&leg.loc)
+        } else {
+            leg.heading
+        };
+        if let Some(s) = leg.ordered_speed {
+            helm.speed = s;
+        }
+        aim = Some(leg);
+    }
+    let mut leg = manoeuvre(loc, heading, speed, helm, kinematics, t, prev);
+    if let (Some(g), Some(aim)) = (guidance.as_mut(), aim) {
+        leg.altitude = aim.altitude;
+        // a unit can't get closer to a point ahead than it can turn, and
+        // steering across corners between updates cuts them by up to
+        // half the distance flown in one
+        let fastest = speed.max(leg.speed);
+        let radius = kinematics.turn_radius(fastest) + fastest * hours(t - prev) / 2.0;
+        leg.completed = g.arrive(loc, &leg.loc, radius, loc_map);
+        if leg.completed.is_some() {
+            // with nothing left to steer for, carry on as the guidance finished
+            if let Some(h) = g.exit_heading() {
+                helm.heading = h;
+            }
+            *guidance = None;
+        }
+    }
+    leg
+}
+
+/// How far a unit got along its route during one update
+#[derive(Debug)]
+pub struct Leg {
+    pub loc: Location,
+    pub heading: f64,
+    pub speed: f64,
+    /// the altitude of the last waypoint passed or headed for, if it gave one
+    pub altitude: Option<f64>,
+    /// the speed the guidance has the unit make, if it sets one
+    pub ordered_speed: Option<f64>,
+    /// when and how the guidance finished, if it finished during this update
+    pub completed: Option<(Timestamp, EventKind)>,
+}
+
+/// How a unit is being steered, in place of a fixed heading
+#[derive(Debug, Clone)]
+pub enum Guidance {
+    Route(Route),
+    Orbit(Orbit),
+    Station(Station),
+    Intercept(Intercept),
+}
+
+impl Guidance {
+    /// Steering that flies a patrol pattern until it is superseded
+    pub fn patrol(pattern: &Pattern) -> Self {
+        let point = |x: f64, y: f64| Waypoint {
+            x,
+            y,
+            altitude: None,
+            speed: None,
+        };
+        match pattern {
+            Pattern::Racetrack { x1, y1, x2, y2 } => {
+                Guidance::Route(Route::looping(vec![point(*x1, *y1), point(*x2, *y2)]))
+            }
+            Pattern::Orbit { x, y, radius } => Guidance::Orbit(Orbit::new(*x, *y, *radius)),
+            // clockwise, starting from the north west corner
+            Pattern::Box {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let (w, h) = (width / 2.0, height / 2.0);
+                Guidance::Route(Route::looping(vec![
+                    point(x - w, y + h),
+                    point(x + w, y + h),
+                    point(x + w, y - h),
+                    point(x - w, y - h),
+                ]))
+            }
+        }
+    }
+
+    /// Move from `loc` at `prev` to time `t`, as if the unit could turn and
+    /// change speed at once. `steer` sets the helm to make for where this
+    /// gets to, at its speed. Guidance relative to other units finds them in
+    /// `loc_map`, and won't go faster than `max_speed` or close on them
+    /// faster than `kinematics` let the unit slow down again
+    #[allow(clippy::too_many_arguments)]
+    pub fn advance(
+        &mut self,
+        loc: &Location,
+        heading: f64,
+        speed: f64,
+        t: Timestamp,
+        prev: Timestamp,
+        loc_map: &LocationMap,
+        max_speed: f64,
+        kinematics: &Kinematics,
+    ) -> Leg {
+        match self {
+            Guidance::Route(r) => r.advance(loc, heading, speed, t, prev),
+            Guidance::Orbit(o) => o.advance(loc, heading, speed, t, prev),
+            Guidance::Station(s) => {
+                s.advance(loc, heading, speed, t, prev, loc_map, max_speed, kinematics)
+            }
+            Guidance::Intercept(i) => i.advance(loc, heading, speed, t, prev, loc_map),
+        }
+    }
+
+    /// Whether a unit that actually moved from `from` to `to` completed the
+    /// guidance, and when. `radius` is as close as it can turn onto a point
+    pub fn arrive(
+        &mut self,
+        from: &Location,
+        to: &Location,
+        radius: f64,
+        loc_map: &LocationMap,
+    ) -> Option<(Timestamp, EventKind)> {
+        match self {
+            Guidance::Route(r) => r.arrive(from, to, radius),
+            Guidance::Orbit(_) | Guidance::Station(_) => None,
+            Guidance::Intercept(i) => i.arrive(from, to, loc_map),
+        }
+    }
+
+    /// The heading to carry on along once the guidance is complete,
+    /// if it gives one
+    pub fn exit_heading(&self) -> Option<f64> {
+        match self {
+            Guidance::Route(r) => r.last_leg_heading(),
+            _ => None,
+        }
+    }
+}
+
+/// A list of waypoints a unit is steering through in turn
+#[derive(Debug, Clone)]
+pub struct Route {
+    waypoints: Vec<Waypoint>,
+    next: usize,
+    /// whether to start again from the first waypoint after the last
+    looping: bool,
+}
+
+impl Route {
+    pub fn new(waypoints: Vec<Waypoint>) -> Self {
+        Route {
+            waypoints,
+            next: 0,
+            looping: false,
+        }
+    }
+
+    /// A route that is flown round and round, and so never completes
+    pub fn looping(waypoints: Vec<Waypoint>) -> Self {
+        Route {
+            waypoints,
+            next: 0,
+            looping: true,
+        }
+    }
+
+    /// The waypoint currently being steered for
+    pub fn get_next(&self) -> Option<&Waypoint> {
+        self.waypoints.get(self.next)
+    }
+
+    /// The heading from the last waypoint but one to the last, which the
+    /// unit carries on along once the route is complete, rather than on
+    /// however it came to the last waypoint
+    pub fn last_leg_heading(&self) -> Option<f64> {
+        if let [.., from, to] = self.waypoints.as_slice() {
+            let (dx, dy) = (to.x - from.x, to.y - from.y);
+            if dx != 0.0 || dy != 0.0 {
+                return Some(dx.atan2(dy).to_degrees().rem_euclid(360.0));
+            }
+        }
+        None
+    }
+
+    /// Pass whichever waypoints a unit that moved from `from` to `to` reached,
+    /// in turn, and say when it completed the route if it did. A waypoint is
+    /// reached once the unit is on it, or has stopped getting any closer
+    /// within `radius`, as it can't turn tightly enough to get closer still.
+    /// The unit is taken to have moved in a straight line
+    pub fn arrive(
+        &mut self,
+        from: &Location,
+        to: &Location,
+        radius: f64,
+    ) -> Option<(Timestamp, EventKind)> {
+        let (dx, dy) = (to.x - from.x, to.y - from.y);
+        let length = dx * dx + dy * dy;
+        // how far along the move each waypoint was passed, as a fraction
+        let mut done = 0.0;
+        let mut lap_start = 0.0;
+        while let Some(wp) = self.waypoints.get(self.next) {
+            let (wx, wy) = (wp.x - from.x, wp.y - from.y);
+            let along = if length > 0.0 {
+                ((wx * dx + wy * dy) / length).clamp(done, 1.0)
+            } else {
+                done
+            };
+            // still closing on it, or never came near enough
+            let closest = (dx * along - wx).hypot(dy * along - wy);
+            if closest > 1e-6 && (along >= 1.0 || closest > radius) {
+                break;
+            }
+            done = along;
+            self.next += 1;
+            if self.next == self.waypoints.len() {
+                if !self.looping {
+                    let at =
+                        from.get_time() + duration(hours(to.get_time() - from.get_time()) * done);
+                    return Some((at, EventKind::RouteComplete));
+                } else if done == lap_start {
+                    // every waypoint is in reach at once, so go round no more
+                    self.next = 0;
+                    break;
+                } else {
+                    self.next = 0;
+                    lap_start = done;
+                }
+            }
+        }
+        None
+    }
+
+    /// Move from `loc` at `prev` to time `t`, heading straight for each
+    /// waypoint in turn. Any time left over after reaching a waypoint is
+    /// spent on the next leg, and once the route is complete the unit
+    /// carries on along the last leg's heading and speed
+    pub fn advance(
+        &mut self,
+        loc: &Location,
+        heading: f64,
+        speed: f64,
+        t: Timestamp,
+        prev: Timestamp,
+    ) -> Leg {
+        let mut leg = Leg {
+            loc: loc.clone(),
+            heading,
+            speed,
+            altitude: None,
+            ordered_speed: None,
+            completed: None,
+        };
+        let mut at = prev;
+        // when the current lap of a looping route began
+        let mut lap_start = prev;
+        while let Some(wp) = self.waypoints.get(self.next) {
+            if let Some(s) = wp.speed {
+                leg.speed = s;
+                leg.ordered_speed = Some(s);
+            }
+            if wp.altitude.is_some() {
+                leg.altitude = wp.altitude;
+            }
+            let target = Location::new(wp.x, wp.y, leg.loc.z, at);
+            let distance = leg.loc.distance(&target);
+            if distance > 0.0 {
+                leg.heading = leg.loc.bearing_to(&target);
+            }
+            // a stopped unit never gets any closer
+            if leg.speed <= 0.0 && distance > 0.0 {
+                break;
+            }
+            if leg.speed * hours(t - at) < distance {
+                break;
+            }
+            let taken = if distance > 0.0 {
+                distance / leg.speed
+            } else {
+                0.0
+            };
+            at += duration(taken);
+            leg.loc = target;
+            self.next += 1;
+            if self.next == self.waypoints.len() {
+                if !self.looping {
+                    if let Some(h) = self.last_leg_heading() {
+                        leg.heading = h;
+                    }
+                    leg.completed = Some((at, EventKind::RouteComplete));
+                } else if at == lap_start {
+                    // every waypoint is in the same place, so stay put
+                    self.next = 0;
+                    break;
+                } else {
+                    self.next = 0;
+                    lap_start = at;
+                }
+            }
+        }
+        leg.loc = calc_new_position(leg.loc, leg.heading, leg.speed, t, at);
+        leg
+    }
+}
+
+/// Clockwise circles around a point
+#[derive(Debug, Clone)]
+pub struct Orbit {
+    x: f64,
+    y: f64,
+    radius: f64,
+}
+
+impl Orbit {
+    pub fn new(x: f64, y: f64, radius: f64) -> Self {
+        Orbit { x, y, radius }
+    }
+
+    /// Head straight for the nearest point on the circle,
+    /// then fly around it for whatever time is left
+    pub fn advance(
+        &mut self,
+        loc: &Location,
+        heading: f64,
+        speed: f64,
+        t: Timestamp,
+        prev: Timestamp,
+    ) -> Leg {
+        let mut leg = Leg {
+            loc: loc.clone(),
+            heading,
+            speed,
+            altitude: None,
+            ordered_speed: None,
+            completed: None,
+        };
+        let centre = Location::new(self.x, self.y, loc.z, prev);
+        let from_centre = centre.distance(loc);
+        let mut at = prev;
+        if (from_centre - self.radius).abs() > 1e-6 {
+            // from the very centre any direction will do, so go north
+            let (ux, uy) = if from_centre > 0.0 {
+                (
+                    (loc.x - self.x) / from_centre,
+                    (loc.y - self.y) / from_centre,
+                )
+            } else {
+                (0.0, 1.0)
+            };
+            let entry = Location::new(
+                self.x + self.radius * ux,
+                self.y + self.radius * uy,
+                loc.z,
+                prev,
+            );
+            let distance = loc.distance(&entry);
+            leg.heading = loc.bearing_to(&entry);
+            if speed <= 0.0 || speed * hours(t - at) < distance {
+                leg.loc = calc_new_position(loc.clone(), leg.heading, speed, t, at);
+                return leg;
+            }
+            let taken = distance / speed;
+            at += duration(taken);
+            leg.loc = entry;
+        }
+
+        let swept = (speed * hours(t - at) / self.radius).to_degrees();
+        let bearing = centre.bearing_to(&leg.loc) + swept;
+        leg.loc = Location::new(
+            self.x + self.radius * bearing.to_radians().sin(),
+            self.y + self.radius * bearing.to_radians().cos(),
+            loc.z,
+            t,
+        );
+        leg.heading = (bearing + 90.0).rem_euclid(360.0);
+        leg
+    }
+}
+
+/// Holding a bearing and range from a guide unit. The bearing is
+/// relative to the guide's heading, so the station turns with the guide
+#[derive(Debug, Clone)]
+pub struct Station {
+    guide: String,
+    bearing: f64,
+    range: f64,
+}
+
+impl Station {
+    pub fn new(guide: &str, bearing: f64, range: f64) -> Self {
+        Station {
+            guide: String::from(guide),
+            bearing,
+            range,
+        }
+    }
+
+    /// Where the station will be at time `t`, given where the guide is now
+    pub fn position(&self, guide: &Location, t: Timestamp) -> Location {
+        let ahead = calc_new_position(
+            guide.clone(),
+            guide.heading,
+            guide.speed,
+            t,
+            guide.get_time(),
+        );
+        let bearing = (guide.heading + self.bearing).to_radians();
+        Location::new(
+            ahead.x + self.range * bearing.sin(),
+            ahead.y + self.range * bearing.cos(),
+            0.0,
+            t,
+        )
+    }
+
+    /// Pick the heading and speed that keep up with the guide while closing
+    /// on the station, no faster than `max_speed`. The unit closes no faster
+    /// than it could slow down again at half the acceleration its `kinematics`
+    /// allow, which leaves it a margin to stop on station rather than
+    /// overshoot it. A guide that can't be found leaves the unit on its
+    /// current heading and speed
+    #[allow(clippy::too_many_arguments)]
+    pub fn advance(
+        &mut self,
+        loc: &Location,
+        heading: f64,
+        speed: f64,
+        t: Timestamp,
+        prev: Timestamp,
+        loc_map: &LocationMap,
+        max_speed: f64,
+        kinematics: &Kinematics,
+    ) -> Leg {
+        let mut leg = Leg {
+            loc: loc.clone(),
+            heading,
+            speed,
+            altitude: None,
+            ordered_speed: None,
+            completed: None,
+        };
+        let time = hours(t - prev);
+        if let (Some(guide), true) = (loc_map.get(self.guide.as_str()), time > 0.0) {
+            let station = self.position(guide, prev);
+            let gap = loc.distance(&station);
+            let h = guide.heading.to_radians();
+            let (mut vx, mut vy) = (guide.speed * h.sin(), guide.speed * h.cos());
+            if gap >= 1e-9 {
+                let braking = (kinematics.acceleration * 60.0 * gap).sqrt();
+                let closing = (gap / time).min(braking);
+                let b = loc.bearing_to(&station).to_radians();
+                vx += closing * b.sin();
+                vy += closing * b.cos();
+            }
+            leg.speed = vx.hypot(vy).min(max_speed);
+            leg.ordered_speed = Some(leg.speed);
+            leg.heading = if leg.speed < 1e-9 {
+                guide.heading
+            } else {
+                vx.atan2(vy).to_degrees().rem_euclid(360.0)
+            };
+        }
+        leg.loc = calc_new_position(loc.clone(), leg.heading, leg.speed, t, prev);
+        leg
+    }
+}
+
+/// Flying a collision course at a moving target, aiming at where the
+/// target will be rather than where it is (lead pursuit)
+#[derive(Debug, Clone)]
+pub struct Intercept {
+    target: String,
+    range: f64,
+}
+
+impl Intercept {
+    /// An intercept that is achieved once within `range` of the target
+    pub fn new(target: &str, range: f64) -> Self {
+        Intercept {
+            target: String::from(target),
+            range,
+        }
+    }
+
+    /// How many hours until a unit at `from` flying at `speed` could meet
+    /// the target, if it can catch it at all. The target is assumed to
+    /// hold its heading and speed
+    pub fn time_to_go(from: &Location, target: &Location, speed: f64) -> Option<f64> {
+        let (dx, dy) = (target.x - from.x, target.y - from.y);
+        let h = target.heading.to_radians();
+        let (vx, vy) = (target.speed * h.sin(), target.speed * h.cos());
+        // |d + v * tau| = speed * tau, as a quadratic in tau
+        let a = vx * vx + vy * vy - speed * speed;
+        let b = 2.0 * (dx * vx + dy * vy);
+        let c = dx * dx + dy * dy;
+        if a.abs() < 1e-9 {
+            // as fast as the target, so only catchable if it is closing
+            return if b < 0.0 { Some(-c / b) } else { None };
+        }
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        let mut times = [(-b - root) / (2.0 * a), (-b + root) / (2.0 * a)];
+        times.sort_by(|x, y| x.partial_cmp(y).unwrap());
+        times.iter().cloned().find(|tau| *tau >= 0.0)
+    }
+
+    /// When a unit that moved from `from` to `to` came within range of the
+    /// target, if it did. Both are taken to have moved in straight lines
+    pub fn arrive(
+        &self,
+        from: &Location,
+        to: &Location,
+        loc_map: &LocationMap,
+    ) -> Option<(Timestamp, EventKind)> {
+        let target = loc_map.get(self.target.as_str())?;
+        let (prev, t) = (from.get_time(), to.get_time());
+        let start = calc_new_position(
+            target.clone(),
+            target.heading,
+            target.speed,
+            prev,
+            target.get_time(),
+        );
+        let end = calc_new_position(
+            target.clone(),
+            target.heading,
+            target.speed,
+            t,
+            target.get_time(),
+        );
+        // the gap between them at the start, and how it changes over the move
+        let (gx, gy) = (from.x - start.x, from.y - start.y);
+        let (cx, cy) = (
+            (to.x - from.x) - (end.x - start.x),
+            (to.y - from.y) - (end.y - start.y),
+        );
+        let change = cx * cx + cy * cy;
+        let along = if change > 0.0 {
+            (-(gx * cx + gy * cy) / change).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        if (gx + cx * along).hypot(gy + cy * along) > self.range {
+            return None;
+        }
+        let achieved = EventKind::InterceptAchieved {
+            target: self.target.clone(),
+        };
+        Some((prev + duration(hours(t - prev) * along), achieved))
+    }
+
+    /// Steer for the intercept point, worked out afresh from where the target
+    /// is now. When the target can't be caught, head straight for it instead
+    pub fn advance(
+        &mut self,
+        loc: &Location,
+        heading: f64,
+        speed: f64,
+        t: Timestamp,
+        prev: Timestamp,
+        loc_map: &LocationMap,
+    ) -> Leg {
+        let mut leg = Leg {
+            loc: loc.clone(),
+            heading,
+            speed,
+            altitude: None,
+            ordered_speed: None,
+            completed: None,
+        };
+        let achieved = EventKind::InterceptAchieved {
+            target: self.target.clone(),
+        };
+        let target = match loc_map.get(self.target.as_str()) {
+            Some(target) => target,
+            None => {
+                leg.loc = calc_new_position(loc.clone(), heading, speed, t, prev);
+                return leg;
+            }
+        };
+        // where the target is at the start and end of this update
+        let at_prev = calc_new_position(
+            target.clone(),
+            target.heading,
+            target.speed,
+            prev,
+            target.get_time(),
+        )
+        .with_motion(target.heading, target.speed);
+        let at_t = calc_new_position(
+            target.clone(),
+            target.heading,
+            target.speed,
+            t,
+            target.get_time(),
+        );
+
+        if loc.distance(&at_prev) <= self.range {
+            leg.completed = Some((prev, achieved));
+        } else {
+            match Intercept::time_to_go(loc, &at_prev, speed) {
+                Some(tau) => {
+                    let aim = calc_new_position(
+                        at_prev.clone(),
+                        target.heading,
+                        target.speed,
+                        prev + duration(tau),
+                        prev,
+                    );
+                    leg.heading = loc.bearing_to(&aim);
+                    if tau <= hours(t - prev) {
+                        leg.completed = Some((prev + duration(tau), achieved));
+                    }
+                }
+                None => leg.heading = loc.bearing_to(&at_prev),
+            }
+        }
+        leg.loc = calc_new_position(loc.clone(), leg.heading, speed, t, prev);
+        if leg.completed.is_none() && leg.loc.distance(&at_t) <= self.range {
+            leg.completed = Some((
+                t,
+                EventKind::InterceptAchieved {
+                    target: self.target.clone(),
+                },
+            ));
+        }
+        leg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movable::MovableKind;
+
+    fn waypoint(x: f64, y: f64, speed: f64) -> Waypoint {
+        Waypoint {
+            x,
+            y,
+            altitude: None,
+            speed: Some(speed),
+        }
+    }
+
+    #[test]
+    fn test_manoeuvre() {
+        let t0 = timestamp(2015, 12, 14, 10, 0, 0);
+        let carrier = Kinematics::new(30.0, 3.0, 0.0);
+        let start = Location::new2(0.0, 0.0, t0);
+
+        // a carrier turns 30 degrees a minute, so only gets a sixth
+        // of the way round to the reciprocal in one update
+        let helm = Helm::new(180.0, 20.0, 0.0);
+        let t1 = timestamp(2015, 12, 14, 10, 1, 0);
+        let leg = manoeuvre(&start, 0.0, 20.0, &helm, &carrier, t1, t0);
+        assert!((leg.heading - 30.0).abs() < 1e-9);
+        assert_eq!(leg.loc.get_time(), t1);
+
+        // after six minutes it has turned through a semicircle to starboard,
+        // whose diameter is twice the speed over the turn rate in radians
+        let t6 = timestamp(2015, 12, 14, 10, 6, 0);
+        let leg = manoeuvre(&start, 0.0, 20.0, &helm, &carrier, t6, t0);
+        assert_eq!(leg.heading, 180.0);
+        let diameter = 2.0 * 20.0 / (30.0 * 60.0_f64).to_radians();
+        assert!((leg.loc.x - diameter).abs() < 1e-9);
+        assert!(leg.loc.y.abs() < 1e-9);
+
+        // turning to port, then carrying straight on
+        let helm = Helm::new(270.0, 20.0, 0.0);
+        let leg = manoeuvre(&start, 0.0, 20.0, &helm, &carrier, t6, t0);
+        assert_eq!(leg.heading, 270.0);
+        let radius = diameter / 2.0;
+        assert!((leg.loc.x - (-radius - 20.0 * 3.0 / 60.0)).abs() < 1e-9);
+        assert!((leg.loc.y - radius).abs() < 1e-9);
+
+        // working up to 12 knots at 3 knots a minute takes four minutes
+        let helm = Helm::new(0.0, 12.0, 0.0);
+        let t2 = timestamp(2015, 12, 14, 10, 2, 0);
+        let leg = manoeuvre(&start, 0.0, 0.0, &helm, &carrier, t2, t0);
+        assert_eq!(leg.speed, 6.0);
+        assert!((leg.loc.y - 0.1).abs() < 1e-9);
+        let leg = manoeuvre(&start, 0.0, 0.0, &helm, &carrier, t6, t0);
+        assert_eq!(leg.speed, 12.0);
+        assert!((leg.loc.y - 0.8).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_route_steers_toward_waypoint() {
+        let t0 = timestamp(2015, 12, 14, 10, 0, 0);
+        let t1 = timestamp(2015, 12, 14, 10, 6, 0);
+        let mut route = Route::new(vec![waypoint(10.0, 0.0, 30.0)]);
+        let leg = route.advance(&Location::new2(0.0, 0.0, t0), 0.0, 0.0, t1, t0);
+        assert_eq!(leg.heading, 90.0);
+        assert_eq!(leg.speed, 30.0);
+        assert!((leg.loc.x - 3.0).abs() < 1e-9);
+        assert!(leg.loc.y.abs() < 1e-9);
+        assert_eq!(leg.completed, None);
+    }
+
+    #[test]
+    fn test_route_turns_at_waypoint() {
+        // 6 minutes at 60 knots covers 6 nm: 2 east then 4 north
+        let t0 = timestamp(2015, 12, 14, 10, 0, 0);
+        let t1 = timestamp(2015, 12, 14, 10, 6, 0);
+        let mut route = Route::new(vec![waypoint(2.0, 0.0, 60.0), waypoint(2.0, 10.0, 60.0)]);
+        let leg = route.advance(&Location::new2(0.0, 0.0, t0), 0.0, 0.0, t1, t0);
+        assert_eq!(leg.heading, 0.0);
+        assert!((leg.loc.x - 2.0).abs() < 1e-9);
+        assert!((leg.loc.y - 4.0).abs() < 1e-6);
+        assert_eq!(route.get_next(), Some(&waypoint(2.0, 10.0, 60.0)));
+    }
+
+    #[test]
+    fn test_route_completes() {
+        let t0 = timestamp(2015, 12, 14, 10, 0, 0);
+        let t1 = timestamp(2015, 12, 14, 10, 6, 0);
+        let mut route = Route::new(vec![waypoint(0.0, 1.0, 60.0)]);
+        let leg = route.advance(&Location::new2(0.0, 0.0, t0), 0.0, 0.0, t1, t0);
+        assert_eq!(
+            leg.completed,
+            Some((timestamp(2015, 12, 14, 10, 1, 0), EventKind::RouteComplete))
+        );
+        // carries on north for the rest of the update
+        assert!((leg.loc.y - 6.0).abs() < 1e-6);
+        assert_eq!(route.get_next(), None);
+    }
+
+    #[test]
+    fn test_racetrack_loops() {
+        // 10 nm legs at 60 knots, so 15 minutes is one and a half legs
+        let t0 = timestamp(2015, 12, 14, 10, 0, 0);
+        let t1 = timestamp(2015, 12, 14, 10, 15, 0);
+        let pattern = Pattern::Racetrack {
+            x1: 0.0,
+            y1: 0.0,
+            x2: 0.0,
+            y2: 10.0,
+        };
+        let mut g = Guidance::patrol(&pattern);
+        let loc_map = LocationMap::new();
+        let leg = g.advance(
+            &Location::new2(0.0, 0.0, t0),
+            0.0,
+            60.0,
+            t1,
+            t0,
+            &loc_map,
+            60.0,
+            &Kinematics::default_for(MovableKind::Fighter),
+        );
+        assert!(leg.loc.x.abs() < 1e-9);
+        assert!((leg.loc.y - 5.0).abs() < 1e-6);
+        assert_eq!(leg.heading, 180.0);
+        assert_eq!(leg.completed, None);
+    }
+
+    #[test]
+    fn test_degenerate_racetrack() {
+        let t0 = timestamp(2015, 12, 14, 10, 0, 0);
+        let t1 = timestamp(2015, 12, 14, 10, 1, 0);
+        let pattern = Pattern::Racetrack {
+            x1: 0.0,
+            y1: 0.0,
+            x2: 0.0,
+            y2: 0.0,
+        };
+        let mut g = Guidance::patrol(&pattern);
+        let loc_map = LocationMap::new();
+        g.advance(
+            &Location::new2(0.0, 0.0, t0),
+            0.0,
+            60.0,
+            t1,
+            t0,
+            &loc_map,
+            60.0,
+            &Kinematics::default_for(MovableKind::Fighter),
+        );
+    }
+
+    #[test]
+    fn test_orbit() {
+        let t0 = timestamp(2015, 12, 14, 10, 0, 0);
+        let t1 = timestamp(2015, 12, 14, 10, 10, 0);
+        let mut g = Guidance::patrol(&Pattern::Orbit {
+            x: 0.0,
+            y: 0.0,
+            radius: 10.0,
+        });
+        // 5 nm to get out to the circle, then a quarter turn clockwise
+        let quarter = 10.0 * std::f64::consts::PI / 2.0;
+        let speed = (5.0 + quarter) * 6.0;
+        let loc_map = LocationMap::new();
+        let leg = g.advance(
+            &Location::new2(0.0, 5.0, t0),
+            0.0,
+            speed,
+            t1,
+            t0,
+            &loc_map,
+            speed,
+            &Kinematics::default_for(MovableKind::Fighter),
+        );
+        assert!((leg.loc.x - 10.0).abs() < 1e-3);
+        assert!(leg.loc.y.abs() < 1e-3);
+        assert!((leg.heading - 180.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_station_keeping() {
+        let t0 = timestamp(2015, 12, 14, 10, 0, 0);
+        let t1 = timestamp(2015, 12, 14, 10, 6, 0);
+        // the guide is heading east at 20 knots, and the station is
+        // 5 nm on its port beam, which is north
+        let mut loc_map = LocationMap::new();
+        let guide = Location::new2(0.0, 0.0, t0).with_motion(90.0, 20.0);
+        loc_map.insert(String::from("CVN-68"), guide);
+        let mut station = Station::new("CVN-68", 270.0, 5.0);
+        let cruiser = Kinematics::default_for(MovableKind::Cruiser);
+
+        // already on station, so match the guide
+        let loc = Location::new2(0.0, 5.0, t0);
+        let leg = station.advance(&loc, 0.0, 0.0, t1, t0, &loc_map, 30.0, &cruiser);
+        assert!((leg.heading - 90.0).abs() < 1e-6);
+        assert!((leg.speed - 20.0).abs() < 1e-6);
+        assert!((leg.loc.x - 2.0).abs() < 1e-6);
+        assert!((leg.loc.y - 5.0).abs() < 1e-6);
+
+        // too far off to make it in one update
+        let loc = Location::new2(0.0, -5.0, t0);
+        let leg = station.advance(&loc, 0.0, 0.0, t1, t0, &loc_map, 30.0, &cruiser);
+        assert_eq!(leg.speed, 30.0);
+        assert!(leg.heading < 90.0);
+    }
+
+    #[test]
+    fn test_time_to_go() {
+        let t = timestamp(2015, 12, 14, 10, 0, 0);
+        // a target 30 nm north heading east at 40 knots, chased at 50 knots,
+        // is met after an hour, 40 nm east of where it started
+        let target = Location::new2(0.0, 30.0, t).with_motion(90.0, 40.0);
+        let from = Location::new2(0.0, 0.0, t);
+        let tau = Intercept::time_to_go(&from, &target, 50.0).unwrap();
+        assert!((tau - 1.0).abs() < 1e-9);
+        // too slow to catch it
+        assert_eq!(Intercept::time_to_go(&from, &target, 30.0), None);
+    }
+
+    #[test]
+    fn test_intercept_leads_target() {
+        let t0 = timestamp(2015, 12, 14, 10, 0, 0);
+        let t1 = timestamp(2015, 12, 14, 10, 1, 0);
+        let mut loc_map = LocationMap::new();
+        let target = Location::new2(0.0, 30.0, t0).with_motion(90.0, 40.0);
+        loc_map.insert(String::from("CVN-68"), target);
+        let mut intercept = Intercept::new("CVN-68", 1.0);
+        let from = Location::new2(0.0, 0.0, t0);
+        let leg = intercept.advance(&from, 0.0, 50.0, t1, t0, &loc_map);
+        // aiming at 40,30 rather than at the target
+        assert!((leg.heading - 40.0f64.atan2(30.0).to_degrees()).abs() < 1e-6);
+        assert_eq!(leg.completed, None);
+
+        let t1 = timestamp(2015, 12, 14, 11, 0, 0);
+        let leg = intercept.advance(&from, 0.0, 50.0, t1, t0, &loc_map);
+        let (at, _) = leg.completed.unwrap();
+        assert!(at <= t1);
+    }
+}
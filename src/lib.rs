@@ -1,4 +1,12 @@
+pub mod error;
+pub mod event;
+pub mod guidance;
 pub mod location;
 pub mod movable;
+pub mod opcode;
 pub mod order;
+pub mod order_queue;
+pub mod parser;
+pub mod scenario;
 pub mod sim_manager;
+pub mod trigger;
@@ -1,20 +1,54 @@
+#![allow(clippy::needless_return, clippy::redundant_field_names)]
+
+use chrono::TimeZone;
 use std::collections::HashMap;
 
 pub type LocationMap = HashMap<String, Location>;
 
+/// A point in time, along with the UTC offset it was given in
+pub type Timestamp = chrono::DateTime<chrono::FixedOffset>;
+
+/// Build a UTC timestamp
+pub fn timestamp(year: i32, month: u32, day: u32, hour: u32, min: u32, sec: u32) -> Timestamp {
+    let naive = chrono::NaiveDate::from_ymd_opt(year, month, day)
+        .unwrap()
+        .and_hms_opt(hour, min, sec)
+        .unwrap();
+    return chrono::FixedOffset::east_opt(0)
+        .unwrap()
+        .from_utc_datetime(&naive);
+}
+
 #[derive(Debug)]
 pub struct Location {
     pub x: f64,
     pub y: f64,
     pub z: f64,
-    t: chrono::NaiveDateTime,
+    t: Timestamp,
+    /// the course and speed the unit was making at the time,
+    /// so other units can work out where it is heading
+    pub heading: f64,
+    pub speed: f64,
 }
 
 impl Location {
-    pub fn new(x: f64, y: f64, z: f64, t: chrono::NaiveDateTime) -> Location {
-        Location { x, y, z, t }
+    pub fn new(x: f64, y: f64, z: f64, t: Timestamp) -> Location {
+        Location {
+            x,
+            y,
+            z,
+            t,
+            heading: 0.0,
+            speed: 0.0,
+        }
     }
-    pub fn new2(x: f64, y: f64, t: chrono::NaiveDateTime) -> Location {
+    /// The same location, along with the course and speed being made
+    pub fn with_motion(mut self, heading: f64, speed: f64) -> Location {
+        self.heading = heading;
+        self.speed = speed;
+        return self;
+    }
+    pub fn new2(x: f64, y: f64, t: Timestamp) -> Location {
         Location::new(x, y, 0.0, t)
     }
     pub fn print(&self) {
@@ -34,22 +68,29 @@ impl Location {
         let dy = self.y - other.y;
         return (dx.powi(2) + dy.powi(2)).sqrt();
     }
+
+    /// The compass bearing from this location to another, in degrees
+    /// clockwise from north (the +y axis)
+    pub fn bearing_to(&self, other: &Location) -> f64 {
+        let dx = other.x - self.x;
+        let dy = other.y - self.y;
+        return dx.atan2(dy).to_degrees().rem_euclid(360.0);
+    }
+
+    pub fn get_time(&self) -> Timestamp {
+        return self.t;
+    }
 }
 
 impl Clone for Location {
     fn clone(&self) -> Location {
-        Location::new(self.x, self.y, self.z, self.t)
+        Location::new(self.x, self.y, self.z, self.t).with_motion(self.heading, self.speed)
     }
 }
 
 impl Default for Location {
     fn default() -> Location {
-        Location {
-            x: 0.0,
-            y: 0.0,
-            z: 0.0,
-            t: chrono::NaiveDate::from_ymd(2000, 1, 1).and_hms(0, 0, 0),
-        }
+        Location::new(0.0, 0.0, 0.0, timestamp(2000, 1, 1, 0, 0, 0))
     }
 }
 
@@ -59,14 +100,24 @@ mod tests {
 
     #[test]
     fn test_location_new() {
-        let t = chrono::NaiveDate::from_ymd(2015, 10, 21).and_hms(17, 2, 0);
+        let t = timestamp(2015, 10, 21, 17, 2, 0);
         let l = Location::new(1.0, 2.0, 3.0, t);
         l.print()
     }
 
+    #[test]
+    fn test_bearing_to() {
+        let t = timestamp(2015, 10, 21, 17, 2, 0);
+        let a = Location::new2(0.0, 0.0, t);
+        assert_eq!(a.bearing_to(&Location::new2(0.0, 5.0, t)), 0.0);
+        assert_eq!(a.bearing_to(&Location::new2(5.0, 0.0, t)), 90.0);
+        assert_eq!(a.bearing_to(&Location::new2(0.0, -5.0, t)), 180.0);
+        assert_eq!(a.bearing_to(&Location::new2(-5.0, 0.0, t)), 270.0);
+    }
+
     #[test]
     fn test_location_new2() {
-        let t = chrono::NaiveDate::from_ymd(2015, 10, 21).and_hms(17, 2, 0);
+        let t = timestamp(2015, 10, 21, 17, 2, 0);
         let l = Location::new2(1.0, 2.0, t);
         l.print()
     }
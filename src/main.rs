@@ -3,20 +3,104 @@ use seawarfare_rs::sim_manager::*;
 use std::env;
 extern crate chrono;
 
+fn usage(program: &str) -> ! {
+    println!(
+        "Usage: {} [--check] [--write <out>] [--order-log <out>] [-D NAME=VALUE]... <file>",
+        program
+    );
+    std::process::exit(1);
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    if args.len() != 2 {
-        println!("Usage: {} <file>", args[0]);
-        std::process::exit(1);
+    let mut check_only = false;
+    let mut write_to: Option<String> = None;
+    let mut order_log_to: Option<String> = None;
+    let mut filename: Option<String> = None;
+    let mut defines: Vec<(String, String)> = Vec::new();
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--check" => check_only = true,
+            "--write" => {
+                i += 1;
+                match args.get(i) {
+                    Some(out) => write_to = Some(out.clone()),
+                    None => usage(&args[0]),
+                }
+            }
+            "--order-log" => {
+                i += 1;
+                match args.get(i) {
+                    Some(out) => order_log_to = Some(out.clone()),
+                    None => usage(&args[0]),
+                }
+            }
+            "-D" | "--define" => {
+                i += 1;
+                let define = args.get(i).and_then(|d| d.split_once('='));
+                match define {
+                    Some((name, value)) => defines.push((String::from(name), String::from(value))),
+                    None => usage(&args[0]),
+                }
+            }
+            _ if filename.is_none() => filename = Some(args[i].clone()),
+            _ => usage(&args[0]),
+        }
+        i += 1;
     }
-    let filename = &args[1];
+    let filename = match filename {
+        Some(f) => f,
+        None => usage(&args[0]),
+    };
 
+    // a filename of "-" reads the scenario from stdin
     let mut sim = SimManager::new();
-    sim.init(filename);
+    for (name, value) in defines.iter() {
+        sim.define(name.as_str(), value.as_str());
+    }
+    let result = if filename == "-" {
+        let stdin = std::io::stdin();
+        sim.init_from_reader(stdin.lock(), "<stdin>")
+    } else {
+        sim.init(filename.as_str())
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+
+    let diags = sim.validate();
+    for d in diags.iter() {
+        eprintln!("{}", d);
+    }
+    if check_only {
+        let failed = diags.iter().any(|d| d.is_error());
+        std::process::exit(if failed { 1 } else { 0 });
+    }
+
+    // write the scenario back out instead of running it
+    if let Some(out) = write_to {
+        if let Err(e) = sim.save(out.as_str()) {
+            eprintln!("{}: {}", out, e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     sim.print();
     sim.print_navy();
     sim.print_orders();
     sim.execute();
     sim.print_history();
+    sim.print_events();
+    sim.print_order_log();
+
+    if let Some(out) = order_log_to {
+        if let Err(e) = sim.save_order_log(out.as_str()) {
+            eprintln!("{}: {}", out, e);
+            std::process::exit(1);
+        }
+    }
 }
@@ -1,19 +1,295 @@
+#![allow(clippy::needless_return, clippy::redundant_field_names)]
+
+use crate::event::*;
+use crate::guidance::*;
 use crate::location::*;
 use crate::order::*;
+use crate::scenario::PlatformDoc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 type HistoryList = Vec<Location>;
 
+/// Named values that orders defined outside the crate can keep on
+/// a unit, such as its EMCON state
+pub type UnitState = HashMap<String, String>;
+
+/// The type of platform a movable is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MovableKind {
+    Cruiser,
+    Carrier,
+    Submarine,
+    Fighter,
+    Helicopter,
+}
+
+impl MovableKind {
+    /// Ships, including submarines, carry out ship orders
+    pub fn is_ship(&self) -> bool {
+        return matches!(
+            self,
+            MovableKind::Cruiser | MovableKind::Carrier | MovableKind::Submarine
+        );
+    }
+
+    /// Whether helicopters can land on this type of ship
+    pub fn has_flight_deck(&self) -> bool {
+        return *self == MovableKind::Cruiser || *self == MovableKind::Carrier;
+    }
+
+    /// Whether this type of aircraft can operate from a type of ship.
+    /// Fighters need a carrier, helicopters any flight deck
+    pub fn can_land_on(&self, ship: MovableKind) -> bool {
+        match self {
+            MovableKind::Fighter => ship == MovableKind::Carrier,
+            MovableKind::Helicopter => ship.has_flight_deck(),
+            _ => false,
+        }
+    }
+}
+
+/// How fast, high and deep a unit can go
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    pub max_speed: f64,
+    pub max_ceiling: Option<f64>,
+    pub max_depth: Option<f64>,
+}
+
+impl Limits {
+    /// Every limit the order goes beyond
+    pub fn check(&self, order: &Order) -> Vec<RejectReason> {
+        let mut exceeded = Vec::new();
+        if let Some(speed) = order.get_speed().filter(|s| *s > self.max_speed) {
+            exceeded.push(RejectReason::ExceedsMaxSpeed {
+                speed: speed,
+                max_speed: self.max_speed,
+            });
+        }
+        if let (Some(altitude), Some(max_ceiling)) = (order.get_altitude(), self.max_ceiling) {
+            if altitude > max_ceiling {
+                exceeded.push(RejectReason::ExceedsMaxCeiling {
+                    altitude: altitude,
+                    max_ceiling: max_ceiling,
+                });
+            }
+        }
+        if let (Some(depth), Some(max_depth)) = (order.get_depth(), self.max_depth) {
+            if depth > max_depth {
+                exceeded.push(RejectReason::ExceedsMaxDepth {
+                    depth: depth,
+                    max_depth: max_depth,
+                });
+            }
+        }
+        return exceeded;
+    }
+
+    pub fn clamp_speed(&self, speed: f64) -> f64 {
+        return clamp_to(speed, Some(self.max_speed));
+    }
+
+    pub fn clamp_altitude(&self, altitude: f64) -> f64 {
+        return clamp_to(altitude, self.max_ceiling);
+    }
+
+    pub fn clamp_depth(&self, depth: f64) -> f64 {
+        return clamp_to(depth, self.max_depth);
+    }
+}
+
+/// A value held to a limit, leaving -1 for unchanged as it is
+fn clamp_to(value: f64, max: Option<f64>) -> f64 {
+    return match max {
+        Some(max) if value != -1.0 && value > max => max,
+        _ => value,
+    };
+}
+
+/// How quickly a unit can turn, change speed and change altitude or depth
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Kinematics {
+    /// degrees a minute
+    pub turn_rate: f64,
+    /// knots a minute, both speeding up and slowing down
+    pub acceleration: f64,
+    /// feet a minute, both climbing and descending. Submarines dive
+    /// at this rate, and it isn't used by surface ships
+    #[serde(default)]
+    pub climb_rate: f64,
+}
+
+impl Kinematics {
+    pub fn new(turn_rate: f64, acceleration: f64, climb_rate: f64) -> Self {
+        Kinematics {
+            turn_rate: turn_rate,
+            acceleration: acceleration,
+            climb_rate: climb_rate,
+        }
+    }
+
+    /// What a type of platform can do unless its Create opcode says otherwise
+    pub fn default_for(kind: MovableKind) -> Self {
+        match kind {
+            MovableKind::Cruiser => Kinematics::new(60.0, 6.0, 0.0),
+            MovableKind::Carrier => Kinematics::new(30.0, 3.0, 0.0),
+            MovableKind::Submarine => Kinematics::new(45.0, 3.0, 300.0),
+            MovableKind::Fighter => Kinematics::new(360.0, 300.0, 30000.0),
+            MovableKind::Helicopter => Kinematics::new(180.0, 60.0, 1500.0),
+        }
+    }
+
+    /// These kinematics if they aren't the defaults for the type of platform,
+    /// so scenarios only give what was overridden
+    pub fn if_overridden(&self, kind: MovableKind) -> Option<Self> {
+        if *self == Kinematics::default_for(kind) {
+            return None;
+        }
+        return Some(*self);
+    }
+
+    /// The radius of the tightest turn at `speed`, in nautical miles
+    pub fn turn_radius(&self, speed: f64) -> f64 {
+        return speed / (self.turn_rate * 60.0).to_radians();
+    }
+
+    /// The z reached after `time` hours climbing or descending towards `ordered`
+    pub fn climb(&self, z: f64, ordered: f64, time: f64) -> f64 {
+        let most = self.climb_rate * 60.0 * time;
+        return z + (ordered - z).clamp(-most, most);
+    }
+}
+
+/// How much fuel an aircraft carries and how quickly it burns it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Fuel {
+    /// pounds, which it is launched with
+    pub capacity: f64,
+    /// pounds an hour at max speed at sea level
+    pub max_burn: f64,
+    /// pounds left at which it gives up what it was doing and
+    /// heads back to land on its carrier
+    pub bingo: f64,
+}
+
+impl Default for Fuel {
+    /// About right for a fighter's internal fuel
+    fn default() -> Self {
+        Fuel::new(10800.0, 20000.0, 2500.0)
+    }
+}
+
+impl Fuel {
+    pub fn new(capacity: f64, max_burn: f64, bingo: f64) -> Self {
+        Fuel {
+            capacity: capacity,
+            max_burn: max_burn,
+            bingo: bingo,
+        }
+    }
+
+    /// This fuel model if it isn't the default, so scenarios only give
+    /// what was overridden
+    pub fn if_overridden(&self) -> Option<Self> {
+        if *self == Fuel::default() {
+            return None;
+        }
+        return Some(*self);
+    }
+
+    /// Pounds an hour burnt at a speed and altitude. Drag goes with the
+    /// square of speed, on top of a quarter of the max burn just to stay
+    /// airborne, and the thinner air at the ceiling takes off up to 40%
+    pub fn burn_rate(&self, speed: f64, max_speed: f64, altitude: f64, max_ceiling: f64) -> f64 {
+        let drag = 0.25 + 0.75 * (speed / max_speed).powi(2);
+        let thin_air = 1.0 - 0.4 * (altitude / max_ceiling).clamp(0.0, 1.0);
+        return self.max_burn * drag * thin_air;
+    }
+}
+
 pub trait Movable {
     fn get_is_deployed(&self) -> bool;
     fn get_was_deployed(&self) -> bool;
     fn get_id(&self) -> String;
     fn get_name(&self) -> String;
+    fn get_kind(&self) -> MovableKind;
     fn get_location(&self) -> Location;
+    fn get_max_speed(&self) -> f64;
+    /// The highest altitude an aircraft can fly at
+    fn get_max_ceiling(&self) -> Option<f64>;
+    /// The deepest a submarine can dive
+    fn get_max_depth(&self) -> Option<f64> {
+        return None;
+    }
+    /// How quickly the unit can turn, change speed and climb
+    fn get_kinematics(&self) -> Kinematics;
+    /// The pounds of fuel an aircraft has left, for those that use it up
+    fn get_fuel(&self) -> Option<f64> {
+        return None;
+    }
+    /// The ship an aircraft operates from
+    fn get_ship_id(&self) -> Option<String>;
     fn get_history(&self) -> &HistoryList;
-    fn deploy(&mut self, x: f64, y: f64, head: f64, spd: f64, t: chrono::NaiveDateTime) -> bool;
-    fn change(&mut self, head: f64, spd: f64, alt: f64, t: chrono::NaiveDateTime) -> bool;
-    fn update_position(&mut self, t: chrono::NaiveDateTime, loc_map: &LocationMap);
-    fn execute(&mut self, order: &Order);
+    /// Describe the platform as it would be created in a scenario
+    fn to_doc(&self) -> PlatformDoc;
+    fn deploy(&mut self, x: f64, y: f64, head: f64, spd: f64, t: Timestamp) -> OrderOutcome;
+    fn change(&mut self, head: f64, spd: f64, alt: f64, t: Timestamp) -> OrderOutcome;
+    /// Launch an aircraft from the ship it operates from
+    fn launch(&mut self, _head: f64, _spd: f64, _alt: f64, _t: Timestamp) -> OrderOutcome {
+        return OrderOutcome::Rejected(RejectReason::WrongPlatform(self.get_kind()));
+    }
+    /// Send an aircraft to land on a ship
+    fn land(&mut self, _ship_id: String, _t: Timestamp) -> OrderOutcome {
+        return OrderOutcome::Rejected(RejectReason::WrongPlatform(self.get_kind()));
+    }
+    /// Stop a helicopter where it is, at `alt` unless that is -1
+    fn hover(&mut self, _alt: f64, _t: Timestamp) -> OrderOutcome {
+        return OrderOutcome::Rejected(RejectReason::WrongPlatform(self.get_kind()));
+    }
+    /// Take a submarine to a depth, surfacing it at 0
+    fn change_depth(&mut self, _depth: f64, _t: Timestamp) -> OrderOutcome {
+        return OrderOutcome::Rejected(RejectReason::WrongPlatform(self.get_kind()));
+    }
+    /// Steer by the guidance, such as a route or patrol pattern,
+    /// until another order gives a new heading
+    fn guide(&mut self, guidance: Guidance, t: Timestamp) -> OrderOutcome;
+    /// Why `guide` would be refused just now, if it would, so orders that
+    /// change other things as well can check before changing any of them
+    fn guidance_refused(&self) -> Option<RejectReason> {
+        return None;
+    }
+    fn update_position(&mut self, t: Timestamp, loc_map: &LocationMap);
+    /// Carry out an order, or say why it can't be
+    fn execute(&mut self, order: &Order) -> OrderOutcome;
+    /// The most the unit can do, which orders are held to
+    fn get_limits(&self) -> Limits {
+        return Limits {
+            max_speed: self.get_max_speed(),
+            max_ceiling: self.get_max_ceiling(),
+            max_depth: self.get_max_depth(),
+        };
+    }
+    /// Check an order against what the unit is and can do
+    fn check_order(&self, order: &Order) -> Result<(), RejectReason> {
+        let kind = self.get_kind();
+        if !order.for_kind(kind) {
+            return Err(RejectReason::WrongPlatform(kind));
+        }
+        if !order.is_deploy() && !self.get_is_deployed() {
+            return Err(RejectReason::NotDeployed);
+        }
+        return match self.get_limits().check(order).into_iter().next() {
+            Some(reason) => Err(reason),
+            None => Ok(()),
+        };
+    }
+    /// Hand over the events raised since this was last called
+    fn take_events(&mut self) -> Vec<Event>;
+    /// Values kept on the unit by custom orders, which the
+    /// built-in orders leave alone
+    fn get_state(&self) -> &UnitState;
+    fn get_state_mut(&mut self) -> &mut UnitState;
     fn print(&self) {
         println!("Name: {} ID: {}", self.get_name(), self.get_id());
     }
@@ -24,39 +300,30 @@ pub trait Movable {
     }
 }
 
-/// Calculate a new position using 'dead reckoning'
-fn calc_new_position(
-    loc: Location,
-    heading: f64,
-    speed: f64,
-    curr_tm: chrono::NaiveDateTime,
-    prev_tm: chrono::NaiveDateTime,
-) -> Location {
-    let time: f64 = ((curr_tm - prev_tm).num_seconds() as f64) / (60.0 * 60.0);
-    let distance = speed * time;
-    let dx = distance * heading.to_radians().sin();
-    let dy = distance * heading.to_radians().cos();
-    return Location::new(loc.x + dx, loc.y + dy, loc.z, curr_tm);
-}
-
 pub trait Ship {
-    fn change(&self, head: f64, spd: f64, alt: f64, t: chrono::NaiveDateTime) -> bool;
-    fn update_position(&self, t: chrono::NaiveDateTime);
+    fn change(&self, head: f64, spd: f64, alt: f64, t: Timestamp) -> bool;
+    fn update_position(&self, t: Timestamp);
 }
 
 #[derive(Debug)]
 pub struct Cruiser {
     name: String,
     id: String,
-    at: chrono::NaiveDateTime,
+    at: Timestamp,
     loc: Location,
     is_deployed: bool,
     was_deployed: bool,
     heading: f64,
     speed: f64,
+    /// what it has been ordered to make, and is turning or climbing towards
+    helm: Helm,
     max_speed: f64,
+    kinematics: Kinematics,
     hl: HistoryList,
     max_missles: i64,
+    guidance: Option<Guidance>,
+    events: Vec<Event>,
+    state: UnitState,
 }
 
 impl Cruiser {
@@ -64,16 +331,33 @@ impl Cruiser {
         Cruiser {
             name: name,
             id: id,
-            at: chrono::NaiveDate::from_ymd(2019, 1, 1).and_hms(0, 0, 0),
+            at: timestamp(2019, 1, 1, 0, 0, 0),
             loc: Location::default(),
             is_deployed: false,
             was_deployed: false,
             heading: 0.0,
             speed: 0.0,
+            helm: Helm::default(),
             max_speed: max_speed,
+            kinematics: Kinematics::default_for(MovableKind::Cruiser),
             hl: HistoryList::new(),
-            max_missles: 0,
+            max_missles: max_missles,
+            guidance: None,
+            events: Vec::new(),
+            state: UnitState::new(),
+        }
+    }
+
+    /// Override, if given, how quickly the cruiser can turn, change speed and climb
+    pub fn with_kinematics(mut self, kinematics: Option<Kinematics>) -> Self {
+        if let Some(kinematics) = kinematics {
+            self.kinematics = kinematics;
         }
+        return self;
+    }
+
+    pub fn get_max_missles(&self) -> i64 {
+        return self.max_missles;
     }
 }
 
@@ -90,66 +374,120 @@ impl Movable for Cruiser {
     fn get_name(&self) -> String {
         return self.name.clone();
     }
+    fn get_kind(&self) -> MovableKind {
+        return MovableKind::Cruiser;
+    }
     fn get_location(&self) -> Location {
-        return self.loc.clone();
+        return self.loc.clone().with_motion(self.heading, self.speed);
+    }
+    fn get_max_speed(&self) -> f64 {
+        return self.max_speed;
+    }
+    fn get_max_ceiling(&self) -> Option<f64> {
+        return None;
+    }
+    fn get_kinematics(&self) -> Kinematics {
+        return self.kinematics;
+    }
+    fn get_ship_id(&self) -> Option<String> {
+        return None;
     }
     fn get_history(&self) -> &HistoryList {
         return &self.hl;
     }
-    /// determine which order we have received
-    /// and call the function associated with it
-    fn execute(&mut self, order: &Order) {
-        let result = match order {
-            Order::DeployShipOrder(o) => {
-                self.deploy(o.start_x, o.start_y, o.heading, o.speed, o.extime)
-            }
-            Order::ChangeShipOrder(o) => self.change(o.heading, o.speed, 0.0, o.extime),
-            _ => false,
-        };
+    fn to_doc(&self) -> PlatformDoc {
+        PlatformDoc::Cruiser {
+            name: self.name.clone(),
+            id: self.id.clone(),
+            max_speed: self.max_speed,
+            max_missiles: self.max_missles,
+            kinematics: self.kinematics.if_overridden(self.get_kind()),
+        }
+    }
+    fn execute(&mut self, order: &Order) -> OrderOutcome {
+        return order.execute_on(self);
     }
-    fn deploy(&mut self, x: f64, y: f64, head: f64, spd: f64, t: chrono::NaiveDateTime) -> bool {
+    fn deploy(&mut self, x: f64, y: f64, head: f64, spd: f64, t: Timestamp) -> OrderOutcome {
         self.is_deployed = true;
         self.was_deployed = true;
         self.loc = Location::new(x, y, 0.0, t);
         self.hl.push(self.loc.clone());
         self.heading = head;
         self.speed = spd;
+        self.helm = Helm::new(head, spd, 0.0);
         self.at = t;
-        return true;
+        self.guidance = None;
+        return OrderOutcome::Accepted;
     }
-    fn change(&mut self, head: f64, spd: f64, alt: f64, t: chrono::NaiveDateTime) -> bool {
-        // self.update_position(t);
+    fn change(&mut self, head: f64, spd: f64, _alt: f64, _t: Timestamp) -> OrderOutcome {
         if head != -1.0 {
-            self.heading = head;
+            self.helm.heading = head;
+            self.guidance = None;
         }
         if spd != -1.0 {
-            self.speed = spd;
+            self.helm.speed = spd;
         }
-        return true;
+        return OrderOutcome::Accepted;
     }
-    fn update_position(&mut self, t: chrono::NaiveDateTime, loc_map: &LocationMap) {
+    fn guide(&mut self, guidance: Guidance, _t: Timestamp) -> OrderOutcome {
+        self.guidance = Some(guidance);
+        return OrderOutcome::Accepted;
+    }
+    fn update_position(&mut self, t: Timestamp, loc_map: &LocationMap) {
         if self.at == t {
             return;
         }
-        self.loc = calc_new_position(self.loc.clone(), self.heading, self.speed, t, self.at);
+        let leg = steer(
+            &self.loc,
+            self.heading,
+            self.speed,
+            &mut self.helm,
+            &mut self.guidance,
+            &self.kinematics,
+            self.max_speed,
+            t,
+            self.at,
+            loc_map,
+        );
+        self.heading = leg.heading;
+        self.speed = leg.speed;
+        if let Some((done, kind)) = leg.completed {
+            self.events.push(Event::new(done, &self.id, kind));
+        }
+        self.loc = leg.loc;
         self.hl.push(self.loc.clone());
         self.at = t;
     }
+    fn take_events(&mut self) -> Vec<Event> {
+        return std::mem::take(&mut self.events);
+    }
+    fn get_state(&self) -> &UnitState {
+        return &self.state;
+    }
+    fn get_state_mut(&mut self) -> &mut UnitState {
+        return &mut self.state;
+    }
 }
 
 #[derive(Debug)]
 pub struct Carrier {
     name: String,
     id: String,
-    at: chrono::NaiveDateTime,
+    at: Timestamp,
     loc: Location,
     is_deployed: bool,
     was_deployed: bool,
     heading: f64,
     speed: f64,
+    /// what it has been ordered to make, and is turning or climbing towards
+    helm: Helm,
     max_speed: f64,
+    kinematics: Kinematics,
     hl: HistoryList,
     max_aircraft: i64,
+    guidance: Option<Guidance>,
+    events: Vec<Event>,
+    state: UnitState,
 }
 
 impl Carrier {
@@ -157,16 +495,33 @@ impl Carrier {
         Carrier {
             name: name,
             id: id,
-            at: chrono::NaiveDate::from_ymd(2000, 1, 1).and_hms(0, 0, 0),
+            at: timestamp(2000, 1, 1, 0, 0, 0),
             loc: Location::default(),
             is_deployed: false,
             was_deployed: false,
             heading: 0.0,
             speed: 0.0,
+            helm: Helm::default(),
             max_speed: max_speed,
+            kinematics: Kinematics::default_for(MovableKind::Carrier),
             hl: HistoryList::new(),
             max_aircraft: max_aircraft,
+            guidance: None,
+            events: Vec::new(),
+            state: UnitState::new(),
+        }
+    }
+
+    /// Override, if given, how quickly the carrier can turn, change speed and climb
+    pub fn with_kinematics(mut self, kinematics: Option<Kinematics>) -> Self {
+        if let Some(kinematics) = kinematics {
+            self.kinematics = kinematics;
         }
+        return self;
+    }
+
+    pub fn get_max_aircraft(&self) -> i64 {
+        return self.max_aircraft;
     }
 }
 
@@ -183,71 +538,343 @@ impl Movable for Carrier {
     fn get_name(&self) -> String {
         return self.name.clone();
     }
+    fn get_kind(&self) -> MovableKind {
+        return MovableKind::Carrier;
+    }
     fn get_location(&self) -> Location {
-        return self.loc.clone();
+        return self.loc.clone().with_motion(self.heading, self.speed);
+    }
+    fn get_max_speed(&self) -> f64 {
+        return self.max_speed;
+    }
+    fn get_max_ceiling(&self) -> Option<f64> {
+        return None;
+    }
+    fn get_kinematics(&self) -> Kinematics {
+        return self.kinematics;
+    }
+    fn get_ship_id(&self) -> Option<String> {
+        return None;
     }
     fn get_history(&self) -> &HistoryList {
         return &self.hl;
     }
-    /// determine which order we have received
-    /// and call the function associated with it
-    fn execute(&mut self, order: &Order) {
-        let result = match order {
-            Order::DeployShipOrder(o) => {
-                self.deploy(o.start_x, o.start_y, o.heading, o.speed, o.extime)
-            }
-            Order::ChangeShipOrder(o) => self.change(o.heading, o.speed, 0.0, o.extime),
-            _ => false,
-        };
+    fn to_doc(&self) -> PlatformDoc {
+        PlatformDoc::AircraftCarrier {
+            name: self.name.clone(),
+            id: self.id.clone(),
+            max_speed: self.max_speed,
+            max_aircraft: self.max_aircraft,
+            kinematics: self.kinematics.if_overridden(self.get_kind()),
+        }
     }
-    fn deploy(&mut self, x: f64, y: f64, head: f64, spd: f64, t: chrono::NaiveDateTime) -> bool {
+    fn execute(&mut self, order: &Order) -> OrderOutcome {
+        return order.execute_on(self);
+    }
+    fn deploy(&mut self, x: f64, y: f64, head: f64, spd: f64, t: Timestamp) -> OrderOutcome {
         self.is_deployed = true;
         self.was_deployed = true;
         self.loc = Location::new(x, y, 0.0, t);
         self.hl.push(self.loc.clone());
         self.heading = head;
         self.speed = spd;
+        self.helm = Helm::new(head, spd, 0.0);
         self.at = t;
-        return true;
+        self.guidance = None;
+        return OrderOutcome::Accepted;
     }
-    fn change(&mut self, head: f64, spd: f64, alt: f64, t: chrono::NaiveDateTime) -> bool {
-        // self.update_position(t);
+    fn change(&mut self, head: f64, spd: f64, _alt: f64, _t: Timestamp) -> OrderOutcome {
         if head != -1.0 {
-            self.heading = head;
+            self.helm.heading = head;
+            self.guidance = None;
         }
         if spd != -1.0 {
-            self.speed = spd;
+            self.helm.speed = spd;
         }
-        return true;
+        return OrderOutcome::Accepted;
+    }
+    fn guide(&mut self, guidance: Guidance, _t: Timestamp) -> OrderOutcome {
+        self.guidance = Some(guidance);
+        return OrderOutcome::Accepted;
     }
-    fn update_position(&mut self, t: chrono::NaiveDateTime, loc_map: &LocationMap) {
+    fn update_position(&mut self, t: Timestamp, loc_map: &LocationMap) {
         if self.at == t {
             return;
         }
-        self.loc = calc_new_position(self.loc.clone(), self.heading, self.speed, t, self.at);
+        let leg = steer(
+            &self.loc,
+            self.heading,
+            self.speed,
+            &mut self.helm,
+            &mut self.guidance,
+            &self.kinematics,
+            self.max_speed,
+            t,
+            self.at,
+            loc_map,
+        );
+        self.heading = leg.heading;
+        self.speed = leg.speed;
+        if let Some((done, kind)) = leg.completed {
+            self.events.push(Event::new(done, &self.id, kind));
+        }
+        self.loc = leg.loc;
         self.hl.push(self.loc.clone());
         self.at = t;
     }
+    fn take_events(&mut self) -> Vec<Event> {
+        return std::mem::take(&mut self.events);
+    }
+    fn get_state(&self) -> &UnitState {
+        return &self.state;
+    }
+    fn get_state_mut(&mut self) -> &mut UnitState {
+        return &mut self.state;
+    }
+}
+
+#[derive(Debug)]
+pub struct Submarine {
+    name: String,
+    id: String,
+    at: Timestamp,
+    loc: Location,
+    is_deployed: bool,
+    was_deployed: bool,
+    heading: f64,
+    speed: f64,
+    /// what it has been ordered to make, and is turning or climbing towards
+    helm: Helm,
+    /// below the surface, so the location's z is -depth
+    depth: f64,
+    max_speed: f64,
+    kinematics: Kinematics,
+    max_depth: f64,
+    hl: HistoryList,
+    max_torpedoes: i64,
+    guidance: Option<Guidance>,
+    events: Vec<Event>,
+    state: UnitState,
+}
+
+impl Submarine {
+    pub fn new(
+        name: String,
+        id: String,
+        max_speed: f64,
+        max_depth: f64,
+        max_torpedoes: i64,
+    ) -> Self {
+        Submarine {
+            name: name,
+            id: id,
+            at: timestamp(2000, 1, 1, 0, 0, 0),
+            loc: Location::default(),
+            is_deployed: false,
+            was_deployed: false,
+            heading: 0.0,
+            speed: 0.0,
+            helm: Helm::default(),
+            depth: 0.0,
+            max_speed: max_speed,
+            kinematics: Kinematics::default_for(MovableKind::Submarine),
+            max_depth: max_depth,
+            hl: HistoryList::new(),
+            max_torpedoes: max_torpedoes,
+            guidance: None,
+            events: Vec::new(),
+            state: UnitState::new(),
+        }
+    }
+
+    /// Override, if given, how quickly the submarine can turn, change speed and climb
+    pub fn with_kinematics(mut self, kinematics: Option<Kinematics>) -> Self {
+        if let Some(kinematics) = kinematics {
+            self.kinematics = kinematics;
+        }
+        return self;
+    }
+
+    pub fn get_max_torpedoes(&self) -> i64 {
+        return self.max_torpedoes;
+    }
+
+    pub fn get_depth(&self) -> f64 {
+        return self.depth;
+    }
+
+    pub fn is_submerged(&self) -> bool {
+        return self.depth > 0.0;
+    }
 }
 
-// #[derive(Debug)]
+impl Movable for Submarine {
+    fn get_is_deployed(&self) -> bool {
+        return self.is_deployed;
+    }
+    fn get_was_deployed(&self) -> bool {
+        return self.was_deployed;
+    }
+    fn get_id(&self) -> String {
+        return self.id.clone();
+    }
+    fn get_name(&self) -> String {
+        return self.name.clone();
+    }
+    fn get_kind(&self) -> MovableKind {
+        return MovableKind::Submarine;
+    }
+    fn get_location(&self) -> Location {
+        return self.loc.clone().with_motion(self.heading, self.speed);
+    }
+    fn get_max_speed(&self) -> f64 {
+        return self.max_speed;
+    }
+    fn get_max_ceiling(&self) -> Option<f64> {
+        return None;
+    }
+    fn get_max_depth(&self) -> Option<f64> {
+        return Some(self.max_depth);
+    }
+    fn get_kinematics(&self) -> Kinematics {
+        return self.kinematics;
+    }
+    fn get_ship_id(&self) -> Option<String> {
+        return None;
+    }
+    fn get_history(&self) -> &HistoryList {
+        return &self.hl;
+    }
+    fn to_doc(&self) -> PlatformDoc {
+        PlatformDoc::Submarine {
+            name: self.name.clone(),
+            id: self.id.clone(),
+            max_speed: self.max_speed,
+            max_depth: self.max_depth,
+            max_torpedoes: self.max_torpedoes,
+            kinematics: self.kinematics.if_overridden(self.get_kind()),
+        }
+    }
+    fn execute(&mut self, order: &Order) -> OrderOutcome {
+        return order.execute_on(self);
+    }
+    /// Submarines are deployed on the surface
+    fn deploy(&mut self, x: f64, y: f64, head: f64, spd: f64, t: Timestamp) -> OrderOutcome {
+        self.is_deployed = true;
+        self.was_deployed = true;
+        self.depth = 0.0;
+        self.loc = Location::new(x, y, 0.0, t);
+        self.hl.push(self.loc.clone());
+        self.heading = head;
+        self.speed = spd;
+        self.helm = Helm::new(head, spd, 0.0);
+        self.at = t;
+        self.guidance = None;
+        return OrderOutcome::Accepted;
+    }
+    fn change(&mut self, head: f64, spd: f64, _alt: f64, _t: Timestamp) -> OrderOutcome {
+        if head != -1.0 {
+            self.helm.heading = head;
+            self.guidance = None;
+        }
+        if spd != -1.0 {
+            self.helm.speed = spd;
+        }
+        return OrderOutcome::Accepted;
+    }
+    fn change_depth(&mut self, depth: f64, _t: Timestamp) -> OrderOutcome {
+        self.helm.z = -depth;
+        return OrderOutcome::Accepted;
+    }
+    fn guide(&mut self, guidance: Guidance, _t: Timestamp) -> OrderOutcome {
+        self.guidance = Some(guidance);
+        return OrderOutcome::Accepted;
+    }
+    fn update_position(&mut self, t: Timestamp, loc_map: &LocationMap) {
+        if self.at == t {
+            return;
+        }
+        let leg = steer(
+            &self.loc,
+            self.heading,
+            self.speed,
+            &mut self.helm,
+            &mut self.guidance,
+            &self.kinematics,
+            self.max_speed,
+            t,
+            self.at,
+            loc_map,
+        );
+        self.heading = leg.heading;
+        self.speed = leg.speed;
+        if let Some((done, kind)) = leg.completed {
+            self.events.push(Event::new(done, &self.id, kind));
+        }
+        self.loc = leg.loc;
+        self.depth = -self
+            .kinematics
+            .climb(-self.depth, self.helm.z, hours(t - self.at));
+        self.loc.z = -self.depth;
+        self.hl.push(self.loc.clone());
+        self.at = t;
+    }
+    fn take_events(&mut self) -> Vec<Event> {
+        return std::mem::take(&mut self.events);
+    }
+    fn get_state(&self) -> &UnitState {
+        return &self.state;
+    }
+    fn get_state_mut(&mut self) -> &mut UnitState {
+        return &mut self.state;
+    }
+}
+
+/// Where the deck of a ship aircraft operate from is at `t`
+fn deck(ship_id: &str, t: Timestamp, loc_map: &LocationMap) -> Option<Location> {
+    let ship = loc_map.get(ship_id)?;
+    return Some(calc_new_position(
+        ship.clone(),
+        ship.heading,
+        ship.speed,
+        t,
+        ship.get_time(),
+    ));
+}
+
+#[derive(Debug)]
 pub struct Fighter {
     name: String,
     id: String,
-    at: chrono::NaiveDateTime,
+    at: Timestamp,
     loc: Location,
     is_deployed: bool,
     was_deployed: bool,
     heading: f64,
     speed: f64,
+    /// what it has been ordered to make, and is turning or climbing towards
+    helm: Helm,
     max_speed: f64,
+    kinematics: Kinematics,
     hl: HistoryList,
+    /// launched, but not yet placed on its carrier's deck
+    is_launching: bool,
     is_landing: bool,
     ship_id: String,
     ship_loc: Location,
     max_ceiling: f64,
     altitude: f64,
     max_bombs: i64,
+    fuel: Fuel,
+    /// pounds of fuel left, a full load until it is launched
+    fuel_left: f64,
+    /// low enough on fuel that it is on its way back to its carrier
+    is_bingo: bool,
+    /// ran out of fuel, and can't be launched again
+    is_lost: bool,
+    guidance: Option<Guidance>,
+    events: Vec<Event>,
+    state: UnitState,
 }
 
 impl Fighter {
@@ -262,39 +889,52 @@ impl Fighter {
         Fighter {
             name: name,
             id: id,
-            at: chrono::NaiveDate::from_ymd(2000, 1, 1).and_hms(0, 0, 0),
+            at: timestamp(2000, 1, 1, 0, 0, 0),
             loc: Location::default(),
             is_deployed: false,
             was_deployed: false,
             heading: 0.0,
             speed: 0.0,
+            helm: Helm::default(),
             max_speed: max_speed,
+            kinematics: Kinematics::default_for(MovableKind::Fighter),
             hl: HistoryList::new(),
+            is_launching: false,
             is_landing: false,
             ship_id: ship_id,
             ship_loc: Location::default(),
             max_ceiling: max_ceiling,
             altitude: 0.0,
             max_bombs: max_bombs,
+            fuel: Fuel::default(),
+            fuel_left: Fuel::default().capacity,
+            is_bingo: false,
+            is_lost: false,
+            guidance: None,
+            events: Vec::new(),
+            state: UnitState::new(),
         }
     }
 
-    pub fn deploy(&mut self, head: f64, spd: f64, alt: f64, t: chrono::NaiveDateTime) -> bool {
-        self.is_deployed = true;
-        self.was_deployed = true;
-        self.loc = Location::new(self.ship_loc.x, self.ship_loc.y, alt, t);
-        self.hl.push(self.loc.clone());
-        self.heading = head;
-        self.speed = spd;
-        self.altitude = alt;
-        self.at = t;
-        return true;
+    /// Override, if given, how quickly the fighter can turn, change speed and climb
+    pub fn with_kinematics(mut self, kinematics: Option<Kinematics>) -> Self {
+        if let Some(kinematics) = kinematics {
+            self.kinematics = kinematics;
+        }
+        return self;
     }
 
-    pub fn land(&mut self, ship_id: String, t: chrono::NaiveDateTime) -> bool {
-        self.ship_id = ship_id;
-        self.is_landing = true;
-        return true;
+    /// Override, if given, how much fuel the fighter carries and burns
+    pub fn with_fuel(mut self, fuel: Option<Fuel>) -> Self {
+        if let Some(fuel) = fuel {
+            self.fuel = fuel;
+            self.fuel_left = fuel.capacity;
+        }
+        return self;
+    }
+
+    pub fn get_max_bombs(&self) -> i64 {
+        return self.max_bombs;
     }
 
     /// Determine if the fighter can land on a carrier
@@ -305,9 +945,42 @@ impl Fighter {
 
     /// Set the heading to the target carrier
     fn goto_carrier(&mut self) {
-        let dx = self.ship_loc.x - self.loc.x;
-        let dy = self.ship_loc.y - self.loc.y;
-        self.heading = dy.atan2(dx).to_degrees();
+        self.helm.heading = self.loc.bearing_to(&self.ship_loc);
+    }
+
+    /// Whether it has been lost after running out of fuel
+    pub fn is_lost(&self) -> bool {
+        return self.is_lost;
+    }
+
+    /// Burn the fuel used flying for `time` hours. Reaching bingo fuel sends
+    /// it back to its carrier, and running out loses it. Returns whether
+    /// it is still flying
+    fn burn_fuel(&mut self, time: f64, t: Timestamp) -> bool {
+        let rate = self
+            .fuel
+            .burn_rate(self.speed, self.max_speed, self.altitude, self.max_ceiling);
+        self.fuel_left = (self.fuel_left - rate * time).max(0.0);
+        if self.fuel_left == 0.0 {
+            self.is_deployed = false;
+            self.is_landing = false;
+            self.is_bingo = false;
+            self.is_lost = true;
+            self.guidance = None;
+            self.events
+                .push(Event::new(t, &self.id, EventKind::FuelExhausted));
+            return false;
+        }
+        if !self.is_bingo && self.fuel_left <= self.fuel.bingo {
+            self.is_bingo = true;
+            self.is_landing = true;
+            self.guidance = None;
+            let bingo = EventKind::BingoFuel {
+                ship: self.ship_id.clone(),
+            };
+            self.events.push(Event::new(t, &self.id, bingo));
+        }
+        return true;
     }
 }
 
@@ -324,56 +997,441 @@ impl Movable for Fighter {
     fn get_name(&self) -> String {
         return self.name.clone();
     }
+    fn get_kind(&self) -> MovableKind {
+        return MovableKind::Fighter;
+    }
     fn get_location(&self) -> Location {
-        return self.loc.clone();
+        return self.loc.clone().with_motion(self.heading, self.speed);
+    }
+    fn get_max_speed(&self) -> f64 {
+        return self.max_speed;
+    }
+    fn get_max_ceiling(&self) -> Option<f64> {
+        return Some(self.max_ceiling);
+    }
+    fn get_kinematics(&self) -> Kinematics {
+        return self.kinematics;
+    }
+    fn get_fuel(&self) -> Option<f64> {
+        return Some(self.fuel_left);
+    }
+    fn get_ship_id(&self) -> Option<String> {
+        return Some(self.ship_id.clone());
     }
     fn get_history(&self) -> &HistoryList {
         return &self.hl;
     }
-    /// determine which order we have received
-    /// and call the function associated with it
-    fn execute(&mut self, order: &Order) {
-        let result = match order {
-            Order::DeployAircraftOrder(o) => self.deploy(o.heading, o.speed, o.altitude, o.extime),
-            Order::ChangeAircraftOrder(o) => self.change(o.heading, o.speed, o.altitude, o.extime),
-            Order::LandAircraftOrder(o) => self.land(o.ship_id.clone(), o.extime),
-            _ => false,
-        };
+    fn to_doc(&self) -> PlatformDoc {
+        PlatformDoc::Fighter {
+            name: self.name.clone(),
+            id: self.id.clone(),
+            ship_id: self.ship_id.clone(),
+            max_speed: self.max_speed,
+            max_ceiling: self.max_ceiling,
+            max_bombs: self.max_bombs,
+            kinematics: self.kinematics.if_overridden(self.get_kind()),
+            fuel: self.fuel.if_overridden(),
+        }
     }
-    fn deploy(&mut self, x: f64, y: f64, head: f64, spd: f64, t: chrono::NaiveDateTime) -> bool {
-        return false;
+    fn execute(&mut self, order: &Order) -> OrderOutcome {
+        return order.execute_on(self);
     }
-    fn change(&mut self, head: f64, spd: f64, alt: f64, t: chrono::NaiveDateTime) -> bool {
+    /// Aircraft are launched from their carrier rather than deployed at a position
+    fn deploy(&mut self, _x: f64, _y: f64, _head: f64, _spd: f64, _t: Timestamp) -> OrderOutcome {
+        return OrderOutcome::Rejected(RejectReason::WrongPlatform(self.get_kind()));
+    }
+    /// Fighters are launched with a full load of fuel
+    fn launch(&mut self, head: f64, spd: f64, alt: f64, t: Timestamp) -> OrderOutcome {
+        if self.is_lost {
+            return OrderOutcome::Rejected(RejectReason::Lost);
+        }
+        self.fuel_left = self.fuel.capacity;
+        self.is_bingo = false;
+        self.is_deployed = true;
+        self.was_deployed = true;
+        self.is_launching = true;
+        self.is_landing = false;
+        self.heading = head;
+        self.speed = spd;
+        self.altitude = alt;
+        self.helm = Helm::new(head, spd, alt);
+        self.at = t;
+        self.guidance = None;
+        return OrderOutcome::Accepted;
+    }
+
+    fn land(&mut self, ship_id: String, _t: Timestamp) -> OrderOutcome {
+        self.ship_id = ship_id;
+        self.is_landing = true;
+        self.guidance = None;
+        return OrderOutcome::Accepted;
+    }
+    /// At bingo fuel a new heading is refused, as it is heading home
+    /// to land, but otherwise it calls off any landing
+    fn change(&mut self, head: f64, spd: f64, alt: f64, _t: Timestamp) -> OrderOutcome {
         // self.update_position(t);
+        if self.is_bingo && head != -1.0 {
+            return OrderOutcome::Rejected(RejectReason::BingoFuel);
+        }
         if spd != -1.0 {
-            self.speed = spd;
+            self.helm.speed = spd;
         }
         if alt != -1.0 {
-            // loc.setZ(alt);
-            self.altitude = alt;
+            self.helm.z = alt;
         }
-        if !self.is_landing && head != -1.0 {
-            self.heading = head;
+        // a new heading calls off a landing, as new guidance does
+        if head != -1.0 {
+            self.helm.heading = head;
+            self.guidance = None;
+            self.is_landing = false;
         }
-        return true;
+        return OrderOutcome::Accepted;
     }
-    fn update_position(&mut self, t: chrono::NaiveDateTime, loc_map: &LocationMap) {
-        self.ship_loc = loc_map.get(self.ship_id.as_str()).unwrap().clone();
-        self.loc = calc_new_position(self.loc.clone(), self.heading, self.speed, t, self.at);
+    /// At bingo fuel it carries on back to its carrier instead
+    fn guide(&mut self, guidance: Guidance, _t: Timestamp) -> OrderOutcome {
+        if let Some(reason) = self.guidance_refused() {
+            return OrderOutcome::Rejected(reason);
+        }
+        self.guidance = Some(guidance);
+        self.is_landing = false;
+        return OrderOutcome::Accepted;
+    }
+    fn guidance_refused(&self) -> Option<RejectReason> {
+        if self.is_bingo {
+            return Some(RejectReason::BingoFuel);
+        }
+        return None;
+    }
+    fn update_position(&mut self, t: Timestamp, loc_map: &LocationMap) {
+        if self.is_launching {
+            // take off from wherever the carrier had got to, or failing
+            // that from where it was last seen
+            if let Some(deck) = deck(&self.ship_id, self.at, loc_map) {
+                self.ship_loc = deck;
+            }
+            self.loc = Location::new(self.ship_loc.x, self.ship_loc.y, self.altitude, self.at);
+            self.hl.push(self.loc.clone());
+            self.is_launching = false;
+        }
+        // without its carrier it heads for where it last knew it to be
+        if let Some(ship) = loc_map.get(self.ship_id.as_str()) {
+            self.ship_loc = ship.clone();
+        }
+        let leg = steer(
+            &self.loc,
+            self.heading,
+            self.speed,
+            &mut self.helm,
+            &mut self.guidance,
+            &self.kinematics,
+            self.max_speed,
+            t,
+            self.at,
+            loc_map,
+        );
+        self.heading = leg.heading;
+        self.speed = leg.speed;
+        if let Some(alt) = leg.altitude {
+            self.helm.z = alt;
+        }
+        if let Some((done, kind)) = leg.completed {
+            self.events.push(Event::new(done, &self.id, kind));
+        }
+        self.loc = leg.loc;
+        self.altitude = self
+            .kinematics
+            .climb(self.altitude, self.helm.z, hours(t - self.at));
         self.loc.z = self.altitude;
         self.hl.push(self.loc.clone());
+        let time = hours(t - self.at);
         self.at = t;
+        if !self.burn_fuel(time, t) {
+            return;
+        }
 
         if self.is_landing {
             if self.can_land() {
                 self.is_deployed = false;
                 self.is_landing = false;
+                self.is_bingo = false;
                 self.altitude = 0.0;
+                self.helm.z = 0.0;
+                let landed = EventKind::Landed {
+                    ship: self.ship_id.clone(),
+                };
+                self.events.push(Event::new(t, &self.id, landed));
             } else {
                 self.goto_carrier();
             }
         }
     }
+    fn take_events(&mut self) -> Vec<Event> {
+        return std::mem::take(&mut self.events);
+    }
+    fn get_state(&self) -> &UnitState {
+        return &self.state;
+    }
+    fn get_state_mut(&mut self) -> &mut UnitState {
+        return &mut self.state;
+    }
+}
+
+/// A rotary-wing aircraft. Unlike a fighter it can hover, and it
+/// can land on any ship with a flight deck
+#[derive(Debug)]
+pub struct Helicopter {
+    name: String,
+    id: String,
+    at: Timestamp,
+    loc: Location,
+    is_deployed: bool,
+    was_deployed: bool,
+    heading: f64,
+    speed: f64,
+    /// what it has been ordered to make, and is turning or climbing towards
+    helm: Helm,
+    max_speed: f64,
+    kinematics: Kinematics,
+    hl: HistoryList,
+    /// the ship it takes off from, or is landing on
+    ship_id: String,
+    /// launched, but not yet placed on its ship's deck
+    is_launching: bool,
+    is_landing: bool,
+    max_ceiling: f64,
+    altitude: f64,
+    max_torpedoes: i64,
+    guidance: Option<Guidance>,
+    events: Vec<Event>,
+    state: UnitState,
+}
+
+impl Helicopter {
+    pub fn new(
+        name: String,
+        id: String,
+        max_speed: f64,
+        ship_id: String,
+        max_ceiling: f64,
+        max_torpedoes: i64,
+    ) -> Self {
+        Helicopter {
+            name: name,
+            id: id,
+            at: timestamp(2000, 1, 1, 0, 0, 0),
+            loc: Location::default(),
+            is_deployed: false,
+            was_deployed: false,
+            heading: 0.0,
+            speed: 0.0,
+            helm: Helm::default(),
+            max_speed: max_speed,
+            kinematics: Kinematics::default_for(MovableKind::Helicopter),
+            hl: HistoryList::new(),
+            ship_id: ship_id,
+            is_launching: false,
+            is_landing: false,
+            max_ceiling: max_ceiling,
+            altitude: 0.0,
+            max_torpedoes: max_torpedoes,
+            guidance: None,
+            events: Vec::new(),
+            state: UnitState::new(),
+        }
+    }
+
+    /// Override, if given, how quickly the helicopter can turn, change speed and climb
+    pub fn with_kinematics(mut self, kinematics: Option<Kinematics>) -> Self {
+        if let Some(kinematics) = kinematics {
+            self.kinematics = kinematics;
+        }
+        return self;
+    }
+
+    pub fn get_max_torpedoes(&self) -> i64 {
+        return self.max_torpedoes;
+    }
+
+    /// Airborne without moving
+    pub fn is_hovering(&self) -> bool {
+        return self.is_deployed && self.speed == 0.0;
+    }
+}
+
+impl Movable for Helicopter {
+    fn get_is_deployed(&self) -> bool {
+        return self.is_deployed;
+    }
+    fn get_was_deployed(&self) -> bool {
+        return self.was_deployed;
+    }
+    fn get_id(&self) -> String {
+        return self.id.clone();
+    }
+    fn get_name(&self) -> String {
+        return self.name.clone();
+    }
+    fn get_kind(&self) -> MovableKind {
+        return MovableKind::Helicopter;
+    }
+    fn get_location(&self) -> Location {
+        return self.loc.clone().with_motion(self.heading, self.speed);
+    }
+    fn get_max_speed(&self) -> f64 {
+        return self.max_speed;
+    }
+    fn get_max_ceiling(&self) -> Option<f64> {
+        return Some(self.max_ceiling);
+    }
+    fn get_kinematics(&self) -> Kinematics {
+        return self.kinematics;
+    }
+    fn get_ship_id(&self) -> Option<String> {
+        return Some(self.ship_id.clone());
+    }
+    fn get_history(&self) -> &HistoryList {
+        return &self.hl;
+    }
+    fn to_doc(&self) -> PlatformDoc {
+        PlatformDoc::Helicopter {
+            name: self.name.clone(),
+            id: self.id.clone(),
+            ship_id: self.ship_id.clone(),
+            max_speed: self.max_speed,
+            max_ceiling: self.max_ceiling,
+            max_torpedoes: self.max_torpedoes,
+            kinematics: self.kinematics.if_overridden(self.get_kind()),
+        }
+    }
+    fn execute(&mut self, order: &Order) -> OrderOutcome {
+        return order.execute_on(self);
+    }
+    /// Helicopters take off from their ship rather than being deployed at a position
+    fn deploy(&mut self, _x: f64, _y: f64, _head: f64, _spd: f64, _t: Timestamp) -> OrderOutcome {
+        return OrderOutcome::Rejected(RejectReason::WrongPlatform(self.get_kind()));
+    }
+    fn launch(&mut self, head: f64, spd: f64, alt: f64, t: Timestamp) -> OrderOutcome {
+        self.is_deployed = true;
+        self.was_deployed = true;
+        self.is_launching = true;
+        self.is_landing = false;
+        self.heading = head;
+        self.speed = spd;
+        self.altitude = alt;
+        self.helm = Helm::new(head, spd, alt);
+        self.at = t;
+        self.guidance = None;
+        return OrderOutcome::Accepted;
+    }
+    /// A hovering helicopter makes for the ship at its max speed
+    fn land(&mut self, ship_id: String, _t: Timestamp) -> OrderOutcome {
+        self.ship_id = ship_id;
+        self.is_landing = true;
+        self.guidance = None;
+        if self.helm.speed == 0.0 {
+            self.helm.speed = self.max_speed;
+        }
+        return OrderOutcome::Accepted;
+    }
+    fn hover(&mut self, alt: f64, _t: Timestamp) -> OrderOutcome {
+        self.helm.speed = 0.0;
+        if alt != -1.0 {
+            self.helm.z = alt;
+        }
+        self.is_landing = false;
+        self.guidance = None;
+        return OrderOutcome::Accepted;
+    }
+    fn change(&mut self, head: f64, spd: f64, alt: f64, _t: Timestamp) -> OrderOutcome {
+        if spd != -1.0 {
+            self.helm.speed = spd;
+        }
+        if alt != -1.0 {
+            self.helm.z = alt;
+        }
+        // a new heading calls off a landing, as new guidance does
+        if head != -1.0 {
+            self.helm.heading = head;
+            self.guidance = None;
+            self.is_landing = false;
+        }
+        return OrderOutcome::Accepted;
+    }
+    fn guide(&mut self, guidance: Guidance, _t: Timestamp) -> OrderOutcome {
+        self.guidance = Some(guidance);
+        self.is_landing = false;
+        return OrderOutcome::Accepted;
+    }
+    fn update_position(&mut self, t: Timestamp, loc_map: &LocationMap) {
+        if self.is_launching {
+            // take off from wherever the ship had got to
+            if let Some(deck) = deck(&self.ship_id, self.at, loc_map) {
+                self.loc = Location::new(deck.x, deck.y, self.altitude, self.at);
+                self.hl.push(self.loc.clone());
+            }
+            self.is_launching = false;
+        }
+        if self.at == t {
+            return;
+        }
+        if self.is_landing {
+            if let Some(deck) = deck(&self.ship_id, t, loc_map) {
+                // set down once the deck is within this update's flight
+                if self.loc.distance(&deck) <= self.speed * hours(t - self.at) {
+                    self.loc = Location::new(deck.x, deck.y, 0.0, t);
+                    self.hl.push(self.loc.clone());
+                    self.at = t;
+                    self.is_deployed = false;
+                    self.is_landing = false;
+                    self.speed = 0.0;
+                    self.altitude = 0.0;
+                    self.helm = Helm::new(self.heading, 0.0, 0.0);
+                    let landed = EventKind::Landed {
+                        ship: self.ship_id.clone(),
+                    };
+                    self.events.push(Event::new(t, &self.id, landed));
+                    return;
+                }
+                self.helm.heading = self.loc.bearing_to(&deck);
+            }
+        }
+        let leg = steer(
+            &self.loc,
+            self.heading,
+            self.speed,
+            &mut self.helm,
+            &mut self.guidance,
+            &self.kinematics,
+            self.max_speed,
+            t,
+            self.at,
+            loc_map,
+        );
+        self.heading = leg.heading;
+        self.speed = leg.speed;
+        if let Some(alt) = leg.altitude {
+            self.helm.z = alt;
+        }
+        if let Some((done, kind)) = leg.completed {
+            self.events.push(Event::new(done, &self.id, kind));
+        }
+        self.loc = leg.loc;
+        self.altitude = self
+            .kinematics
+            .climb(self.altitude, self.helm.z, hours(t - self.at));
+        self.loc.z = self.altitude;
+        self.hl.push(self.loc.clone());
+        self.at = t;
+    }
+    fn take_events(&mut self) -> Vec<Event> {
+        return std::mem::take(&mut self.events);
+    }
+    fn get_state(&self) -> &UnitState {
+        return &self.state;
+    }
+    fn get_state_mut(&mut self) -> &mut UnitState {
+        return &mut self.state;
+    }
 }
 
 #[cfg(test)]
@@ -414,9 +1472,127 @@ mod tests {
             30,
         ));
         a.print();
-        let atime = chrono::NaiveDate::from_ymd(2015, 10, 21).and_hms(17, 2, 0);
+        let atime = timestamp(2015, 10, 21, 17, 2, 0);
         let op = DeployShip::new(atime, String::from("CGN-39"), 0.0, 0.0, 0.0, 0.0);
         let order = Order::DeployShipOrder(op);
-        a.execute(&order);
+        assert_eq!(a.execute(&order), OrderOutcome::Accepted);
+    }
+
+    #[test]
+    fn test_rejected_orders() {
+        let mut a = Cruiser::new(String::from("Chelsey"), String::from("I264"), 12.0, 30);
+        let atime = timestamp(2015, 10, 21, 17, 2, 0);
+        let id = String::from("I264");
+
+        let order = Order::ChangeShipOrder(ChangeShip::new(atime, id.clone(), 90.0, 10.0));
+        let rejected = OrderOutcome::Rejected(RejectReason::NotDeployed);
+        assert_eq!(a.execute(&order), rejected);
+
+        let op = DeployAircraft::new(atime, id.clone(), 0.0, 400.0, 20000.0);
+        let rejected = OrderOutcome::Rejected(RejectReason::WrongPlatform(MovableKind::Cruiser));
+        assert_eq!(a.execute(&Order::DeployAircraftOrder(op)), rejected);
+
+        let op = DeployShip::new(atime, id.clone(), 0.0, 0.0, 0.0, 20.0);
+        let rejected = OrderOutcome::Rejected(RejectReason::ExceedsMaxSpeed {
+            speed: 20.0,
+            max_speed: 12.0,
+        });
+        assert_eq!(a.execute(&Order::DeployShipOrder(op)), rejected);
+        assert!(!a.get_is_deployed());
+
+        let mut f = Fighter::new(
+            String::from("Brunhilde"),
+            String::from("G264"),
+            500.0,
+            String::from("P131"),
+            40000.0,
+            20,
+        );
+        let op = DeployAircraft::new(atime, String::from("G264"), 0.0, 400.0, 50000.0);
+        let rejected = OrderOutcome::Rejected(RejectReason::ExceedsMaxCeiling {
+            altitude: 50000.0,
+            max_ceiling: 40000.0,
+        });
+        assert_eq!(f.execute(&Order::DeployAircraftOrder(op)), rejected);
+    }
+
+    #[test]
+    fn test_patrol_at_bingo() {
+        let mut f = Fighter::new(
+            String::from("Brunhilde"),
+            String::from("G264"),
+            500.0,
+            String::from("P131"),
+            40000.0,
+            20,
+        );
+        let atime = timestamp(2015, 10, 21, 17, 2, 0);
+        let id = String::from("G264");
+        let op = DeployAircraft::new(atime, id.clone(), 90.0, 400.0, 20000.0);
+        assert!(f.execute(&Order::DeployAircraftOrder(op)).is_accepted());
+
+        // on its way home, a patrol changes nothing rather than just the
+        // speed and altitude it would have flown it at
+        f.is_bingo = true;
+        let helm = f.helm.clone();
+        let pattern = Pattern::Orbit {
+            x: 0.0,
+            y: 0.0,
+            radius: 10.0,
+        };
+        let op = Patrol::new(atime, id.clone(), 300.0, 30000.0, pattern);
+        let rejected = OrderOutcome::Rejected(RejectReason::BingoFuel);
+        assert_eq!(f.execute(&Order::PatrolOrder(op)), rejected);
+        assert_eq!(f.helm, helm);
+        assert!(f.guidance.is_none());
+    }
+
+    #[test]
+    fn test_submarine_depth() {
+        let mut a = Submarine::new(
+            String::from("Virginia"),
+            String::from("SSN-774"),
+            25.0,
+            1600.0,
+            38,
+        );
+        let atime = timestamp(2015, 10, 21, 17, 2, 0);
+        let id = String::from("SSN-774");
+        let op = DeployShip::new(atime, id.clone(), 0.0, 0.0, 90.0, 12.0);
+        assert!(a.execute(&Order::DeployShipOrder(op)).is_accepted());
+        assert!(!a.is_submerged());
+
+        // it dives at 300 feet a minute
+        let op = ChangeDepth::new(atime, id.clone(), 400.0);
+        assert!(a.execute(&Order::ChangeDepthOrder(op)).is_accepted());
+        assert!(!a.is_submerged());
+        let mut loc_map = LocationMap::new();
+        loc_map.insert(id.clone(), a.get_location());
+        a.update_position(timestamp(2015, 10, 21, 17, 3, 0), &loc_map);
+        assert!(a.is_submerged());
+        assert_eq!(a.get_depth(), 300.0);
+        a.update_position(timestamp(2015, 10, 21, 17, 7, 0), &loc_map);
+        assert_eq!(a.get_location().z, -400.0);
+        assert!((a.get_location().x - 1.0).abs() < 1e-9);
+
+        let op = ChangeDepth::new(atime, id.clone(), 2000.0);
+        let rejected = OrderOutcome::Rejected(RejectReason::ExceedsMaxDepth {
+            depth: 2000.0,
+            max_depth: 1600.0,
+        });
+        assert_eq!(a.execute(&Order::ChangeDepthOrder(op)), rejected);
+
+        let op = ChangeDepth::new(atime, id.clone(), 0.0);
+        assert!(a.execute(&Order::ChangeDepthOrder(op)).is_accepted());
+        a.update_position(timestamp(2015, 10, 21, 17, 9, 0), &loc_map);
+        assert!(!a.is_submerged());
+        assert_eq!(a.get_location().z, 0.0);
+
+        let mut c = Cruiser::new(String::from("Chelsey"), String::from("I264"), 12.0, 30);
+        let op = DeployShip::new(atime, String::from("I264"), 0.0, 0.0, 0.0, 10.0);
+        c.execute(&Order::DeployShipOrder(op));
+        let op = ChangeDepth::new(atime, String::from("I264"), 100.0);
+        let rejected = OrderOutcome::Rejected(RejectReason::WrongPlatform(MovableKind::Cruiser));
+        assert_eq!(c.execute(&Order::ChangeDepthOrder(op)), rejected);
     }
 }
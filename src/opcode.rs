@@ -0,0 +1,717 @@
+use crate::error::*;
+use crate::location::*;
+use crate::movable::*;
+use crate::order::*;
+use crate::parser::*;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// What an opcode's line describes
+pub enum Parsed {
+    /// A platform to add to the simulation
+    Platform(Box<dyn Movable>),
+    /// An order to queue
+    Order(Order),
+}
+
+/// Parses the arguments of one opcode
+pub type OpcodeParser = Rc<dyn Fn(&Line, &mut ParseContext) -> Result<Parsed, ScenarioError>>;
+
+/// The state of the scenario reader that opcode parsers need,
+/// mostly for working out when orders should be executed
+pub struct ParseContext<'a> {
+    time_format: &'a TimeFormat,
+    /// the StartSim time, if one has been read
+    start: Option<Timestamp>,
+    /// the time of the last order read for each unit,
+    /// which +HH:MM:SS times are relative to
+    last_order_times: &'a mut HashMap<String, Timestamp>,
+    /// what +HH:MM:SS times are relative to for a unit without
+    /// an earlier order, if anything
+    fallback: Option<Timestamp>,
+}
+
+impl<'a> ParseContext<'a> {
+    pub fn new(
+        time_format: &'a TimeFormat,
+        start: Option<Timestamp>,
+        last_order_times: &'a mut HashMap<String, Timestamp>,
+    ) -> Self {
+        ParseContext {
+            time_format,
+            start,
+            last_order_times,
+            fallback: None,
+        }
+    }
+
+    /// Take +HH:MM:SS times for units without an earlier order
+    /// as relative to `t`
+    pub fn relative_to(mut self, t: Timestamp) -> Self {
+        self.fallback = Some(t);
+        self
+    }
+
+    pub fn get_time_format(&self) -> &TimeFormat {
+        self.time_format
+    }
+
+    /// Parse the time an order is given at, starting at `idx`.
+    /// Returns the time and the index of the token after it
+    pub fn time(&self, line: &Line, idx: usize) -> Result<(TimeSpec, usize), ScenarioError> {
+        line.time(idx, self.time_format)
+    }
+
+    /// Turn the time an order was given into an absolute time.
+    /// `id` is the unit the order is for, if any.
+    /// On failure, returns what was needed to resolve the time
+    pub fn resolve(&mut self, when: &TimeSpec, id: Option<&str>) -> Result<Timestamp, String> {
+        let previous = id
+            .and_then(|i| self.last_order_times.get(i).cloned())
+            .or(self.fallback);
+        let t = when.resolve(self.start, previous, id.unwrap_or("StopSim"))?;
+        if let Some(i) = id {
+            self.last_order_times.insert(String::from(i), t);
+        }
+        Ok(t)
+    }
+
+    /// Resolve a time read from the token at `idx` of a line
+    pub fn resolve_time(
+        &mut self,
+        line: &Line,
+        idx: usize,
+        when: &TimeSpec,
+        id: Option<&str>,
+    ) -> Result<Timestamp, ScenarioError> {
+        self.resolve(when, id)
+            .map_err(|expected| line.error(idx, ErrorKind::InvalidField(expected)))
+    }
+}
+
+/// The opcodes that describe platforms and orders, by name.
+/// Scenario directives such as StartSim and Include aren't in here,
+/// and take precedence over any opcode registered with the same name
+#[derive(Clone)]
+pub struct OpcodeRegistry {
+    parsers: HashMap<String, OpcodeParser>,
+}
+
+impl Default for OpcodeRegistry {
+    fn default() -> Self {
+        OpcodeRegistry::new()
+    }
+}
+
+impl OpcodeRegistry {
+    /// A registry holding the built-in opcodes
+    pub fn new() -> Self {
+        let mut registry = OpcodeRegistry::empty();
+        registry.register("CreateCruiser", create_cruiser);
+        registry.register("CreateAircraftCarrier", create_aircraft_carrier);
+        registry.register("CreateSubmarine", create_submarine);
+        registry.register("CreateFighter", create_fighter);
+        registry.register("CreateHelicopter", create_helicopter);
+        registry.register("DeployShip", deploy_ship);
+        registry.register("DeployAircraft", deploy_aircraft);
+        registry.register("ChangeShipOrders", change_ship_orders);
+        registry.register("ChangeAircraftOrders", change_aircraft_orders);
+        registry.register("LandAircraft", land_aircraft);
+        registry.register("FollowRoute", follow_route);
+        registry.register("Patrol", patrol);
+        registry.register("TakeStation", take_station);
+        registry.register("Intercept", intercept);
+        registry.register("CancelOrders", cancel_orders);
+        registry.register("ChangeDepth", change_depth);
+        registry.register("Hover", hover);
+        registry
+    }
+
+    pub fn empty() -> Self {
+        OpcodeRegistry {
+            parsers: HashMap::new(),
+        }
+    }
+
+    /// Add an opcode, replacing any existing one with the same name
+    pub fn register<F>(&mut self, name: &str, parser: F)
+    where
+        F: Fn(&Line, &mut ParseContext) -> Result<Parsed, ScenarioError> + 'static,
+    {
+        self.parsers.insert(String::from(name), Rc::new(parser));
+    }
+
+    pub fn get(&self, name: &str) -> Option<OpcodeParser> {
+        self.parsers.get(name).cloned()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.parsers.contains_key(name)
+    }
+}
+
+fn create_cruiser(line: &Line, _ctx: &mut ParseContext) -> Result<Parsed, ScenarioError> {
+    let name = line.string(0, "name")?;
+    let id = line.string(1, "id")?;
+    let max_speed = line.f64(2, "max speed")?;
+    let missiles = line.i64(3, "max missiles")?;
+    let kinematics = kinematics(line, 4, MovableKind::Cruiser)?;
+    let mp = Box::new(Cruiser::new(name, id, max_speed, missiles).with_kinematics(kinematics));
+    Ok(Parsed::Platform(mp))
+}
+
+fn create_aircraft_carrier(line: &Line, _ctx: &mut ParseContext) -> Result<Parsed, ScenarioError> {
+    let name = line.string(0, "name")?;
+    let id = line.string(1, "id")?;
+    let max_speed = line.f64(2, "max speed")?;
+    let max_aircraft = line.i64(3, "max aircraft")?;
+    let kinematics = kinematics(line, 4, MovableKind::Carrier)?;
+    let mp = Box::new(Carrier::new(name, id, max_speed, max_aircraft).with_kinematics(kinematics));
+    Ok(Parsed::Platform(mp))
+}
+
+fn create_submarine(line: &Line, _ctx: &mut ParseContext) -> Result<Parsed, ScenarioError> {
+    let name = line.string(0, "name")?;
+    let id = line.string(1, "id")?;
+    let max_speed = line.f64(2, "max speed")?;
+    let max_depth = line.f64(3, "max depth")?;
+    if max_depth <= 0.0 {
+        let expected = String::from("max depth above 0");
+        return Err(line.error(3, ErrorKind::InvalidField(expected)));
+    }
+    let max_torpedoes = line.i64(4, "max torpedoes")?;
+    let kinematics = kinematics(line, 5, MovableKind::Submarine)?;
+    let mp = Box::new(
+        Submarine::new(name, id, max_speed, max_depth, max_torpedoes).with_kinematics(kinematics),
+    );
+    Ok(Parsed::Platform(mp))
+}
+
+fn create_fighter(line: &Line, _ctx: &mut ParseContext) -> Result<Parsed, ScenarioError> {
+    let name = line.string(0, "name")?;
+    let id = line.string(1, "id")?;
+    let ship_id = line.string(2, "carrier id")?;
+    let max_speed = line.f64(3, "max speed")?;
+    let max_ceiling = line.f64(4, "max ceiling")?;
+    let max_bombs = line.i64(5, "max bombs")?;
+    let kinematics = kinematics(line, 6, MovableKind::Fighter)?;
+    let fuel = fuel(line, 9)?;
+    let mp = Box::new(
+        Fighter::new(name, id, max_speed, ship_id, max_ceiling, max_bombs)
+            .with_kinematics(kinematics)
+            .with_fuel(fuel),
+    );
+    Ok(Parsed::Platform(mp))
+}
+
+fn create_helicopter(line: &Line, _ctx: &mut ParseContext) -> Result<Parsed, ScenarioError> {
+    let name = line.string(0, "name")?;
+    let id = line.string(1, "id")?;
+    let ship_id = line.string(2, "ship id")?;
+    let max_speed = line.f64(3, "max speed")?;
+    let max_ceiling = line.f64(4, "max ceiling")?;
+    let max_torpedoes = line.i64(5, "max torpedoes")?;
+    let kinematics = kinematics(line, 6, MovableKind::Helicopter)?;
+    let mp = Box::new(
+        Helicopter::new(name, id, max_speed, ship_id, max_ceiling, max_torpedoes)
+            .with_kinematics(kinematics),
+    );
+    Ok(Parsed::Platform(mp))
+}
+
+/// Read the turn rate, acceleration and climb rate a Create opcode may
+/// end with, from `idx` on. Surface ships don't have a climb rate, and
+/// anything left out keeps the default for the type of platform
+fn kinematics(
+    line: &Line,
+    idx: usize,
+    kind: MovableKind,
+) -> Result<Option<Kinematics>, ScenarioError> {
+    if line.args.len() <= idx {
+        return Ok(None);
+    }
+    let mut kinematics = Kinematics::default_for(kind);
+    kinematics.turn_rate = rate(line, idx, "turn rate")?;
+    if line.args.len() > idx + 1 {
+        kinematics.acceleration = rate(line, idx + 1, "acceleration")?;
+    }
+    let climbs = kind == MovableKind::Submarine || !kind.is_ship();
+    if climbs && line.args.len() > idx + 2 {
+        kinematics.climb_rate = rate(line, idx + 2, "climb rate")?;
+    }
+    Ok(Some(kinematics))
+}
+
+/// Read the fuel capacity, max burn and bingo fuel a CreateFighter opcode
+/// may end with, after its kinematics. Anything left out keeps the default
+fn fuel(line: &Line, idx: usize) -> Result<Option<Fuel>, ScenarioError> {
+    if line.args.len() <= idx {
+        return Ok(None);
+    }
+    let mut fuel = Fuel {
+        capacity: rate(line, idx, "fuel capacity")?,
+        ..Fuel::default()
+    };
+    if line.args.len() > idx + 1 {
+        fuel.max_burn = rate(line, idx + 1, "max burn")?;
+    }
+    if line.args.len() > idx + 2 {
+        fuel.bingo = line.f64(idx + 2, "bingo fuel")?;
+    }
+    if fuel.bingo < 0.0 || fuel.bingo >= fuel.capacity {
+        // point at the capacity if it was what left the default bingo above it
+        let at = if line.args.len() > idx + 2 {
+            idx + 2
+        } else {
+            idx
+        };
+        let expected = String::from("bingo fuel of 0 or more, below the fuel capacity");
+        return Err(line.error(at, ErrorKind::InvalidField(expected)));
+    }
+    Ok(Some(fuel))
+}
+
+/// A rate or amount, which has to be above 0 for the unit to get anywhere
+fn rate(line: &Line, idx: usize, what: &str) -> Result<f64, ScenarioError> {
+    let rate = line.f64(idx, what)?;
+    if rate <= 0.0 {
+        let expected = format!("{} above 0", what);
+        return Err(line.error(idx, ErrorKind::InvalidField(expected)));
+    }
+    Ok(rate)
+}
+
+fn deploy_ship(line: &Line, ctx: &mut ParseContext) -> Result<Parsed, ScenarioError> {
+    let (when, i) = ctx.time(line, 0)?;
+    let id = line.string(i, "ship id")?;
+    let x = line.f64(i + 1, "x")?;
+    let y = line.f64(i + 2, "y")?;
+    let head = line.f64(i + 3, "heading")?;
+    let spd = line.f64(i + 4, "speed")?;
+    let atm = ctx.resolve_time(line, 0, &when, Some(&id))?;
+    let op = DeployShip::new(atm, id, x, y, head, spd);
+    Ok(Parsed::Order(Order::DeployShipOrder(op)))
+}
+
+fn deploy_aircraft(line: &Line, ctx: &mut ParseContext) -> Result<Parsed, ScenarioError> {
+    let (when, i) = ctx.time(line, 0)?;
+    let id = line.string(i, "aircraft id")?;
+    let head = line.f64(i + 1, "heading")?;
+    let spd = line.f64(i + 2, "speed")?;
+    let z = line.f64(i + 3, "altitude")?;
+    let atm = ctx.resolve_time(line, 0, &when, Some(&id))?;
+    let op = DeployAircraft::new(atm, id, head, spd, z);
+    Ok(Parsed::Order(Order::DeployAircraftOrder(op)))
+}
+
+fn change_ship_orders(line: &Line, ctx: &mut ParseContext) -> Result<Parsed, ScenarioError> {
+    let (when, i) = ctx.time(line, 0)?;
+    let id = line.string(i, "ship id")?;
+    let head = line.f64(i + 1, "heading")?;
+    let spd = line.f64(i + 2, "speed")?;
+    let atm = ctx.resolve_time(line, 0, &when, Some(&id))?;
+    let op = ChangeShip::new(atm, id, head, spd);
+    Ok(Parsed::Order(Order::ChangeShipOrder(op)))
+}
+
+fn change_aircraft_orders(line: &Line, ctx: &mut ParseContext) -> Result<Parsed, ScenarioError> {
+    let (when, i) = ctx.time(line, 0)?;
+    let id = line.string(i, "aircraft id")?;
+    let head = line.f64(i + 1, "heading")?;
+    let spd = line.f64(i + 2, "speed")?;
+    let z = line.f64(i + 3, "altitude")?;
+    let atm = ctx.resolve_time(line, 0, &when, Some(&id))?;
+    let op = ChangeAircraft::new(atm, id, head, spd, z);
+    Ok(Parsed::Order(Order::ChangeAircraftOrder(op)))
+}
+
+fn land_aircraft(line: &Line, ctx: &mut ParseContext) -> Result<Parsed, ScenarioError> {
+    let (when, i) = ctx.time(line, 0)?;
+    let ship_id = line.string(i, "ship id")?;
+    let id = line.string(i + 1, "aircraft id")?;
+    let atm = ctx.resolve_time(line, 0, &when, Some(&id))?;
+    let op = LandAircraft::new(atm, id, ship_id);
+    Ok(Parsed::Order(Order::LandAircraftOrder(op)))
+}
+
+fn follow_route(line: &Line, ctx: &mut ParseContext) -> Result<Parsed, ScenarioError> {
+    let (when, i) = ctx.time(line, 0)?;
+    let id = line.string(i, "unit id")?;
+    // at least one waypoint, then as many as are given
+    let mut waypoints = vec![line.waypoint(i + 1)?];
+    for idx in i + 2..line.args.len() {
+        waypoints.push(line.waypoint(idx)?);
+    }
+    let atm = ctx.resolve_time(line, 0, &when, Some(&id))?;
+    let op = FollowRoute::new(atm, id, waypoints);
+    Ok(Parsed::Order(Order::FollowRouteOrder(op)))
+}
+
+fn patrol(line: &Line, ctx: &mut ParseContext) -> Result<Parsed, ScenarioError> {
+    let (when, i) = ctx.time(line, 0)?;
+    let id = line.string(i, "unit id")?;
+    let spd = line.f64(i + 1, "speed")?;
+    let alt = line.f64(i + 2, "altitude")?;
+    let i = i + 3;
+    let pattern = match line.token(i, "pattern as Racetrack, Orbit or Box")? {
+        "Racetrack" => {
+            let (x1, y1) = line.point(i + 1, "first end")?;
+            let (x2, y2) = line.point(i + 2, "second end")?;
+            Pattern::Racetrack { x1, y1, x2, y2 }
+        }
+        "Orbit" => {
+            let (x, y) = line.point(i + 1, "centre")?;
+            let radius = line.f64(i + 2, "radius")?;
+            if radius <= 0.0 {
+                let expected = String::from("radius above 0");
+                return Err(line.error(i + 2, ErrorKind::InvalidField(expected)));
+            }
+            Pattern::Orbit { x, y, radius }
+        }
+        "Box" => {
+            let (x, y) = line.point(i + 1, "centre")?;
+            let width = line.f64(i + 2, "width")?;
+            if width <= 0.0 {
+                let expected = String::from("width above 0");
+                return Err(line.error(i + 2, ErrorKind::InvalidField(expected)));
+            }
+            let height = line.f64(i + 3, "height")?;
+            if height <= 0.0 {
+                let expected = String::from("height above 0");
+                return Err(line.error(i + 3, ErrorKind::InvalidField(expected)));
+            }
+            Pattern::Box {
+                x,
+                y,
+                width,
+                height,
+            }
+        }
+        _ => {
+            let expected = String::from("pattern as Racetrack, Orbit or Box");
+            return Err(line.error(i, ErrorKind::InvalidField(expected)));
+        }
+    };
+    let atm = ctx.resolve_time(line, 0, &when, Some(&id))?;
+    let op = Patrol::new(atm, id, spd, alt, pattern);
+    Ok(Parsed::Order(Order::PatrolOrder(op)))
+}
+
+fn take_station(line: &Line, ctx: &mut ParseContext) -> Result<Parsed, ScenarioError> {
+    let (when, i) = ctx.time(line, 0)?;
+    let id = line.string(i, "ship id")?;
+    let guide_id = line.string(i + 1, "guide id")?;
+    let bearing = line.f64(i + 2, "bearing")?;
+    let range = line.f64(i + 3, "range")?;
+    if range < 0.0 {
+        let expected = String::from("range of 0 or more");
+        return Err(line.error(i + 3, ErrorKind::InvalidField(expected)));
+    }
+    let atm = ctx.resolve_time(line, 0, &when, Some(&id))?;
+    let op = TakeStation::new(atm, id, guide_id, bearing, range);
+    Ok(Parsed::Order(Order::TakeStationOrder(op)))
+}
+
+fn intercept(line: &Line, ctx: &mut ParseContext) -> Result<Parsed, ScenarioError> {
+    let (when, i) = ctx.time(line, 0)?;
+    let id = line.string(i, "aircraft id")?;
+    let target_id = line.string(i + 1, "target id")?;
+    // the range is optional
+    let range = if line.args.len() > i + 2 {
+        line.f64(i + 2, "range")?
+    } else {
+        DEFAULT_INTERCEPT_RANGE
+    };
+    if range <= 0.0 {
+        let expected = String::from("range above 0");
+        return Err(line.error(i + 2, ErrorKind::InvalidField(expected)));
+    }
+    let atm = ctx.resolve_time(line, 0, &when, Some(&id))?;
+    let op = Intercept::new(atm, id, target_id, range);
+    Ok(Parsed::Order(Order::InterceptOrder(op)))
+}
+
+fn cancel_orders(line: &Line, ctx: &mut ParseContext) -> Result<Parsed, ScenarioError> {
+    let (when, i) = ctx.time(line, 0)?;
+    let id = line.string(i, "unit id")?;
+    // without an order id, every pending order for the unit is cancelled
+    let order_id = if line.args.len() > i + 1 {
+        let n = line.i64(i + 1, "order id")?;
+        if n < 1 {
+            let expected = String::from("order id of 1 or more");
+            return Err(line.error(i + 1, ErrorKind::InvalidField(expected)));
+        }
+        Some(n as OrderId)
+    } else {
+        None
+    };
+    let atm = ctx.resolve_time(line, 0, &when, Some(&id))?;
+    let op = CancelOrders::new(atm, id, order_id);
+    Ok(Parsed::Order(Order::CancelOrdersOrder(op)))
+}
+
+fn change_depth(line: &Line, ctx: &mut ParseContext) -> Result<Parsed, ScenarioError> {
+    let (when, i) = ctx.time(line, 0)?;
+    let id = line.string(i, "submarine id")?;
+    // 0 surfaces the submarine
+    let depth = line.f64(i + 1, "depth")?;
+    if depth < 0.0 {
+        let expected = String::from("depth of 0 or more");
+        return Err(line.error(i + 1, ErrorKind::InvalidField(expected)));
+    }
+    let atm = ctx.resolve_time(line, 0, &when, Some(&id))?;
+    let op = ChangeDepth::new(atm, id, depth);
+    Ok(Parsed::Order(Order::ChangeDepthOrder(op)))
+}
+
+fn hover(line: &Line, ctx: &mut ParseContext) -> Result<Parsed, ScenarioError> {
+    let (when, i) = ctx.time(line, 0)?;
+    let id = line.string(i, "helicopter id")?;
+    // without an altitude, it hovers at the one it is flying at
+    let altitude = if line.args.len() > i + 1 {
+        line.f64(i + 1, "altitude")?
+    } else {
+        -1.0
+    };
+    let atm = ctx.resolve_time(line, 0, &when, Some(&id))?;
+    let op = Hover::new(atm, id, altitude);
+    Ok(Parsed::Order(Order::HoverOrder(op)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenario::Format;
+    use crate::sim_manager::SimManager;
+
+    #[test]
+    fn test_builtins_registered() {
+        let registry = OpcodeRegistry::new();
+        assert!(registry.contains("CreateFighter"));
+        assert!(registry.contains("LandAircraft"));
+        assert!(!registry.contains("StartSim"));
+        assert!(!OpcodeRegistry::empty().contains("CreateFighter"));
+    }
+
+    #[test]
+    fn test_custom_opcodes() {
+        let mut sim = SimManager::new();
+        // a platform opcode with fixed performance figures
+        sim.register_opcode("CreateTiconderoga", |line, _ctx| {
+            let id = line.string(0, "id")?;
+            let mp = Cruiser::new(String::from("Ticonderoga"), id, 32.5, 122);
+            Ok(Parsed::Platform(Box::new(mp)))
+        });
+        // an order opcode that deploys a ship from a fixed anchorage
+        sim.register_opcode("LeavePort", |line, ctx| {
+            let (when, i) = ctx.time(line, 0)?;
+            let id = line.string(i, "ship id")?;
+            let atm = ctx.resolve_time(line, 0, &when, Some(&id))?;
+            let op = DeployShip::new(atm, id, -10.0, 5.0, 90.0, 12.0);
+            Ok(Parsed::Order(Order::DeployShipOrder(op)))
+        });
+        sim.init_from_str(
+            "CreateTiconderoga CG-49\n\
+             StartSim 12/14/2015 10:00:00\n\
+             LeavePort T+00:05:00 CG-49\n\
+             ChangeShipOrders +00:10:00 CG-49 180 -1\n\
+             StopSim T+01:00:00",
+        )
+        .unwrap();
+        assert!(sim.validate().is_empty());
+
+        let doc = sim.to_doc();
+        assert_eq!(
+            doc.to_text().lines().collect::<Vec<&str>>()[..4],
+            [
+                "CreateCruiser Ticonderoga CG-49 32.5 122",
+                "StartSim 2015-12-14T10:00:00Z",
+                "DeployShip 2015-12-14T10:05:00Z CG-49 -10 5 90 12",
+                "ChangeShipOrders 2015-12-14T10:15:00Z CG-49 180 -1",
+            ]
+        );
+    }
+
+    /// An order defined outside the crate, slowing a unit to a speed
+    #[derive(Debug, Clone)]
+    struct SlowTo {
+        id: String,
+        extime: Timestamp,
+        speed: f64,
+    }
+
+    impl UnitOrder for SlowTo {
+        fn get_id(&self) -> String {
+            self.id.clone()
+        }
+        fn get_extime(&self) -> Timestamp {
+            self.extime
+        }
+        fn set_extime(&mut self, t: Timestamp) {
+            self.extime = t;
+        }
+        fn get_opcode(&self) -> &str {
+            "SlowTo"
+        }
+        fn get_args(&self) -> Vec<String> {
+            vec![self.speed.to_string()]
+        }
+        fn get_speed(&self) -> Option<f64> {
+            Some(self.speed)
+        }
+        fn apply(&self, unit: &mut dyn Movable) -> OrderOutcome {
+            unit.change(-1.0, self.speed, -1.0, self.extime)
+        }
+        fn clone_box(&self) -> Box<dyn UnitOrder> {
+            Box::new(self.clone())
+        }
+    }
+
+    /// An order defined outside the crate that keeps its own state on the unit
+    #[derive(Debug, Clone)]
+    struct SetEmcon {
+        id: String,
+        extime: Timestamp,
+        emcon: String,
+    }
+
+    impl UnitOrder for SetEmcon {
+        fn get_id(&self) -> String {
+            self.id.clone()
+        }
+        fn get_extime(&self) -> Timestamp {
+            self.extime
+        }
+        fn set_extime(&mut self, t: Timestamp) {
+            self.extime = t;
+        }
+        fn get_opcode(&self) -> &str {
+            "SetEmcon"
+        }
+        fn get_args(&self) -> Vec<String> {
+            vec![self.emcon.clone()]
+        }
+        fn apply(&self, unit: &mut dyn Movable) -> OrderOutcome {
+            let state = unit.get_state_mut();
+            state.insert(String::from("emcon"), self.emcon.clone());
+            OrderOutcome::Accepted
+        }
+        fn clone_box(&self) -> Box<dyn UnitOrder> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[test]
+    fn test_custom_unit_state() {
+        let mut sim = SimManager::new();
+        sim.register_opcode("SetEmcon", |line, ctx| {
+            let (when, i) = ctx.time(line, 0)?;
+            let id = line.string(i, "unit id")?;
+            let emcon = line.string(i + 1, "EMCON state")?;
+            let extime = ctx.resolve_time(line, 0, &when, Some(&id))?;
+            let op = SetEmcon { id, extime, emcon };
+            Ok(Parsed::Order(Order::CustomOrder(Box::new(op))))
+        });
+        sim.init_from_str(
+            "CreateCruiser USS_Texas CGN-39 30 50\n\
+             CreateCruiser USS_Ohio CGN-40 30 50\n\
+             StartSim 12/14/2015 10:00:00\n\
+             DeployShip T+00:00:00 CGN-39 0 0 90 25\n\
+             SetEmcon +00:10:00 CGN-39 Silent\n\
+             StopSim T+01:00:00",
+        )
+        .unwrap();
+        assert!(sim.get_unit("CGN-39").unwrap().get_state().is_empty());
+        sim.execute();
+        let emcon = sim
+            .get_unit("CGN-39")
+            .unwrap()
+            .get_state()
+            .get("emcon")
+            .cloned();
+        assert_eq!(emcon, Some(String::from("Silent")));
+        assert!(sim.get_unit("CGN-40").unwrap().get_state().is_empty());
+    }
+
+    fn with_slow_to() -> SimManager {
+        let mut sim = SimManager::new();
+        sim.register_opcode("SlowTo", |line, ctx| {
+            let (when, i) = ctx.time(line, 0)?;
+            let id = line.string(i, "unit id")?;
+            let speed = line.f64(i + 1, "speed")?;
+            let extime = ctx.resolve_time(line, 0, &when, Some(&id))?;
+            let op = SlowTo { id, extime, speed };
+            Ok(Parsed::Order(Order::CustomOrder(Box::new(op))))
+        });
+        sim
+    }
+
+    #[test]
+    fn test_custom_orders() {
+        let mut sim = with_slow_to();
+        sim.init_from_str(
+            "CreateCruiser USS_Texas CGN-39 30 50\n\
+             StartSim 12/14/2015 10:00:00\n\
+             DeployShip T+00:00:00 CGN-39 0 0 90 25\n\
+             SlowTo +00:10:00 CGN-39 10\n\
+             SlowTo +00:10:00 CGN-39 40\n\
+             StopSim T+01:00:00",
+        )
+        .unwrap();
+        let doc = sim.to_doc();
+        assert_eq!(
+            doc.to_text().lines().nth(3),
+            Some("SlowTo 2015-12-14T10:10:00Z CGN-39 10")
+        );
+
+        // custom orders are read back with their opcode
+        let mut from_json = with_slow_to();
+        from_json
+            .init_with_format(doc.to_json().as_bytes(), "s.json", Format::Json)
+            .unwrap();
+        assert_eq!(from_json.to_doc(), doc);
+        let e = SimManager::new()
+            .init_with_format(doc.to_json().as_bytes(), "s.json", Format::Json)
+            .unwrap_err();
+        assert!(matches!(e.kind, ErrorKind::UnknownOpcode));
+        // nor can opcodes that aren't a single word
+        for opcode in ["#x", "", "Slow To"] {
+            let json = doc
+                .to_json()
+                .replace("\"SlowTo\"", &format!("{:?}", opcode));
+            let e = with_slow_to()
+                .init_with_format(json.as_bytes(), "s.json", Format::Json)
+                .unwrap_err();
+            assert!(matches!(e.kind, ErrorKind::UnknownOpcode), "{:?}", opcode);
+            assert_eq!(
+                e.to_string(),
+                format!("s.json: unknown opcode '{}'", opcode)
+            );
+        }
+
+        sim.execute();
+        let log: Vec<String> = sim.get_order_log().iter().map(|r| r.to_string()).collect();
+        assert_eq!(
+            log[1..],
+            [
+                "2015-12-14 10:10:00 +00:00 #2 SlowTo CGN-39: accepted",
+                "2015-12-14 10:20:00 +00:00 #3 SlowTo CGN-39: rejected: speed 40 exceeds max speed 30",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_custom_opcode_errors() {
+        let mut sim = SimManager::new();
+        sim.register_opcode("CreateTiconderoga", |line, _ctx| {
+            let id = line.string(0, "id")?;
+            let mp = Cruiser::new(String::from("Ticonderoga"), id, 32.5, 122);
+            Ok(Parsed::Platform(Box::new(mp)))
+        });
+        let e = sim.init_from_str("CreateTiconderoga").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "<string>:1:18: CreateTiconderoga: expected id, got end of line"
+        );
+    }
+}
@@ -1,46 +1,328 @@
+#![allow(clippy::needless_return, clippy::redundant_field_names)]
+
+use crate::guidance::{Guidance, Route, Station};
+use crate::location::*;
+use crate::movable::{Limits, Movable, MovableKind};
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 
+/// Identifies an order for as long as it is queued. Orders are numbered
+/// from 1 in the order they are read
+pub type OrderId = u64;
+
+/// Why an order wasn't carried out
+#[derive(Debug, Clone, PartialEq)]
+pub enum RejectReason {
+    /// the order is for another type of platform than this one
+    WrongPlatform(MovableKind),
+    /// only a deploy order can be given to a unit that isn't deployed
+    NotDeployed,
+    ExceedsMaxSpeed {
+        speed: f64,
+        max_speed: f64,
+    },
+    ExceedsMaxCeiling {
+        altitude: f64,
+        max_ceiling: f64,
+    },
+    ExceedsMaxDepth {
+        depth: f64,
+        max_depth: f64,
+    },
+    /// there is no unit with the order's id
+    UnknownUnit,
+    /// the aircraft is at bingo fuel, and only heads back to its carrier
+    BingoFuel,
+    /// the aircraft ran out of fuel and was lost
+    Lost,
+}
+
+impl std::fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RejectReason::WrongPlatform(kind) => write!(f, "wrong platform type for a {:?}", kind),
+            RejectReason::NotDeployed => write!(f, "not deployed"),
+            RejectReason::ExceedsMaxSpeed { speed, max_speed } => {
+                write!(f, "speed {} exceeds max speed {}", speed, max_speed)
+            }
+            RejectReason::ExceedsMaxCeiling {
+                altitude,
+                max_ceiling,
+            } => write!(
+                f,
+                "altitude {} exceeds max ceiling {}",
+                altitude, max_ceiling
+            ),
+            RejectReason::ExceedsMaxDepth { depth, max_depth } => {
+                write!(f, "depth {} exceeds max depth {}", depth, max_depth)
+            }
+            RejectReason::UnknownUnit => write!(f, "no such unit"),
+            RejectReason::BingoFuel => write!(f, "at bingo fuel and returning to its carrier"),
+            RejectReason::Lost => write!(f, "aircraft was lost"),
+        }
+    }
+}
+
+/// What became of an order when its time came
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderOutcome {
+    Accepted,
+    Rejected(RejectReason),
+    /// carried out, but held to the limits it went beyond
+    Clamped(Vec<RejectReason>),
+    /// dropped by the CancelOrders order with this id before it was executed
+    Cancelled(OrderId),
+}
+
+impl OrderOutcome {
+    /// Whether the order was carried out, even if clamped
+    pub fn is_accepted(&self) -> bool {
+        return matches!(self, OrderOutcome::Accepted | OrderOutcome::Clamped(_));
+    }
+}
+
+impl std::fmt::Display for OrderOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            OrderOutcome::Accepted => write!(f, "accepted"),
+            OrderOutcome::Rejected(reason) => write!(f, "rejected: {}", reason),
+            OrderOutcome::Clamped(exceeded) => {
+                let reasons: Vec<String> = exceeded.iter().map(|r| r.to_string()).collect();
+                write!(f, "clamped: {}", reasons.join(", "))
+            }
+            OrderOutcome::Cancelled(by) => write!(f, "cancelled by #{}", by),
+        }
+    }
+}
+
+/// What is done with an order that goes beyond what its unit can do
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum LimitPolicy {
+    /// the order isn't carried out
+    #[default]
+    Reject,
+    /// the order is carried out with its values held to the limits
+    Clamp,
+}
+
+impl LimitPolicy {
+    pub fn parse(text: &str) -> Option<LimitPolicy> {
+        match text {
+            "Reject" => Some(LimitPolicy::Reject),
+            "Clamp" => Some(LimitPolicy::Clamp),
+            _ => None,
+        }
+    }
+}
+
+/// What every order has and does. The built-in orders implement it, and
+/// orders defined outside this crate can be queued as `Order::CustomOrder`
+/// and parsed with an opcode registered on the simulation
+pub trait UnitOrder: std::fmt::Debug {
+    /// The unit the order is for
+    fn get_id(&self) -> String;
+    fn get_extime(&self) -> Timestamp;
+    fn set_extime(&mut self, t: Timestamp);
+    /// The opcode the order is written with
+    fn get_opcode(&self) -> &str;
+    /// The order's arguments after its time and unit, as written in the
+    /// text format. Only needed to save orders that aren't built in
+    fn get_args(&self) -> Vec<String> {
+        return Vec::new();
+    }
+    /// Whether the order is only for ships (Some(true)) or
+    /// only for aircraft (Some(false)), if it's only for one
+    fn for_ship(&self) -> Option<bool> {
+        return None;
+    }
+    /// Whether a type of platform can carry out the order
+    fn for_kind(&self, kind: MovableKind) -> bool {
+        return match self.for_ship() {
+            Some(s) => s == kind.is_ship(),
+            None => true,
+        };
+    }
+    /// Whether the order puts a unit into the simulation,
+    /// the only order a unit that isn't deployed can carry out
+    fn is_deploy(&self) -> bool {
+        return false;
+    }
+    /// Which of the orders for the same time goes first, lowest first.
+    /// Deployments go before other orders so the unit is there for them
+    fn get_priority(&self) -> u8 {
+        return if self.is_deploy() { 1 } else { 2 };
+    }
+    /// The speed the order sets, if it sets one
+    fn get_speed(&self) -> Option<f64> {
+        return None;
+    }
+    /// The altitude the order sets, if it sets one
+    fn get_altitude(&self) -> Option<f64> {
+        return None;
+    }
+    /// The depth the order sets, if it sets one
+    fn get_depth(&self) -> Option<f64> {
+        return None;
+    }
+    /// Hold the speed, altitude and depth the order sets to the limits.
+    /// Orders that set none of them are left as they are
+    fn clamp(&mut self, _limits: &Limits) {}
+    /// Carry out the order on the unit it is for, once
+    /// the unit has checked it can carry it out
+    fn apply(&self, unit: &mut dyn Movable) -> OrderOutcome;
+    fn clone_box(&self) -> Box<dyn UnitOrder>;
+    fn print(&self) {
+        println!("id: {} extime: {}", self.get_id(), self.get_extime())
+    }
+}
+
+impl Clone for Box<dyn UnitOrder> {
+    fn clone(&self) -> Self {
+        return self.clone_box();
+    }
+}
+
+/// An order set for -1 leaves that value unchanged
+fn if_set(value: f64) -> Option<f64> {
+    return if value != -1.0 { Some(value) } else { None };
+}
+
+#[derive(Debug, Clone)]
 pub enum Order {
     DeployShipOrder(DeployShip),
     DeployAircraftOrder(DeployAircraft),
     ChangeShipOrder(ChangeShip),
     ChangeAircraftOrder(ChangeAircraft),
     LandAircraftOrder(LandAircraft),
+    FollowRouteOrder(FollowRoute),
+    PatrolOrder(Patrol),
+    TakeStationOrder(TakeStation),
+    InterceptOrder(Intercept),
+    CancelOrdersOrder(CancelOrders),
+    ChangeDepthOrder(ChangeDepth),
+    HoverOrder(Hover),
+    /// an order defined outside this crate
+    CustomOrder(Box<dyn UnitOrder>),
 }
 
 impl Order {
-    pub fn get_id(&self) -> String {
+    pub fn as_unit_order(&self) -> &dyn UnitOrder {
         match self {
-            Order::DeployShipOrder(o) => o.get_id(),
-            Order::DeployAircraftOrder(o) => o.get_id(),
-            Order::ChangeShipOrder(o) => o.get_id(),
-            Order::ChangeAircraftOrder(o) => o.get_id(),
-            Order::LandAircraftOrder(o) => o.get_id(),
+            Order::DeployShipOrder(o) => o,
+            Order::DeployAircraftOrder(o) => o,
+            Order::ChangeShipOrder(o) => o,
+            Order::ChangeAircraftOrder(o) => o,
+            Order::LandAircraftOrder(o) => o,
+            Order::FollowRouteOrder(o) => o,
+            Order::PatrolOrder(o) => o,
+            Order::TakeStationOrder(o) => o,
+            Order::InterceptOrder(o) => o,
+            Order::CancelOrdersOrder(o) => o,
+            Order::ChangeDepthOrder(o) => o,
+            Order::HoverOrder(o) => o,
+            Order::CustomOrder(o) => o.as_ref(),
         }
     }
 
-    pub fn get_extime(&self) -> chrono::NaiveDateTime {
+    fn as_unit_order_mut(&mut self) -> &mut dyn UnitOrder {
         match self {
-            Order::DeployShipOrder(o) => o.get_extime(),
-            Order::DeployAircraftOrder(o) => o.get_extime(),
-            Order::ChangeShipOrder(o) => o.get_extime(),
-            Order::ChangeAircraftOrder(o) => o.get_extime(),
-            Order::LandAircraftOrder(o) => o.get_extime(),
+            Order::DeployShipOrder(o) => o,
+            Order::DeployAircraftOrder(o) => o,
+            Order::ChangeShipOrder(o) => o,
+            Order::ChangeAircraftOrder(o) => o,
+            Order::LandAircraftOrder(o) => o,
+            Order::FollowRouteOrder(o) => o,
+            Order::PatrolOrder(o) => o,
+            Order::TakeStationOrder(o) => o,
+            Order::InterceptOrder(o) => o,
+            Order::CancelOrdersOrder(o) => o,
+            Order::ChangeDepthOrder(o) => o,
+            Order::HoverOrder(o) => o,
+            Order::CustomOrder(o) => o.as_mut(),
         }
     }
 
-    pub fn print(&self) {
-        match self {
-            Order::DeployShipOrder(o) => o.print(),
-            Order::DeployAircraftOrder(o) => o.print(),
-            Order::ChangeShipOrder(o) => o.print(),
-            Order::ChangeAircraftOrder(o) => o.print(),
-            Order::LandAircraftOrder(o) => o.print(),
+    pub fn get_id(&self) -> String {
+        return self.as_unit_order().get_id();
+    }
+
+    pub fn get_extime(&self) -> Timestamp {
+        return self.as_unit_order().get_extime();
+    }
+
+    /// Move the order to another time, such as when
+    /// a triggered order's condition is met
+    pub fn set_extime(&mut self, t: Timestamp) {
+        self.as_unit_order_mut().set_extime(t);
+    }
+
+    pub fn get_opcode(&self) -> &str {
+        return self.as_unit_order().get_opcode();
+    }
+
+    /// Whether the order is only for ships (Some(true)) or
+    /// only for aircraft (Some(false)), if it's only for one
+    pub fn for_ship(&self) -> Option<bool> {
+        return self.as_unit_order().for_ship();
+    }
+
+    /// Whether a type of platform can carry out the order
+    pub fn for_kind(&self, kind: MovableKind) -> bool {
+        return self.as_unit_order().for_kind(kind);
+    }
+
+    /// Which of the orders for the same time goes first, lowest first
+    pub fn get_priority(&self) -> u8 {
+        return self.as_unit_order().get_priority();
+    }
+
+    /// Whether the order puts a unit into the simulation,
+    /// the only order a unit that isn't deployed can carry out
+    pub fn is_deploy(&self) -> bool {
+        return self.as_unit_order().is_deploy();
+    }
+
+    /// The speed the order sets, if it sets one
+    pub fn get_speed(&self) -> Option<f64> {
+        return self.as_unit_order().get_speed();
+    }
+
+    /// The altitude the order sets, if it sets one
+    pub fn get_altitude(&self) -> Option<f64> {
+        return self.as_unit_order().get_altitude();
+    }
+
+    /// The depth the order sets, if it sets one
+    pub fn get_depth(&self) -> Option<f64> {
+        return self.as_unit_order().get_depth();
+    }
+
+    /// Carry out the order on `unit`, or say why it can't be
+    pub fn execute_on(&self, unit: &mut dyn Movable) -> OrderOutcome {
+        if let Err(reason) = unit.check_order(self) {
+            return OrderOutcome::Rejected(reason);
         }
+        return self.as_unit_order().apply(unit);
     }
 
-    fn cmp(&self, other: &Order) -> Ordering {
-        return self.get_extime().partial_cmp(&other.get_extime()).unwrap();
+    /// Carry out the order on `unit`. If it goes beyond what the unit
+    /// can do and the policy is to clamp, the order is held to the limits
+    pub fn execute_with(&self, unit: &mut dyn Movable, policy: LimitPolicy) -> OrderOutcome {
+        let limits = unit.get_limits();
+        let exceeded = limits.check(self);
+        if policy == LimitPolicy::Reject || exceeded.is_empty() {
+            return unit.execute(self);
+        }
+        let mut clamped = self.clone();
+        clamped.as_unit_order_mut().clamp(&limits);
+        return match unit.execute(&clamped) {
+            OrderOutcome::Accepted => OrderOutcome::Clamped(exceeded),
+            outcome => outcome,
+        };
+    }
+
+    pub fn print(&self) {
+        self.as_unit_order().print();
     }
 }
 
@@ -54,7 +336,7 @@ impl Eq for Order {}
 
 impl Ord for Order {
     fn cmp(&self, other: &Order) -> Ordering {
-        return self.cmp(other);
+        return self.get_extime().cmp(&other.get_extime());
     }
 }
 
@@ -64,10 +346,10 @@ impl PartialOrd for Order {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DeployShip {
     pub id: String,
-    pub extime: chrono::NaiveDateTime,
+    pub extime: Timestamp,
     pub start_x: f64,
     pub start_y: f64,
     pub heading: f64,
@@ -75,7 +357,7 @@ pub struct DeployShip {
 }
 
 impl DeployShip {
-    pub fn new(a: chrono::NaiveDateTime, id: String, x: f64, y: f64, head: f64, spd: f64) -> Self {
+    pub fn new(a: Timestamp, id: String, x: f64, y: f64, head: f64, spd: f64) -> Self {
         DeployShip {
             extime: a,
             id: id,
@@ -85,31 +367,58 @@ impl DeployShip {
             speed: spd,
         }
     }
+}
 
-    pub fn get_id(&self) -> String {
+impl UnitOrder for DeployShip {
+    fn get_id(&self) -> String {
         return self.id.clone();
     }
-
-    pub fn get_extime(&self) -> chrono::NaiveDateTime {
-        return self.extime.clone();
+    fn get_extime(&self) -> Timestamp {
+        return self.extime;
     }
-
-    pub fn print(&self) {
-        println!("id: {} extime: {}", self.id, self.extime)
+    fn set_extime(&mut self, t: Timestamp) {
+        self.extime = t;
+    }
+    fn get_opcode(&self) -> &str {
+        return "DeployShip";
+    }
+    fn for_ship(&self) -> Option<bool> {
+        return Some(true);
+    }
+    fn is_deploy(&self) -> bool {
+        return true;
+    }
+    fn get_speed(&self) -> Option<f64> {
+        return if_set(self.speed);
+    }
+    fn clamp(&mut self, limits: &Limits) {
+        self.speed = limits.clamp_speed(self.speed);
+    }
+    fn apply(&self, unit: &mut dyn Movable) -> OrderOutcome {
+        return unit.deploy(
+            self.start_x,
+            self.start_y,
+            self.heading,
+            self.speed,
+            self.extime,
+        );
+    }
+    fn clone_box(&self) -> Box<dyn UnitOrder> {
+        return Box::new(self.clone());
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DeployAircraft {
     pub id: String,
-    pub extime: chrono::NaiveDateTime,
+    pub extime: Timestamp,
     pub heading: f64,
     pub speed: f64,
     pub altitude: f64,
 }
 
 impl DeployAircraft {
-    pub fn new(a: chrono::NaiveDateTime, id: String, head: f64, spd: f64, alt: f64) -> Self {
+    pub fn new(a: Timestamp, id: String, head: f64, spd: f64, alt: f64) -> Self {
         DeployAircraft {
             extime: a,
             id: id,
@@ -118,30 +427,55 @@ impl DeployAircraft {
             altitude: alt,
         }
     }
+}
 
-    pub fn get_id(&self) -> String {
+impl UnitOrder for DeployAircraft {
+    fn get_id(&self) -> String {
         return self.id.clone();
     }
-
-    pub fn get_extime(&self) -> chrono::NaiveDateTime {
-        return self.extime.clone();
+    fn get_extime(&self) -> Timestamp {
+        return self.extime;
     }
-
-    pub fn print(&self) {
-        println!("id: {} extime: {}", self.id, self.extime)
+    fn set_extime(&mut self, t: Timestamp) {
+        self.extime = t;
+    }
+    fn get_opcode(&self) -> &str {
+        return "DeployAircraft";
+    }
+    fn for_ship(&self) -> Option<bool> {
+        return Some(false);
+    }
+    fn is_deploy(&self) -> bool {
+        return true;
+    }
+    fn get_speed(&self) -> Option<f64> {
+        return if_set(self.speed);
+    }
+    fn get_altitude(&self) -> Option<f64> {
+        return if_set(self.altitude);
+    }
+    fn clamp(&mut self, limits: &Limits) {
+        self.speed = limits.clamp_speed(self.speed);
+        self.altitude = limits.clamp_altitude(self.altitude);
+    }
+    fn apply(&self, unit: &mut dyn Movable) -> OrderOutcome {
+        return unit.launch(self.heading, self.speed, self.altitude, self.extime);
+    }
+    fn clone_box(&self) -> Box<dyn UnitOrder> {
+        return Box::new(self.clone());
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ChangeShip {
     pub id: String,
-    pub extime: chrono::NaiveDateTime,
+    pub extime: Timestamp,
     pub heading: f64,
     pub speed: f64,
 }
 
 impl ChangeShip {
-    pub fn new(a: chrono::NaiveDateTime, id: String, head: f64, spd: f64) -> Self {
+    pub fn new(a: Timestamp, id: String, head: f64, spd: f64) -> Self {
         ChangeShip {
             extime: a,
             id: id,
@@ -149,31 +483,49 @@ impl ChangeShip {
             speed: spd,
         }
     }
+}
 
-    pub fn get_id(&self) -> String {
+impl UnitOrder for ChangeShip {
+    fn get_id(&self) -> String {
         return self.id.clone();
     }
-
-    pub fn get_extime(&self) -> chrono::NaiveDateTime {
-        return self.extime.clone();
+    fn get_extime(&self) -> Timestamp {
+        return self.extime;
     }
-
-    pub fn print(&self) {
-        println!("id: {} extime: {}", self.id, self.extime)
+    fn set_extime(&mut self, t: Timestamp) {
+        self.extime = t;
+    }
+    fn get_opcode(&self) -> &str {
+        return "ChangeShipOrders";
+    }
+    fn for_ship(&self) -> Option<bool> {
+        return Some(true);
+    }
+    fn get_speed(&self) -> Option<f64> {
+        return if_set(self.speed);
+    }
+    fn clamp(&mut self, limits: &Limits) {
+        self.speed = limits.clamp_speed(self.speed);
+    }
+    fn apply(&self, unit: &mut dyn Movable) -> OrderOutcome {
+        return unit.change(self.heading, self.speed, 0.0, self.extime);
+    }
+    fn clone_box(&self) -> Box<dyn UnitOrder> {
+        return Box::new(self.clone());
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ChangeAircraft {
     pub id: String,
-    pub extime: chrono::NaiveDateTime,
+    pub extime: Timestamp,
     pub heading: f64,
     pub speed: f64,
     pub altitude: f64,
 }
 
 impl ChangeAircraft {
-    pub fn new(a: chrono::NaiveDateTime, id: String, head: f64, spd: f64, alt: f64) -> Self {
+    pub fn new(a: Timestamp, id: String, head: f64, spd: f64, alt: f64) -> Self {
         ChangeAircraft {
             extime: a,
             id: id,
@@ -182,46 +534,509 @@ impl ChangeAircraft {
             altitude: alt,
         }
     }
+}
 
-    pub fn get_id(&self) -> String {
+impl UnitOrder for ChangeAircraft {
+    fn get_id(&self) -> String {
         return self.id.clone();
     }
-
-    pub fn get_extime(&self) -> chrono::NaiveDateTime {
-        return self.extime.clone();
+    fn get_extime(&self) -> Timestamp {
+        return self.extime;
     }
-
-    pub fn print(&self) {
-        println!("id: {} extime: {}", self.id, self.extime)
+    fn set_extime(&mut self, t: Timestamp) {
+        self.extime = t;
+    }
+    fn get_opcode(&self) -> &str {
+        return "ChangeAircraftOrders";
+    }
+    fn for_ship(&self) -> Option<bool> {
+        return Some(false);
+    }
+    fn get_speed(&self) -> Option<f64> {
+        return if_set(self.speed);
+    }
+    fn get_altitude(&self) -> Option<f64> {
+        return if_set(self.altitude);
+    }
+    fn clamp(&mut self, limits: &Limits) {
+        self.speed = limits.clamp_speed(self.speed);
+        self.altitude = limits.clamp_altitude(self.altitude);
+    }
+    fn apply(&self, unit: &mut dyn Movable) -> OrderOutcome {
+        return unit.change(self.heading, self.speed, self.altitude, self.extime);
+    }
+    fn clone_box(&self) -> Box<dyn UnitOrder> {
+        return Box::new(self.clone());
     }
 }
 
-// #[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LandAircraft {
     pub id: String,
-    pub extime: chrono::NaiveDateTime,
+    pub extime: Timestamp,
     pub ship_id: String,
 }
 
 impl LandAircraft {
-    pub fn new(a: chrono::NaiveDateTime, id: String, ship_id: String) -> Self {
+    pub fn new(a: Timestamp, id: String, ship_id: String) -> Self {
         LandAircraft {
             extime: a,
             id: id,
             ship_id: ship_id,
         }
     }
+}
 
-    pub fn get_id(&self) -> String {
+impl UnitOrder for LandAircraft {
+    fn get_id(&self) -> String {
         return self.id.clone();
     }
+    fn get_extime(&self) -> Timestamp {
+        return self.extime;
+    }
+    fn set_extime(&mut self, t: Timestamp) {
+        self.extime = t;
+    }
+    fn get_opcode(&self) -> &str {
+        return "LandAircraft";
+    }
+    fn for_ship(&self) -> Option<bool> {
+        return Some(false);
+    }
+    fn apply(&self, unit: &mut dyn Movable) -> OrderOutcome {
+        return unit.land(self.ship_id.clone(), self.extime);
+    }
+    fn clone_box(&self) -> Box<dyn UnitOrder> {
+        return Box::new(self.clone());
+    }
+}
+
+/// A point on a route. Altitude is only used by aircraft, and
+/// leaving out the speed keeps the speed of the previous leg
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Waypoint {
+    pub x: f64,
+    pub y: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub altitude: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f64>,
+}
 
-    pub fn get_extime(&self) -> chrono::NaiveDateTime {
-        return self.extime.clone();
+#[derive(Debug, Clone)]
+pub struct FollowRoute {
+    pub id: String,
+    pub extime: Timestamp,
+    pub waypoints: Vec<Waypoint>,
+}
+
+impl FollowRoute {
+    pub fn new(a: Timestamp, id: String, waypoints: Vec<Waypoint>) -> Self {
+        FollowRoute {
+            extime: a,
+            id: id,
+            waypoints: waypoints,
+        }
     }
+}
 
-    pub fn print(&self) {
-        println!("id: {} extime: {}", self.id, self.extime)
+impl UnitOrder for FollowRoute {
+    fn get_id(&self) -> String {
+        return self.id.clone();
+    }
+    fn get_extime(&self) -> Timestamp {
+        return self.extime;
+    }
+    fn set_extime(&mut self, t: Timestamp) {
+        self.extime = t;
+    }
+    fn get_opcode(&self) -> &str {
+        return "FollowRoute";
+    }
+    /// The fastest of the route's waypoints
+    fn get_speed(&self) -> Option<f64> {
+        return self
+            .waypoints
+            .iter()
+            .filter_map(|w| w.speed)
+            .reduce(f64::max);
+    }
+    /// The highest of the route's waypoints
+    fn get_altitude(&self) -> Option<f64> {
+        return self
+            .waypoints
+            .iter()
+            .filter_map(|w| w.altitude)
+            .reduce(f64::max);
+    }
+    fn clamp(&mut self, limits: &Limits) {
+        for w in self.waypoints.iter_mut() {
+            w.speed = w.speed.map(|s| limits.clamp_speed(s));
+            w.altitude = w.altitude.map(|a| limits.clamp_altitude(a));
+        }
+    }
+    fn apply(&self, unit: &mut dyn Movable) -> OrderOutcome {
+        let route = Route::new(self.waypoints.clone());
+        return unit.guide(Guidance::Route(route), self.extime);
+    }
+    fn clone_box(&self) -> Box<dyn UnitOrder> {
+        return Box::new(self.clone());
+    }
+    fn print(&self) {
+        println!(
+            "id: {} extime: {} waypoints: {}",
+            self.id,
+            self.extime,
+            self.waypoints.len()
+        )
+    }
+}
+
+/// The shape of a patrol, which is flown until another order supersedes it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "shape")]
+pub enum Pattern {
+    /// Back and forth between two points
+    Racetrack { x1: f64, y1: f64, x2: f64, y2: f64 },
+    /// Clockwise circles around a point
+    Orbit { x: f64, y: f64, radius: f64 },
+    /// Clockwise around a rectangle centred on a point
+    Box {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Patrol {
+    pub id: String,
+    pub extime: Timestamp,
+    /// -1 leaves the speed as it is
+    pub speed: f64,
+    /// -1 leaves the altitude as it is. Ships ignore it
+    pub altitude: f64,
+    pub pattern: Pattern,
+}
+
+impl Patrol {
+    pub fn new(a: Timestamp, id: String, spd: f64, alt: f64, pattern: Pattern) -> Self {
+        Patrol {
+            extime: a,
+            id: id,
+            speed: spd,
+            altitude: alt,
+            pattern: pattern,
+        }
+    }
+}
+
+impl UnitOrder for Patrol {
+    fn get_id(&self) -> String {
+        return self.id.clone();
+    }
+    fn get_extime(&self) -> Timestamp {
+        return self.extime;
+    }
+    fn set_extime(&mut self, t: Timestamp) {
+        self.extime = t;
+    }
+    fn get_opcode(&self) -> &str {
+        return "Patrol";
+    }
+    fn get_speed(&self) -> Option<f64> {
+        return if_set(self.speed);
+    }
+    fn get_altitude(&self) -> Option<f64> {
+        return if_set(self.altitude);
+    }
+    fn clamp(&mut self, limits: &Limits) {
+        self.speed = limits.clamp_speed(self.speed);
+        self.altitude = limits.clamp_altitude(self.altitude);
+    }
+    fn apply(&self, unit: &mut dyn Movable) -> OrderOutcome {
+        // refused before the speed and altitude change, not after
+        if let Some(reason) = unit.guidance_refused() {
+            return OrderOutcome::Rejected(reason);
+        }
+        match unit.change(-1.0, self.speed, self.altitude, self.extime) {
+            OrderOutcome::Accepted => unit.guide(Guidance::patrol(&self.pattern), self.extime),
+            rejected => rejected,
+        }
+    }
+    fn clone_box(&self) -> Box<dyn UnitOrder> {
+        return Box::new(self.clone());
+    }
+    fn print(&self) {
+        println!(
+            "id: {} extime: {} pattern: {:?}",
+            self.id, self.extime, self.pattern
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TakeStation {
+    pub id: String,
+    pub extime: Timestamp,
+    /// the unit to keep station on
+    pub guide_id: String,
+    /// degrees, relative to the guide's heading
+    pub bearing: f64,
+    pub range: f64,
+}
+
+impl TakeStation {
+    pub fn new(a: Timestamp, id: String, guide_id: String, bearing: f64, range: f64) -> Self {
+        TakeStation {
+            extime: a,
+            id: id,
+            guide_id: guide_id,
+            bearing: bearing,
+            range: range,
+        }
+    }
+}
+
+impl UnitOrder for TakeStation {
+    fn get_id(&self) -> String {
+        return self.id.clone();
+    }
+    fn get_extime(&self) -> Timestamp {
+        return self.extime;
+    }
+    fn set_extime(&mut self, t: Timestamp) {
+        self.extime = t;
+    }
+    fn get_opcode(&self) -> &str {
+        return "TakeStation";
+    }
+    fn for_ship(&self) -> Option<bool> {
+        return Some(true);
+    }
+    fn apply(&self, unit: &mut dyn Movable) -> OrderOutcome {
+        let station = Station::new(self.guide_id.as_str(), self.bearing, self.range);
+        return unit.guide(Guidance::Station(station), self.extime);
+    }
+    fn clone_box(&self) -> Box<dyn UnitOrder> {
+        return Box::new(self.clone());
+    }
+    fn print(&self) {
+        println!(
+            "id: {} extime: {} guide: {}",
+            self.id, self.extime, self.guide_id
+        )
+    }
+}
+
+/// How close, in nm, an intercepting aircraft has to get when no range is given
+pub const DEFAULT_INTERCEPT_RANGE: f64 = 1.0;
+
+#[derive(Debug, Clone)]
+pub struct Intercept {
+    pub id: String,
+    pub extime: Timestamp,
+    pub target_id: String,
+    /// nm from the target that counts as intercepted
+    pub range: f64,
+}
+
+impl Intercept {
+    pub fn new(a: Timestamp, id: String, target_id: String, range: f64) -> Self {
+        Intercept {
+            extime: a,
+            id: id,
+            target_id: target_id,
+            range: range,
+        }
+    }
+}
+
+impl UnitOrder for Intercept {
+    fn get_id(&self) -> String {
+        return self.id.clone();
+    }
+    fn get_extime(&self) -> Timestamp {
+        return self.extime;
+    }
+    fn set_extime(&mut self, t: Timestamp) {
+        self.extime = t;
+    }
+    fn get_opcode(&self) -> &str {
+        return "Intercept";
+    }
+    fn for_ship(&self) -> Option<bool> {
+        return Some(false);
+    }
+    fn apply(&self, unit: &mut dyn Movable) -> OrderOutcome {
+        let intercept = crate::guidance::Intercept::new(self.target_id.as_str(), self.range);
+        return unit.guide(Guidance::Intercept(intercept), self.extime);
+    }
+    fn clone_box(&self) -> Box<dyn UnitOrder> {
+        return Box::new(self.clone());
+    }
+    fn print(&self) {
+        println!(
+            "id: {} extime: {} target: {}",
+            self.id, self.extime, self.target_id
+        )
+    }
+}
+
+/// Drop orders for a unit that haven't been executed yet, either one
+/// order or all of them. It is carried out by the simulation itself
+/// rather than by the unit
+#[derive(Debug, Clone)]
+pub struct CancelOrders {
+    pub id: String,
+    pub extime: Timestamp,
+    /// the order to cancel, or None for every pending order for the unit
+    pub order_id: Option<OrderId>,
+}
+
+impl CancelOrders {
+    pub fn new(a: Timestamp, id: String, order_id: Option<OrderId>) -> Self {
+        CancelOrders {
+            extime: a,
+            id: id,
+            order_id: order_id,
+        }
+    }
+}
+
+impl UnitOrder for CancelOrders {
+    fn get_id(&self) -> String {
+        return self.id.clone();
+    }
+    fn get_extime(&self) -> Timestamp {
+        return self.extime;
+    }
+    fn set_extime(&mut self, t: Timestamp) {
+        self.extime = t;
+    }
+    fn get_opcode(&self) -> &str {
+        return "CancelOrders";
+    }
+    /// Cancellations go first so they catch orders for the same time
+    fn get_priority(&self) -> u8 {
+        return 0;
+    }
+    /// The simulation carries out cancellations, so a unit never should
+    fn apply(&self, unit: &mut dyn Movable) -> OrderOutcome {
+        return OrderOutcome::Rejected(RejectReason::WrongPlatform(unit.get_kind()));
+    }
+    fn clone_box(&self) -> Box<dyn UnitOrder> {
+        return Box::new(self.clone());
+    }
+    fn print(&self) {
+        match self.order_id {
+            Some(n) => println!("id: {} extime: {} cancel: #{}", self.id, self.extime, n),
+            None => println!("id: {} extime: {} cancel: all", self.id, self.extime),
+        }
+    }
+}
+
+/// Take a submarine to a depth below the surface, or surface it at 0
+#[derive(Debug, Clone)]
+pub struct ChangeDepth {
+    pub id: String,
+    pub extime: Timestamp,
+    pub depth: f64,
+}
+
+impl ChangeDepth {
+    pub fn new(a: Timestamp, id: String, depth: f64) -> Self {
+        ChangeDepth {
+            extime: a,
+            id: id,
+            depth: depth,
+        }
+    }
+}
+
+impl UnitOrder for ChangeDepth {
+    fn get_id(&self) -> String {
+        return self.id.clone();
+    }
+    fn get_extime(&self) -> Timestamp {
+        return self.extime;
+    }
+    fn set_extime(&mut self, t: Timestamp) {
+        self.extime = t;
+    }
+    fn get_opcode(&self) -> &str {
+        return "ChangeDepth";
+    }
+    fn for_kind(&self, kind: MovableKind) -> bool {
+        return kind == MovableKind::Submarine;
+    }
+    fn get_depth(&self) -> Option<f64> {
+        return Some(self.depth);
+    }
+    fn clamp(&mut self, limits: &Limits) {
+        self.depth = limits.clamp_depth(self.depth);
+    }
+    fn apply(&self, unit: &mut dyn Movable) -> OrderOutcome {
+        return unit.change_depth(self.depth, self.extime);
+    }
+    fn clone_box(&self) -> Box<dyn UnitOrder> {
+        return Box::new(self.clone());
+    }
+    fn print(&self) {
+        println!(
+            "id: {} extime: {} depth: {}",
+            self.id, self.extime, self.depth
+        )
+    }
+}
+
+/// Stop a helicopter in the air where it is
+#[derive(Debug, Clone)]
+pub struct Hover {
+    pub id: String,
+    pub extime: Timestamp,
+    /// -1 leaves the altitude as it is
+    pub altitude: f64,
+}
+
+impl Hover {
+    pub fn new(a: Timestamp, id: String, alt: f64) -> Self {
+        Hover {
+            extime: a,
+            id: id,
+            altitude: alt,
+        }
+    }
+}
+
+impl UnitOrder for Hover {
+    fn get_id(&self) -> String {
+        return self.id.clone();
+    }
+    fn get_extime(&self) -> Timestamp {
+        return self.extime;
+    }
+    fn set_extime(&mut self, t: Timestamp) {
+        self.extime = t;
+    }
+    fn get_opcode(&self) -> &str {
+        return "Hover";
+    }
+    fn for_kind(&self, kind: MovableKind) -> bool {
+        return kind == MovableKind::Helicopter;
+    }
+    fn get_altitude(&self) -> Option<f64> {
+        return if_set(self.altitude);
+    }
+    fn clamp(&mut self, limits: &Limits) {
+        self.altitude = limits.clamp_altitude(self.altitude);
+    }
+    fn apply(&self, unit: &mut dyn Movable) -> OrderOutcome {
+        return unit.hover(self.altitude, self.extime);
+    }
+    fn clone_box(&self) -> Box<dyn UnitOrder> {
+        return Box::new(self.clone());
     }
 }
 
@@ -231,42 +1046,55 @@ mod tests {
 
     #[test]
     fn test_deploy_ship_new() {
-        let atime = chrono::NaiveDate::from_ymd(2015, 10, 21).and_hms(17, 2, 0);
+        let atime = timestamp(2015, 10, 21, 17, 2, 0);
         let a = DeployShip::new(atime, String::from("CGN-39"), 0.0, 0.0, 0.0, 0.0);
         a.print();
     }
 
     #[test]
     fn test_deploy_aircraft_new() {
-        let atime = chrono::NaiveDate::from_ymd(2015, 11, 21).and_hms(17, 10, 0);
+        let atime = timestamp(2015, 11, 21, 17, 10, 0);
         let a = DeployAircraft::new(atime, String::from("FA18C_1"), 0.0, 0.0, 0.0);
         a.print();
     }
 
     #[test]
     fn test_change_ship_new() {
-        let atime = chrono::NaiveDate::from_ymd(2015, 11, 21).and_hms(17, 12, 0);
+        let atime = timestamp(2015, 11, 21, 17, 12, 0);
         let a = ChangeShip::new(atime, String::from("CGN-39"), 0.0, 100.0);
         a.print();
     }
 
     #[test]
     fn test_change_aircraft_new() {
-        let atime = chrono::NaiveDate::from_ymd(2015, 11, 21).and_hms(17, 13, 0);
+        let atime = timestamp(2015, 11, 21, 17, 13, 0);
         let a = ChangeAircraft::new(atime, String::from("FA18C_1"), 0.0, 500.0, -1.0);
         a.print();
     }
 
     #[test]
     fn test_land_aircraft_new() {
-        let atime = chrono::NaiveDate::from_ymd(2015, 11, 21).and_hms(17, 13, 0);
+        let atime = timestamp(2015, 11, 21, 17, 13, 0);
         let a = LandAircraft::new(atime, String::from("FA18C_1"), String::from("CVN-68"));
         a.print();
     }
 
+    #[test]
+    fn test_follow_route_new() {
+        let atime = timestamp(2015, 11, 21, 17, 13, 0);
+        let wp = Waypoint {
+            x: 10.0,
+            y: 5.0,
+            altitude: None,
+            speed: Some(30.0),
+        };
+        let a = FollowRoute::new(atime, String::from("CGN-39"), vec![wp]);
+        a.print();
+    }
+
     #[test]
     fn test_order_equals() {
-        let atime = chrono::NaiveDate::from_ymd(2015, 10, 21).and_hms(17, 2, 0);
+        let atime = timestamp(2015, 10, 21, 17, 2, 0);
         let a = DeployShip::new(atime, String::from("CGN-39"), 0.0, 0.0, 0.0, 0.0);
         let b = DeployShip::new(atime, String::from("CVN-68"), 1.0, 1.0, 0.0, 0.0);
         assert!(Order::DeployShipOrder(a) == Order::DeployShipOrder(b));
@@ -274,7 +1102,7 @@ mod tests {
 
     #[test]
     fn test_order_equals_not() {
-        let atime = chrono::NaiveDate::from_ymd(2015, 10, 21).and_hms(17, 2, 0);
+        let atime = timestamp(2015, 10, 21, 17, 2, 0);
         let a = DeployShip::new(atime, String::from("CGN-39"), 0.0, 0.0, 0.0, 0.0);
         let btime = atime - chrono::Duration::seconds(1);
         let b = DeployShip::new(btime, String::from("CVN-68"), 1.0, 1.0, 0.0, 0.0);
@@ -0,0 +1,152 @@
+use crate::error::Origin;
+use crate::location::*;
+use crate::order::*;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+/// Where an order comes in the queue: by time, then priority,
+/// then the order the orders were given in
+pub type OrderKey = (Timestamp, u8, OrderId);
+
+/// An order waiting to be executed, along with where it was defined
+#[derive(Debug, Clone)]
+pub struct QueuedOrder {
+    /// stays the same however the queue changes
+    pub id: OrderId,
+    pub order: Order,
+    pub origin: Option<Origin>,
+}
+
+impl QueuedOrder {
+    pub fn key(&self) -> OrderKey {
+        (self.order.get_extime(), self.order.get_priority(), self.id)
+    }
+}
+
+impl PartialEq for QueuedOrder {
+    fn eq(&self, other: &QueuedOrder) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for QueuedOrder {}
+
+impl Ord for QueuedOrder {
+    fn cmp(&self, other: &QueuedOrder) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl PartialOrd for QueuedOrder {
+    fn partial_cmp(&self, other: &QueuedOrder) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The orders waiting to be executed. Adding an order and taking the
+/// next one are O(log n), so orders can be added mid-run cheaply.
+/// Order ids are unique, which makes the order fully deterministic
+#[derive(Debug, Default)]
+pub struct OrderQueue {
+    heap: BinaryHeap<Reverse<QueuedOrder>>,
+}
+
+impl OrderQueue {
+    pub fn new() -> Self {
+        OrderQueue {
+            heap: BinaryHeap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    pub fn push(&mut self, qo: QueuedOrder) {
+        self.heap.push(Reverse(qo));
+    }
+
+    /// The next order to be executed
+    pub fn peek(&self) -> Option<&QueuedOrder> {
+        self.heap.peek().map(|o| &o.0)
+    }
+
+    /// Take the next order, if it is due by `now`
+    pub fn pop_due(&mut self, now: Timestamp) -> Option<QueuedOrder> {
+        if self.peek()?.order.get_extime() > now {
+            return None;
+        }
+        self.heap.pop().map(|o| o.0)
+    }
+
+    /// Every order, in the order they will be executed
+    pub fn iter(&self) -> std::vec::IntoIter<&QueuedOrder> {
+        let mut orders: Vec<&QueuedOrder> = self.heap.iter().map(|o| &o.0).collect();
+        orders.sort();
+        orders.into_iter()
+    }
+
+    pub fn find(&self, id: OrderId) -> Option<&QueuedOrder> {
+        self.heap.iter().map(|o| &o.0).find(|o| o.id == id)
+    }
+
+    /// Take the orders `f` picks out of the queue, in no particular order
+    pub fn remove_if<F>(&mut self, f: F) -> Vec<QueuedOrder>
+    where
+        F: Fn(&QueuedOrder) -> bool,
+    {
+        let (removed, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.heap)
+            .into_vec()
+            .into_iter()
+            .partition(|o| f(&o.0));
+        self.heap = BinaryHeap::from(kept);
+        removed.into_iter().map(|o| o.0).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(id: OrderId, minute: u32) -> QueuedOrder {
+        let t = timestamp(2015, 12, 14, 10, minute, 0);
+        let op = ChangeShip::new(t, String::from("CGN-39"), 90.0, -1.0);
+        QueuedOrder {
+            id,
+            order: Order::ChangeShipOrder(op),
+            origin: None,
+        }
+    }
+
+    #[test]
+    fn test_queue_order() {
+        let mut q = OrderQueue::new();
+        for (id, minute) in [(1, 10), (2, 5), (3, 10), (4, 0)].iter() {
+            q.push(change(*id, *minute));
+        }
+        // deploying goes before other orders for the same time
+        let t = timestamp(2015, 12, 14, 10, 10, 0);
+        let op = DeployShip::new(t, String::from("CGN-39"), 0.0, 0.0, 90.0, 20.0);
+        q.push(QueuedOrder {
+            id: 5,
+            order: Order::DeployShipOrder(op),
+            origin: None,
+        });
+        let ids: Vec<OrderId> = q.iter().map(|o| o.id).collect();
+        assert_eq!(ids, [4, 2, 5, 1, 3]);
+
+        assert_eq!(q.pop_due(timestamp(2015, 12, 14, 10, 5, 0)).unwrap().id, 4);
+        assert_eq!(q.pop_due(timestamp(2015, 12, 14, 10, 5, 0)).unwrap().id, 2);
+        assert!(q.pop_due(timestamp(2015, 12, 14, 10, 5, 0)).is_none());
+        assert_eq!(q.len(), 3);
+
+        let removed = q.remove_if(|o| o.id == 1);
+        assert_eq!(removed.len(), 1);
+        assert!(q.find(1).is_none());
+        assert_eq!(q.peek().unwrap().id, 5);
+    }
+}
@@ -0,0 +1,723 @@
+use crate::error::*;
+use crate::location::*;
+use crate::order::Waypoint;
+use chrono::TimeZone;
+
+/// The default date and time format used by scenario files
+pub const DATETIME_FORMAT: &str = "%m/%d/%Y %H:%M:%S";
+
+/// How absolute times are written in a scenario.
+/// ISO-8601 times are always accepted as well
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeFormat {
+    /// A strftime style format, which may span several tokens
+    pub format: String,
+    /// The UTC offset of times that don't give one
+    pub zone: chrono::FixedOffset,
+}
+
+impl Default for TimeFormat {
+    fn default() -> Self {
+        TimeFormat::new(DATETIME_FORMAT, chrono::FixedOffset::east_opt(0).unwrap())
+    }
+}
+
+impl TimeFormat {
+    pub fn new(format: &str, zone: chrono::FixedOffset) -> Self {
+        TimeFormat {
+            format: String::from(format),
+            zone,
+        }
+    }
+
+    /// The number of whitespace separated tokens a time takes up
+    pub fn tokens(&self) -> usize {
+        self.format.split_whitespace().count().max(1)
+    }
+
+    /// Whether the format includes its own UTC offset
+    fn has_offset(&self) -> bool {
+        self.format.contains("%z") || self.format.contains("%:z") || self.format.contains("%#z")
+    }
+
+    /// Parse a time in either ISO-8601 or this format
+    pub fn parse(&self, text: &str) -> Option<Timestamp> {
+        if let Some(t) = parse_iso8601(text, self.zone) {
+            return Some(t);
+        }
+        if self.has_offset() {
+            return chrono::DateTime::parse_from_str(text, self.format.as_str()).ok();
+        }
+        let naive = chrono::NaiveDateTime::parse_from_str(text, self.format.as_str()).ok()?;
+        self.zone.from_local_datetime(&naive).single()
+    }
+
+    /// Describe the accepted forms of time for error messages
+    pub fn expected(&self) -> String {
+        format!("date and time as '{}' or ISO-8601", self.format)
+    }
+}
+
+/// Parse an ISO-8601 date and time such as 2015-12-14T10:00:00.5+05:00.
+/// Times without a UTC offset are taken to be in `zone`
+pub fn parse_iso8601(text: &str, zone: chrono::FixedOffset) -> Option<Timestamp> {
+    if let Ok(t) = chrono::DateTime::parse_from_rfc3339(text) {
+        return Some(t);
+    }
+    let naive = chrono::NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S%.f").ok()?;
+    zone.from_local_datetime(&naive).single()
+}
+
+/// Write a time as ISO-8601, keeping its UTC offset
+pub fn format_iso8601(t: Timestamp) -> String {
+    t.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true)
+}
+
+/// Describes a UTC offset for error messages
+pub const ZONE_EXPECTED: &str = "UTC offset such as +05:00, -0800 or UTC";
+
+/// Parse a UTC offset such as UTC, Z, +05:00, -0800 or +5
+pub fn parse_zone(text: &str) -> Option<chrono::FixedOffset> {
+    if text == "UTC" || text == "Z" {
+        return chrono::FixedOffset::east_opt(0);
+    }
+    let sign = match text.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits: String = text[1..].chars().filter(|c| *c != ':').collect();
+    if digits.is_empty() || digits.len() > 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = if digits.len() <= 2 {
+        (digits.parse::<i32>().ok()?, 0)
+    } else {
+        let split = digits.len() - 2;
+        (
+            digits[..split].parse::<i32>().ok()?,
+            digits[split..].parse::<i32>().ok()?,
+        )
+    };
+    if minutes >= 60 {
+        return None;
+    }
+    chrono::FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// When an order should be executed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeSpec {
+    /// An absolute date and time
+    At(Timestamp),
+    /// An offset from StartSim, written T+HH:MM:SS
+    AfterStart(chrono::Duration),
+    /// An offset from the previous order for the same unit, written +HH:MM:SS
+    AfterPrevious(chrono::Duration),
+}
+
+/// Parse an HH:MM:SS offset. Hours may be larger than 24
+/// and seconds may have a fractional part
+pub fn parse_offset(text: &str) -> Option<chrono::Duration> {
+    let parts: Vec<&str> = text.split(':').collect();
+    if parts.len() != 3 {
+        return None;
+    }
+    let (seconds, fraction) = match parts[2].find('.') {
+        Some(i) => (&parts[2][..i], &parts[2][i + 1..]),
+        None => (parts[2], "0"),
+    };
+    let mut values = Vec::new();
+    for p in [parts[0], parts[1], seconds, fraction].iter() {
+        if p.is_empty() || !p.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        values.push(p.parse::<i64>().ok()?);
+    }
+    if values[1] >= 60 || values[2] >= 60 || fraction.len() > 9 {
+        return None;
+    }
+    let nanos = values[3] * 10_i64.pow(9 - fraction.len() as u32);
+    // hours too large for a duration are no more valid than bad digits
+    let seconds = values[0]
+        .checked_mul(3600)?
+        .checked_add(values[1] * 60 + values[2])?;
+    chrono::Duration::try_seconds(seconds)?.checked_add(&chrono::Duration::nanoseconds(nanos))
+}
+
+/// Write a duration as HH:MM:SS, the reverse of parse_offset
+pub fn format_offset(d: chrono::Duration) -> String {
+    let seconds = d.num_seconds();
+    let nanos = (d - chrono::Duration::seconds(seconds))
+        .num_nanoseconds()
+        .unwrap_or(0);
+    let mut text = format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    );
+    if nanos > 0 {
+        text.push_str(format!(".{:09}", nanos).trim_end_matches('0'));
+    }
+    text
+}
+
+/// A time some way after another, as long as it can be represented
+fn after(t: Timestamp, d: chrono::Duration) -> Result<Timestamp, String> {
+    t.checked_add_signed(d)
+        .ok_or_else(|| String::from("an offset that keeps the time in range"))
+}
+
+impl TimeSpec {
+    /// Whether a token is a relative time rather than the start of a date
+    pub fn is_relative(text: &str) -> bool {
+        text.starts_with("T+") || text.starts_with('+')
+    }
+
+    /// Parse a time given as a single string, either relative
+    /// or a date and time in the given format
+    pub fn parse(text: &str, format: &TimeFormat) -> Option<TimeSpec> {
+        if let Some(offset) = text.strip_prefix("T+") {
+            return parse_offset(offset).map(TimeSpec::AfterStart);
+        }
+        if let Some(offset) = text.strip_prefix('+') {
+            return parse_offset(offset).map(TimeSpec::AfterPrevious);
+        }
+        format.parse(text).map(TimeSpec::At)
+    }
+
+    /// Work out the absolute time. On failure, returns what was
+    /// needed to resolve the time
+    pub fn resolve(
+        &self,
+        start: Option<Timestamp>,
+        previous: Option<Timestamp>,
+        id: &str,
+    ) -> Result<Timestamp, String> {
+        match self {
+            TimeSpec::At(t) => Ok(*t),
+            TimeSpec::AfterStart(d) => match start {
+                Some(t) => after(t, *d),
+                None => Err(String::from("a StartSim earlier in the scenario")),
+            },
+            TimeSpec::AfterPrevious(d) => match previous {
+                Some(t) => after(t, *d),
+                None => Err(format!("an earlier order for '{}'", id)),
+            },
+        }
+    }
+
+    /// Describe the accepted forms of time for error messages
+    pub fn expected(format: &TimeFormat) -> String {
+        format!("{}, T+HH:MM:SS or +HH:MM:SS", format.expected())
+    }
+}
+
+/// Whether text can be used as a variable, parameter or macro name
+pub fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// Replace $NAME and ${NAME} references in text with their values.
+/// A $ that isn't followed by a name is left alone
+pub fn substitute<F>(text: &str, lookup: F) -> Result<String, ErrorKind>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(i) = rest.find('$') {
+        result.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        let (name, after) = if let Some(braced) = rest.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => {
+                    return Err(ErrorKind::InvalidField(String::from(
+                        "'}' to close variable reference",
+                    )))
+                }
+            }
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        };
+        if !is_name(name) {
+            result.push('$');
+            continue;
+        }
+        match lookup(name) {
+            Some(value) => result.push_str(value.as_str()),
+            None => return Err(ErrorKind::Undefined(String::from(name))),
+        }
+        rest = after;
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Parse a waypoint written as x,y[,altitude][@speed]
+pub fn parse_waypoint(text: &str) -> Option<Waypoint> {
+    let (point, speed) = match text.split_once('@') {
+        Some((p, s)) => (p, Some(s.parse::<f64>().ok()?)),
+        None => (text, None),
+    };
+    let values = point
+        .split(',')
+        .map(|v| v.parse::<f64>().ok())
+        .collect::<Option<Vec<f64>>>()?;
+    if values.len() != 2 && values.len() != 3 {
+        return None;
+    }
+    Some(Waypoint {
+        x: values[0],
+        y: values[1],
+        altitude: values.get(2).cloned(),
+        speed,
+    })
+}
+
+/// A named block of scenario lines defined between Macro and EndMacro.
+/// The body is kept as text and only parsed when the macro is used
+#[derive(Debug, Clone)]
+pub struct Macro {
+    pub params: Vec<String>,
+    pub file: String,
+    /// line numbers and text of the body
+    pub body: Vec<(usize, String)>,
+}
+
+/// A whitespace separated token and the column it starts at
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub text: String,
+    pub column: usize,
+}
+
+/// A single tokenized line of a scenario file
+#[derive(Debug)]
+pub struct Line {
+    pub file: String,
+    pub number: usize,
+    pub opcode: Token,
+    pub args: Vec<Token>,
+}
+
+/// Split a line on whitespace, keeping track of the (1-based)
+/// column each token starts at
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut column = 0;
+    for (i, c) in text.chars().enumerate() {
+        if !c.is_whitespace() {
+            if current.is_empty() {
+                column = i + 1;
+            }
+            current.push(c);
+        } else if !current.is_empty() {
+            tokens.push(Token {
+                text: current.clone(),
+                column,
+            });
+            current.clear();
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(Token {
+            text: current,
+            column,
+        });
+    }
+    tokens
+}
+
+impl Line {
+    /// Tokenize a line of a scenario file.
+    /// Returns None for blank lines and comments
+    pub fn new(file: &str, number: usize, text: &str) -> Option<Line> {
+        let mut tokens = tokenize(text);
+        if tokens.is_empty() || tokens[0].text.starts_with('#') {
+            return None;
+        }
+        let opcode = tokens.remove(0);
+        Some(Line {
+            file: String::from(file),
+            number,
+            opcode,
+            args: tokens,
+        })
+    }
+
+    pub fn origin(&self) -> Origin {
+        Origin::new(self.file.as_str(), self.number)
+    }
+
+    pub fn get_opcode(&self) -> &str {
+        self.opcode.text.as_str()
+    }
+
+    /// Build an error pointing at the token at `idx`,
+    /// or at the end of the line if there is no such token
+    pub fn error(&self, idx: usize, kind: ErrorKind) -> ScenarioError {
+        let (column, token) = match self.args.get(idx) {
+            Some(t) => (t.column, t.text.clone()),
+            None => match self.args.last() {
+                Some(t) => (t.column + t.text.chars().count(), String::new()),
+                None => (
+                    self.opcode.column + self.opcode.text.chars().count(),
+                    String::new(),
+                ),
+            },
+        };
+        ScenarioError {
+            file: self.file.clone(),
+            line: self.number,
+            column,
+            opcode: self.opcode.text.clone(),
+            token,
+            kind,
+        }
+    }
+
+    /// Build an error pointing at the opcode itself
+    pub fn opcode_error(&self, kind: ErrorKind) -> ScenarioError {
+        ScenarioError {
+            file: self.file.clone(),
+            line: self.number,
+            column: self.opcode.column,
+            opcode: self.opcode.text.clone(),
+            token: self.opcode.text.clone(),
+            kind,
+        }
+    }
+
+    /// Build an error for an opcode that isn't recognized
+    pub fn unknown_opcode(&self) -> ScenarioError {
+        self.opcode_error(ErrorKind::UnknownOpcode)
+    }
+
+    /// Get the raw token at `idx`
+    pub fn token(&self, idx: usize, what: &str) -> Result<&str, ScenarioError> {
+        match self.args.get(idx) {
+            Some(t) => Ok(t.text.as_str()),
+            None => Err(self.error(idx, ErrorKind::MissingField(String::from(what)))),
+        }
+    }
+
+    pub fn string(&self, idx: usize, what: &str) -> Result<String, ScenarioError> {
+        self.token(idx, what).map(String::from)
+    }
+
+    pub fn f64(&self, idx: usize, what: &str) -> Result<f64, ScenarioError> {
+        let expected = format!("{} as number", what);
+        let token = self.token(idx, expected.as_str())?;
+        token
+            .parse::<f64>()
+            .map_err(|_| self.error(idx, ErrorKind::InvalidField(expected)))
+    }
+
+    pub fn i64(&self, idx: usize, what: &str) -> Result<i64, ScenarioError> {
+        let expected = format!("{} as integer", what);
+        let token = self.token(idx, expected.as_str())?;
+        token
+            .parse::<i64>()
+            .map_err(|_| self.error(idx, ErrorKind::InvalidField(expected)))
+    }
+
+    /// Parse a point written as x,y
+    pub fn point(&self, idx: usize, what: &str) -> Result<(f64, f64), ScenarioError> {
+        let expected = format!("{} as x,y", what);
+        let token = self.token(idx, expected.as_str())?;
+        let point = token
+            .split_once(',')
+            .and_then(|(x, y)| Some((x.parse::<f64>().ok()?, y.parse::<f64>().ok()?)));
+        point.ok_or_else(|| self.error(idx, ErrorKind::InvalidField(expected)))
+    }
+
+    pub fn waypoint(&self, idx: usize) -> Result<Waypoint, ScenarioError> {
+        let expected = "waypoint as x,y[,altitude][@speed]";
+        let token = self.token(idx, expected)?;
+        parse_waypoint(token)
+            .ok_or_else(|| self.error(idx, ErrorKind::InvalidField(String::from(expected))))
+    }
+
+    /// Parse the time an order should be executed, starting at `idx`.
+    /// Times may take up several tokens, so the index of the token
+    /// after the time is returned as well
+    pub fn time(
+        &self,
+        idx: usize,
+        format: &TimeFormat,
+    ) -> Result<(TimeSpec, usize), ScenarioError> {
+        let expected = TimeSpec::expected(format);
+        let token = self.token(idx, expected.as_str())?;
+        if TimeSpec::is_relative(token) {
+            return match TimeSpec::parse(token, format) {
+                Some(spec) => Ok((spec, idx + 1)),
+                None => Err(self.error(idx, ErrorKind::InvalidField(expected))),
+            };
+        }
+        let (t, next) = self.datetime(idx, format)?;
+        Ok((TimeSpec::At(t), next))
+    }
+
+    /// Parse an absolute date and time starting at `idx`, either as a
+    /// single ISO-8601 token or spread over as many tokens as the format has
+    pub fn datetime(
+        &self,
+        idx: usize,
+        format: &TimeFormat,
+    ) -> Result<(Timestamp, usize), ScenarioError> {
+        let expected = format.expected();
+        let first = self.token(idx, expected.as_str())?;
+        if let Some(t) = parse_iso8601(first, format.zone) {
+            return Ok((t, idx + 1));
+        }
+        let mut parts = Vec::new();
+        for i in idx..idx + format.tokens() {
+            parts.push(self.token(i, expected.as_str())?);
+        }
+        match format.parse(parts.join(" ").as_str()) {
+            Some(t) => Ok((t, idx + format.tokens())),
+            None => Err(self.error(idx, ErrorKind::InvalidField(expected))),
+        }
+    }
+
+    /// Replace variable references in the arguments. The opcode is left as it is
+    pub fn substitute<F>(&mut self, lookup: F) -> Result<(), ScenarioError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        for idx in 0..self.args.len() {
+            match substitute(self.args[idx].text.as_str(), &lookup) {
+                Ok(text) => self.args[idx].text = text,
+                Err(kind) => return Err(self.error(idx, kind)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Everything from `idx` to the end of the line, rejoined with single spaces
+    pub fn rest(&self, idx: usize, what: &str) -> Result<String, ScenarioError> {
+        self.token(idx, what)?;
+        let rest: Vec<&str> = self.args[idx..].iter().map(|t| t.text.as_str()).collect();
+        Ok(rest.join(" "))
+    }
+
+    /// The order written after the `Then` at `idx`, as a line of its own.
+    /// Its tokens keep their columns, so errors point into this line
+    pub fn then_line(&self, idx: usize) -> Result<Line, ScenarioError> {
+        if self.token(idx, "Then")? != "Then" {
+            return Err(self.error(idx, ErrorKind::InvalidField(String::from("Then"))));
+        }
+        let opcode = match self.args.get(idx + 1) {
+            Some(opcode) => opcode.clone(),
+            None => return Err(self.error(idx + 1, ErrorKind::MissingField(String::from("order")))),
+        };
+        Ok(Line {
+            file: self.file.clone(),
+            number: self.number,
+            opcode,
+            args: self.args[idx + 2..].to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_skips_comments_and_blanks() {
+        assert!(Line::new("f", 1, "").is_none());
+        assert!(Line::new("f", 1, "   \t ").is_none());
+        assert!(Line::new("f", 1, "# comment").is_none());
+        assert!(Line::new("f", 1, "   # indented comment").is_none());
+    }
+
+    #[test]
+    fn test_line_columns() {
+        let l = Line::new("f", 3, "DeployShip  12/1/2004 10:00:00 CG-49").unwrap();
+        assert_eq!(l.get_opcode(), "DeployShip");
+        assert_eq!(l.args[0].column, 13);
+        assert_eq!(l.args[2].column, 32);
+    }
+
+    #[test]
+    fn test_line_invalid_number() {
+        let l = Line::new(
+            "f.txt",
+            7,
+            "DeployShip 12/1/2004 10:00:00 CG-49 0 0 north 30",
+        )
+        .unwrap();
+        let e = l.f64(5, "heading").unwrap_err();
+        assert_eq!(e.line, 7);
+        assert_eq!(e.column, 41);
+        assert_eq!(e.token, "north");
+        assert_eq!(
+            e.to_string(),
+            "f.txt:7:41: DeployShip: expected heading as number, got 'north'"
+        );
+    }
+
+    #[test]
+    fn test_line_missing_field() {
+        let l = Line::new("f.txt", 2, "CreateCruiser USS_Texas CG-39 50").unwrap();
+        let e = l.i64(3, "max missiles").unwrap_err();
+        assert_eq!(e.column, 33);
+        assert_eq!(
+            e.to_string(),
+            "f.txt:2:33: CreateCruiser: expected max missiles as integer, got end of line"
+        );
+    }
+
+    #[test]
+    fn test_parse_offset() {
+        assert_eq!(parse_offset("00:05:00"), Some(chrono::Duration::minutes(5)));
+        assert_eq!(
+            parse_offset("36:00:01"),
+            Some(chrono::Duration::seconds(129601))
+        );
+        assert_eq!(parse_offset("00:60:00"), None);
+        assert_eq!(parse_offset("5:00"), None);
+        assert_eq!(parse_offset("00:-5:00"), None);
+        assert_eq!(parse_offset("99999999999999:00:00"), None);
+        assert_eq!(parse_offset("99999999999999999999:00:00"), None);
+    }
+
+    #[test]
+    fn test_parse_offset_fraction() {
+        assert_eq!(
+            parse_offset("00:00:01.25"),
+            Some(chrono::Duration::milliseconds(1250))
+        );
+        assert_eq!(parse_offset("00:00:01."), None);
+    }
+
+    #[test]
+    fn test_format_offset() {
+        assert_eq!(format_offset(chrono::Duration::minutes(30)), "00:30:00");
+        assert_eq!(format_offset(chrono::Duration::seconds(129601)), "36:00:01");
+        let d = chrono::Duration::milliseconds(1250);
+        assert_eq!(format_offset(d), "00:00:01.25");
+        assert_eq!(parse_offset(format_offset(d).as_str()), Some(d));
+    }
+
+    #[test]
+    fn test_line_time() {
+        let fmt = TimeFormat::default();
+        let l = Line::new("f", 1, "DeployShip 12/1/2004 10:00:00 CG-49").unwrap();
+        let (spec, next) = l.time(0, &fmt).unwrap();
+        assert_eq!(next, 2);
+        assert_eq!(spec, TimeSpec::At(l.datetime(0, &fmt).unwrap().0));
+
+        let l = Line::new("f", 1, "DeployShip T+00:05:00 CG-49").unwrap();
+        assert_eq!(
+            l.time(0, &fmt).unwrap(),
+            (TimeSpec::AfterStart(chrono::Duration::minutes(5)), 1)
+        );
+
+        let l = Line::new("f", 1, "DeployShip +01:00:00 CG-49").unwrap();
+        assert_eq!(
+            l.time(0, &fmt).unwrap(),
+            (TimeSpec::AfterPrevious(chrono::Duration::hours(1)), 1)
+        );
+
+        let l = Line::new("f", 1, "DeployShip T+5m CG-49").unwrap();
+        assert!(l.time(0, &fmt).is_err());
+    }
+
+    #[test]
+    fn test_line_datetime() {
+        let fmt = TimeFormat::default();
+        let l = Line::new("f", 1, "StartSim 12/1/2004 10:00:00").unwrap();
+        let (t, next) = l.datetime(0, &fmt).unwrap();
+        assert_eq!(t.to_string(), "2004-12-01 10:00:00 +00:00");
+        assert_eq!(next, 2);
+
+        let l = Line::new("f", 1, "StartSim 12/1/2004").unwrap();
+        assert!(l.datetime(0, &fmt).is_err());
+    }
+
+    #[test]
+    fn test_iso8601() {
+        let fmt = TimeFormat::default();
+        let l = Line::new("f", 1, "StartSim 2004-12-01T10:00:00.5+05:00 CG-49").unwrap();
+        let (t, next) = l.datetime(0, &fmt).unwrap();
+        assert_eq!(next, 1);
+        assert_eq!(format_iso8601(t), "2004-12-01T10:00:00.500+05:00");
+
+        // without an offset the scenario's time zone applies
+        let fmt = TimeFormat::new(DATETIME_FORMAT, parse_zone("-08:00").unwrap());
+        let t = fmt.parse("2004-12-01T10:00:00").unwrap();
+        assert_eq!(format_iso8601(t), "2004-12-01T10:00:00-08:00");
+        let t = fmt.parse("12/01/2004 10:00:00").unwrap();
+        assert_eq!(format_iso8601(t), "2004-12-01T10:00:00-08:00");
+    }
+
+    #[test]
+    fn test_custom_format() {
+        let fmt = TimeFormat::new("%d.%m.%Y %H:%M %z", parse_zone("UTC").unwrap());
+        assert_eq!(fmt.tokens(), 3);
+        let l = Line::new("f", 1, "StartSim 01.12.2004 10:30 +0100").unwrap();
+        let (t, next) = l.datetime(0, &fmt).unwrap();
+        assert_eq!(next, 3);
+        assert_eq!(format_iso8601(t), "2004-12-01T10:30:00+01:00");
+    }
+
+    #[test]
+    fn test_parse_zone() {
+        assert_eq!(parse_zone("Z").unwrap().local_minus_utc(), 0);
+        assert_eq!(parse_zone("+05:30").unwrap().local_minus_utc(), 19800);
+        assert_eq!(parse_zone("-0800").unwrap().local_minus_utc(), -28800);
+        assert_eq!(parse_zone("+5").unwrap().local_minus_utc(), 18000);
+        assert_eq!(parse_zone("EST"), None);
+        assert_eq!(parse_zone("+05:75"), None);
+    }
+
+    #[test]
+    fn test_substitute() {
+        let lookup = |name: &str| match name {
+            "SPEED" => Some(String::from("30")),
+            "DAY" => Some(String::from("2015-12-14")),
+            _ => None,
+        };
+        assert_eq!(substitute("$SPEED", lookup).unwrap(), "30");
+        assert_eq!(
+            substitute("${DAY}T10:00:00", lookup).unwrap(),
+            "2015-12-14T10:00:00"
+        );
+        assert_eq!(substitute("$5 and $", lookup).unwrap(), "$5 and $");
+        assert!(matches!(
+            substitute("$ALTITUDE", lookup),
+            Err(ErrorKind::Undefined(name)) if name == "ALTITUDE"
+        ));
+        assert!(substitute("${DAY", lookup).is_err());
+    }
+
+    #[test]
+    fn test_parse_waypoint() {
+        let wp = parse_waypoint("10,-5.5").unwrap();
+        assert_eq!(
+            (wp.x, wp.y, wp.altitude, wp.speed),
+            (10.0, -5.5, None, None)
+        );
+        let wp = parse_waypoint("10,5,30000@450").unwrap();
+        assert_eq!(wp.altitude, Some(30000.0));
+        assert_eq!(wp.speed, Some(450.0));
+        assert_eq!(parse_waypoint("10"), None);
+        assert_eq!(parse_waypoint("10,5,1,2"), None);
+        assert_eq!(parse_waypoint("10,5@fast"), None);
+    }
+}
@@ -0,0 +1,1047 @@
+use crate::error::*;
+use crate::location::*;
+use crate::movable::*;
+use crate::order::*;
+use crate::parser::*;
+use crate::trigger::*;
+use serde::{Deserialize, Serialize};
+
+/// The formats a scenario can be written in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// The line oriented opcode format
+    Text,
+    Json,
+    Toml,
+}
+
+impl Format {
+    /// Pick a format based on a file's extension.
+    /// Anything that isn't .json or .toml is treated as text
+    pub fn from_filename(filename: &str) -> Self {
+        let ext = std::path::Path::new(filename)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match ext.as_deref() {
+            Some("json") => Format::Json,
+            Some("toml") => Format::Toml,
+            _ => Format::Text,
+        }
+    }
+}
+
+/// A scenario in the structured (JSON or TOML) formats.
+/// This is also what gets written out when saving a scenario
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScenarioDoc {
+    /// The format of times that aren't ISO-8601, like the TimeFormat opcode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_format: Option<String>,
+    /// The UTC offset of times that don't give one, like the TimeZone opcode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_zone: Option<String>,
+    /// What is done with orders beyond what their unit can do,
+    /// like the LimitPolicy opcode. Orders are rejected if it's left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_policy: Option<LimitPolicy>,
+    pub start: String,
+    pub stop: String,
+    #[serde(default)]
+    pub platforms: Vec<PlatformDoc>,
+    #[serde(default)]
+    pub orders: Vec<OrderDoc>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub triggers: Vec<TriggerDoc>,
+}
+
+/// An order given once a condition is met, like the When opcode.
+/// The order's time is a delay after the condition is met
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct TriggerDoc {
+    pub when: Condition,
+    pub order: OrderDoc,
+}
+
+impl TriggerDoc {
+    pub fn new(trigger: &Trigger) -> Self {
+        let mut order = OrderDoc::new(&trigger.order);
+        order.set_time(format!("+{}", format_offset(trigger.delay)));
+        TriggerDoc {
+            when: trigger.condition.clone(),
+            order,
+        }
+    }
+
+    /// Write the trigger as a When opcode
+    pub fn to_line(&self) -> String {
+        format!(
+            "When {} Then {}",
+            self.when.to_tokens(),
+            self.order.to_line()
+        )
+    }
+}
+
+/// A platform to create, named after the Create opcodes
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum PlatformDoc {
+    Cruiser {
+        name: String,
+        id: String,
+        max_speed: f64,
+        max_missiles: i64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        kinematics: Option<Kinematics>,
+    },
+    AircraftCarrier {
+        name: String,
+        id: String,
+        max_speed: f64,
+        max_aircraft: i64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        kinematics: Option<Kinematics>,
+    },
+    Submarine {
+        name: String,
+        id: String,
+        max_speed: f64,
+        max_depth: f64,
+        max_torpedoes: i64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        kinematics: Option<Kinematics>,
+    },
+    Fighter {
+        name: String,
+        id: String,
+        ship_id: String,
+        max_speed: f64,
+        max_ceiling: f64,
+        max_bombs: i64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        kinematics: Option<Kinematics>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fuel: Option<Fuel>,
+    },
+    Helicopter {
+        name: String,
+        id: String,
+        ship_id: String,
+        max_speed: f64,
+        max_ceiling: f64,
+        max_torpedoes: i64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        kinematics: Option<Kinematics>,
+    },
+}
+
+/// A timed order, named after the order opcodes.
+/// Fields left out of a change order are left unchanged
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum OrderDoc {
+    DeployShip {
+        time: String,
+        id: String,
+        x: f64,
+        y: f64,
+        heading: f64,
+        speed: f64,
+    },
+    DeployAircraft {
+        time: String,
+        id: String,
+        heading: f64,
+        speed: f64,
+        altitude: f64,
+    },
+    ChangeShipOrders {
+        time: String,
+        id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        heading: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        speed: Option<f64>,
+    },
+    ChangeAircraftOrders {
+        time: String,
+        id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        heading: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        speed: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        altitude: Option<f64>,
+    },
+    LandAircraft {
+        time: String,
+        id: String,
+        ship_id: String,
+    },
+    FollowRoute {
+        time: String,
+        id: String,
+        waypoints: Vec<Waypoint>,
+    },
+    Patrol {
+        time: String,
+        id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        speed: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        altitude: Option<f64>,
+        pattern: Pattern,
+    },
+    TakeStation {
+        time: String,
+        id: String,
+        guide: String,
+        bearing: f64,
+        range: f64,
+    },
+    Intercept {
+        time: String,
+        id: String,
+        target: String,
+        #[serde(default = "default_intercept_range")]
+        range: f64,
+    },
+    CancelOrders {
+        time: String,
+        id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        order: Option<OrderId>,
+    },
+    ChangeDepth {
+        time: String,
+        id: String,
+        depth: f64,
+    },
+    Hover {
+        time: String,
+        id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        altitude: Option<f64>,
+    },
+    /// An order that isn't built in, read back by the opcode registered for it
+    Custom {
+        opcode: String,
+        time: String,
+        id: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        args: Vec<String>,
+    },
+}
+
+fn default_intercept_range() -> f64 {
+    DEFAULT_INTERCEPT_RANGE
+}
+
+/// Parse a time from a structured scenario
+pub fn parse_time(
+    file: &str,
+    what: &str,
+    time: &str,
+    format: &TimeFormat,
+) -> Result<TimeSpec, ScenarioError> {
+    TimeSpec::parse(time, format).ok_or_else(|| ScenarioError {
+        file: String::from(file),
+        line: 0,
+        column: 0,
+        opcode: String::from(what),
+        token: String::from(time),
+        kind: ErrorKind::InvalidField(TimeSpec::expected(format)),
+    })
+}
+
+/// -1 tells a change order to leave a value as it is
+fn or_unchanged(value: Option<f64>) -> f64 {
+    value.unwrap_or(-1.0)
+}
+
+fn if_changed(value: f64) -> Option<f64> {
+    if value == -1.0 {
+        return None;
+    }
+    Some(value)
+}
+
+/// Write a value in the text format, where -1 means unchanged
+fn text_value(value: Option<f64>) -> String {
+    or_unchanged(value).to_string()
+}
+
+/// Write a waypoint as a single x,y[,altitude][@speed] token
+pub fn waypoint_token(wp: &Waypoint) -> String {
+    let mut token = format!("{},{}", wp.x, wp.y);
+    if let Some(alt) = wp.altitude {
+        token.push_str(format!(",{}", alt).as_str());
+    }
+    if let Some(speed) = wp.speed {
+        token.push_str(format!("@{}", speed).as_str());
+    }
+    token
+}
+
+/// Write a patrol pattern as its shape followed by its points and sizes
+pub fn pattern_tokens(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Racetrack { x1, y1, x2, y2 } => format!("Racetrack {},{} {},{}", x1, y1, x2, y2),
+        Pattern::Orbit { x, y, radius } => format!("Orbit {},{} {}", x, y, radius),
+        Pattern::Box {
+            x,
+            y,
+            width,
+            height,
+        } => format!("Box {},{} {} {}", x, y, width, height),
+    }
+}
+
+/// Times are always written as ISO-8601 so they don't depend on
+/// the TimeFormat and TimeZone they were read with
+pub fn format_time(t: Timestamp) -> String {
+    format_iso8601(t)
+}
+
+impl ScenarioDoc {
+    /// The time format given by the time_format and time_zone fields
+    pub fn get_time_format(&self, file: &str) -> Result<TimeFormat, ScenarioError> {
+        let mut format = TimeFormat::default();
+        if let Some(f) = &self.time_format {
+            format.format = f.clone();
+        }
+        if let Some(z) = &self.time_zone {
+            format.zone = parse_zone(z.as_str()).ok_or_else(|| ScenarioError {
+                file: String::from(file),
+                line: 0,
+                column: 0,
+                opcode: String::from("time_zone"),
+                token: z.clone(),
+                kind: ErrorKind::InvalidField(String::from(ZONE_EXPECTED)),
+            })?;
+        }
+        Ok(format)
+    }
+
+    pub fn from_json(text: &str, file: &str) -> Result<Self, ScenarioError> {
+        serde_json::from_str(text).map_err(|e| ScenarioError {
+            file: String::from(file),
+            line: e.line(),
+            column: e.column(),
+            opcode: String::new(),
+            token: String::new(),
+            kind: ErrorKind::Syntax(e.to_string()),
+        })
+    }
+
+    pub fn from_toml(text: &str, file: &str) -> Result<Self, ScenarioError> {
+        toml::from_str(text).map_err(|e| {
+            // toml reports zero based positions
+            let (line, column) = match e.line_col() {
+                Some((l, c)) => (l + 1, c + 1),
+                None => (0, 0),
+            };
+            ScenarioError {
+                file: String::from(file),
+                line,
+                column,
+                opcode: String::new(),
+                token: String::new(),
+                kind: ErrorKind::Syntax(e.to_string()),
+            }
+        })
+    }
+}
+
+impl ScenarioDoc {
+    /// Write the scenario out in the given format
+    pub fn write(&self, format: Format) -> String {
+        match format {
+            Format::Text => self.to_text(),
+            Format::Json => self.to_json(),
+            Format::Toml => self.to_toml(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut text = serde_json::to_string_pretty(self).unwrap();
+        text.push('\n');
+        text
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).unwrap()
+    }
+
+    /// Write the scenario as canonical opcode text: platforms first,
+    /// then the sim window with the orders in execution order
+    pub fn to_text(&self) -> String {
+        let mut lines = Vec::new();
+        if let Some(policy) = self.limit_policy {
+            lines.push(format!("LimitPolicy {:?}", policy));
+        }
+        for p in self.platforms.iter() {
+            lines.push(p.to_line());
+        }
+        lines.push(format!("StartSim {}", self.start));
+        for o in self.orders.iter() {
+            lines.push(o.to_line());
+        }
+        for t in self.triggers.iter() {
+            lines.push(t.to_line());
+        }
+        lines.push(format!("StopSim {}", self.stop));
+        let mut text = lines.join("\n");
+        text.push('\n');
+        text
+    }
+}
+
+impl PlatformDoc {
+    /// Write the platform as a Create opcode
+    pub fn to_line(&self) -> String {
+        match self {
+            PlatformDoc::Cruiser {
+                name,
+                id,
+                max_speed,
+                max_missiles,
+                kinematics,
+            } => format!(
+                "CreateCruiser {} {} {} {}{}",
+                name,
+                id,
+                max_speed,
+                max_missiles,
+                kinematics_tokens(kinematics, false)
+            ),
+            PlatformDoc::AircraftCarrier {
+                name,
+                id,
+                max_speed,
+                max_aircraft,
+                kinematics,
+            } => format!(
+                "CreateAircraftCarrier {} {} {} {}{}",
+                name,
+                id,
+                max_speed,
+                max_aircraft,
+                kinematics_tokens(kinematics, false)
+            ),
+            PlatformDoc::Submarine {
+                name,
+                id,
+                max_speed,
+                max_depth,
+                max_torpedoes,
+                kinematics,
+            } => format!(
+                "CreateSubmarine {} {} {} {} {}{}",
+                name,
+                id,
+                max_speed,
+                max_depth,
+                max_torpedoes,
+                kinematics_tokens(kinematics, true)
+            ),
+            PlatformDoc::Fighter {
+                name,
+                id,
+                ship_id,
+                max_speed,
+                max_ceiling,
+                max_bombs,
+                kinematics,
+                fuel,
+            } => {
+                // the fuel comes after the kinematics, so they have to be given too
+                let kinematics = match fuel {
+                    Some(_) => kinematics.or(Some(Kinematics::default_for(MovableKind::Fighter))),
+                    None => *kinematics,
+                };
+                format!(
+                    "CreateFighter {} {} {} {} {} {}{}{}",
+                    name,
+                    id,
+                    ship_id,
+                    max_speed,
+                    max_ceiling,
+                    max_bombs,
+                    kinematics_tokens(&kinematics, true),
+                    fuel_tokens(fuel)
+                )
+            }
+            PlatformDoc::Helicopter {
+                name,
+                id,
+                ship_id,
+                max_speed,
+                max_ceiling,
+                max_torpedoes,
+                kinematics,
+            } => format!(
+                "CreateHelicopter {} {} {} {} {} {}{}",
+                name,
+                id,
+                ship_id,
+                max_speed,
+                max_ceiling,
+                max_torpedoes,
+                kinematics_tokens(kinematics, true)
+            ),
+        }
+    }
+
+    pub fn get_id(&self) -> String {
+        match self {
+            PlatformDoc::Cruiser { id, .. } => id.clone(),
+            PlatformDoc::AircraftCarrier { id, .. } => id.clone(),
+            PlatformDoc::Submarine { id, .. } => id.clone(),
+            PlatformDoc::Fighter { id, .. } => id.clone(),
+            PlatformDoc::Helicopter { id, .. } => id.clone(),
+        }
+    }
+
+    pub fn to_movable(&self) -> Box<dyn Movable> {
+        match self {
+            PlatformDoc::Cruiser {
+                name,
+                id,
+                max_speed,
+                max_missiles,
+                kinematics,
+            } => Box::new(
+                Cruiser::new(name.clone(), id.clone(), *max_speed, *max_missiles)
+                    .with_kinematics(*kinematics),
+            ),
+            PlatformDoc::AircraftCarrier {
+                name,
+                id,
+                max_speed,
+                max_aircraft,
+                kinematics,
+            } => Box::new(
+                Carrier::new(name.clone(), id.clone(), *max_speed, *max_aircraft)
+                    .with_kinematics(*kinematics),
+            ),
+            PlatformDoc::Submarine {
+                name,
+                id,
+                max_speed,
+                max_depth,
+                max_torpedoes,
+                kinematics,
+            } => Box::new(
+                Submarine::new(
+                    name.clone(),
+                    id.clone(),
+                    *max_speed,
+                    *max_depth,
+                    *max_torpedoes,
+                )
+                .with_kinematics(*kinematics),
+            ),
+            PlatformDoc::Fighter {
+                name,
+                id,
+                ship_id,
+                max_speed,
+                max_ceiling,
+                max_bombs,
+                kinematics,
+                fuel,
+            } => Box::new(
+                Fighter::new(
+                    name.clone(),
+                    id.clone(),
+                    *max_speed,
+                    ship_id.clone(),
+                    *max_ceiling,
+                    *max_bombs,
+                )
+                .with_kinematics(*kinematics)
+                .with_fuel(*fuel),
+            ),
+            PlatformDoc::Helicopter {
+                name,
+                id,
+                ship_id,
+                max_speed,
+                max_ceiling,
+                max_torpedoes,
+                kinematics,
+            } => Box::new(
+                Helicopter::new(
+                    name.clone(),
+                    id.clone(),
+                    *max_speed,
+                    ship_id.clone(),
+                    *max_ceiling,
+                    *max_torpedoes,
+                )
+                .with_kinematics(*kinematics),
+            ),
+        }
+    }
+}
+
+/// The turn rate, acceleration and climb rate a Create opcode ends with
+/// when they were overridden. Surface ships don't climb, so leave it out
+fn kinematics_tokens(kinematics: &Option<Kinematics>, climbs: bool) -> String {
+    match kinematics {
+        None => String::new(),
+        Some(k) if climbs => format!(" {} {} {}", k.turn_rate, k.acceleration, k.climb_rate),
+        Some(k) => format!(" {} {}", k.turn_rate, k.acceleration),
+    }
+}
+
+/// The fuel capacity, max burn and bingo fuel a CreateFighter opcode
+/// ends with when they were overridden
+fn fuel_tokens(fuel: &Option<Fuel>) -> String {
+    match fuel {
+        None => String::new(),
+        Some(f) => format!(" {} {} {}", f.capacity, f.max_burn, f.bingo),
+    }
+}
+
+impl OrderDoc {
+    /// Describe an order in the structured format
+    pub fn new(order: &Order) -> Self {
+        match order {
+            Order::DeployShipOrder(o) => OrderDoc::DeployShip {
+                time: format_time(o.extime),
+                id: o.id.clone(),
+                x: o.start_x,
+                y: o.start_y,
+                heading: o.heading,
+                speed: o.speed,
+            },
+            Order::DeployAircraftOrder(o) => OrderDoc::DeployAircraft {
+                time: format_time(o.extime),
+                id: o.id.clone(),
+                heading: o.heading,
+                speed: o.speed,
+                altitude: o.altitude,
+            },
+            Order::ChangeShipOrder(o) => OrderDoc::ChangeShipOrders {
+                time: format_time(o.extime),
+                id: o.id.clone(),
+                heading: if_changed(o.heading),
+                speed: if_changed(o.speed),
+            },
+            Order::ChangeAircraftOrder(o) => OrderDoc::ChangeAircraftOrders {
+                time: format_time(o.extime),
+                id: o.id.clone(),
+                heading: if_changed(o.heading),
+                speed: if_changed(o.speed),
+                altitude: if_changed(o.altitude),
+            },
+            Order::LandAircraftOrder(o) => OrderDoc::LandAircraft {
+                time: format_time(o.extime),
+                id: o.id.clone(),
+                ship_id: o.ship_id.clone(),
+            },
+            Order::FollowRouteOrder(o) => OrderDoc::FollowRoute {
+                time: format_time(o.extime),
+                id: o.id.clone(),
+                waypoints: o.waypoints.clone(),
+            },
+            Order::PatrolOrder(o) => OrderDoc::Patrol {
+                time: format_time(o.extime),
+                id: o.id.clone(),
+                speed: if_changed(o.speed),
+                altitude: if_changed(o.altitude),
+                pattern: o.pattern.clone(),
+            },
+            Order::TakeStationOrder(o) => OrderDoc::TakeStation {
+                time: format_time(o.extime),
+                id: o.id.clone(),
+                guide: o.guide_id.clone(),
+                bearing: o.bearing,
+                range: o.range,
+            },
+            Order::InterceptOrder(o) => OrderDoc::Intercept {
+                time: format_time(o.extime),
+                id: o.id.clone(),
+                target: o.target_id.clone(),
+                range: o.range,
+            },
+            Order::CancelOrdersOrder(o) => OrderDoc::CancelOrders {
+                time: format_time(o.extime),
+                id: o.id.clone(),
+                order: o.order_id,
+            },
+            Order::ChangeDepthOrder(o) => OrderDoc::ChangeDepth {
+                time: format_time(o.extime),
+                id: o.id.clone(),
+                depth: o.depth,
+            },
+            Order::HoverOrder(o) => OrderDoc::Hover {
+                time: format_time(o.extime),
+                id: o.id.clone(),
+                altitude: if_changed(o.altitude),
+            },
+            Order::CustomOrder(o) => OrderDoc::Custom {
+                opcode: String::from(o.get_opcode()),
+                time: format_time(o.get_extime()),
+                id: o.get_id(),
+                args: o.get_args(),
+            },
+        }
+    }
+
+    /// Write the order as an opcode
+    pub fn to_line(&self) -> String {
+        match self {
+            OrderDoc::DeployShip {
+                time,
+                id,
+                x,
+                y,
+                heading,
+                speed,
+            } => format!(
+                "DeployShip {} {} {} {} {} {}",
+                time, id, x, y, heading, speed
+            ),
+            OrderDoc::DeployAircraft {
+                time,
+                id,
+                heading,
+                speed,
+                altitude,
+            } => format!(
+                "DeployAircraft {} {} {} {} {}",
+                time, id, heading, speed, altitude
+            ),
+            OrderDoc::ChangeShipOrders {
+                time,
+                id,
+                heading,
+                speed,
+            } => format!(
+                "ChangeShipOrders {} {} {} {}",
+                time,
+                id,
+                text_value(*heading),
+                text_value(*speed)
+            ),
+            OrderDoc::ChangeAircraftOrders {
+                time,
+                id,
+                heading,
+                speed,
+                altitude,
+            } => format!(
+                "ChangeAircraftOrders {} {} {} {} {}",
+                time,
+                id,
+                text_value(*heading),
+                text_value(*speed),
+                text_value(*altitude)
+            ),
+            // the text format names the ship before the aircraft
+            OrderDoc::LandAircraft { time, id, ship_id } => {
+                format!("LandAircraft {} {} {}", time, ship_id, id)
+            }
+            OrderDoc::FollowRoute {
+                time,
+                id,
+                waypoints,
+            } => {
+                let tokens: Vec<String> = waypoints.iter().map(waypoint_token).collect();
+                format!("FollowRoute {} {} {}", time, id, tokens.join(" "))
+            }
+            OrderDoc::Patrol {
+                time,
+                id,
+                speed,
+                altitude,
+                pattern,
+            } => format!(
+                "Patrol {} {} {} {} {}",
+                time,
+                id,
+                text_value(*speed),
+                text_value(*altitude),
+                pattern_tokens(pattern)
+            ),
+            OrderDoc::TakeStation {
+                time,
+                id,
+                guide,
+                bearing,
+                range,
+            } => format!(
+                "TakeStation {} {} {} {} {}",
+                time, id, guide, bearing, range
+            ),
+            OrderDoc::Intercept {
+                time,
+                id,
+                target,
+                range,
+            } => format!("Intercept {} {} {} {}", time, id, target, range),
+            OrderDoc::CancelOrders { time, id, order } => match order {
+                Some(n) => format!("CancelOrders {} {} {}", time, id, n),
+                None => format!("CancelOrders {} {}", time, id),
+            },
+            OrderDoc::ChangeDepth { time, id, depth } => {
+                format!("ChangeDepth {} {} {}", time, id, depth)
+            }
+            OrderDoc::Hover { time, id, altitude } => match altitude {
+                Some(altitude) => format!("Hover {} {} {}", time, id, altitude),
+                None => format!("Hover {} {}", time, id),
+            },
+            OrderDoc::Custom {
+                opcode,
+                time,
+                id,
+                args,
+            } => {
+                let mut tokens = vec![opcode.clone(), time.clone(), id.clone()];
+                tokens.extend(args.iter().cloned());
+                tokens.join(" ")
+            }
+        }
+    }
+
+    pub fn get_opcode(&self) -> &str {
+        match self {
+            OrderDoc::DeployShip { .. } => "DeployShip",
+            OrderDoc::DeployAircraft { .. } => "DeployAircraft",
+            OrderDoc::ChangeShipOrders { .. } => "ChangeShipOrders",
+            OrderDoc::ChangeAircraftOrders { .. } => "ChangeAircraftOrders",
+            OrderDoc::LandAircraft { .. } => "LandAircraft",
+            OrderDoc::FollowRoute { .. } => "FollowRoute",
+            OrderDoc::Patrol { .. } => "Patrol",
+            OrderDoc::TakeStation { .. } => "TakeStation",
+            OrderDoc::Intercept { .. } => "Intercept",
+            OrderDoc::CancelOrders { .. } => "CancelOrders",
+            OrderDoc::ChangeDepth { .. } => "ChangeDepth",
+            OrderDoc::Hover { .. } => "Hover",
+            OrderDoc::Custom { opcode, .. } => opcode,
+        }
+    }
+
+    pub fn get_id(&self) -> String {
+        match self {
+            OrderDoc::DeployShip { id, .. } => id.clone(),
+            OrderDoc::DeployAircraft { id, .. } => id.clone(),
+            OrderDoc::ChangeShipOrders { id, .. } => id.clone(),
+            OrderDoc::ChangeAircraftOrders { id, .. } => id.clone(),
+            OrderDoc::LandAircraft { id, .. } => id.clone(),
+            OrderDoc::FollowRoute { id, .. } => id.clone(),
+            OrderDoc::Patrol { id, .. } => id.clone(),
+            OrderDoc::TakeStation { id, .. } => id.clone(),
+            OrderDoc::Intercept { id, .. } => id.clone(),
+            OrderDoc::CancelOrders { id, .. } => id.clone(),
+            OrderDoc::ChangeDepth { id, .. } => id.clone(),
+            OrderDoc::Hover { id, .. } => id.clone(),
+            OrderDoc::Custom { id, .. } => id.clone(),
+        }
+    }
+
+    pub fn get_time(&self) -> &str {
+        match self {
+            OrderDoc::DeployShip { time, .. } => time,
+            OrderDoc::DeployAircraft { time, .. } => time,
+            OrderDoc::ChangeShipOrders { time, .. } => time,
+            OrderDoc::ChangeAircraftOrders { time, .. } => time,
+            OrderDoc::LandAircraft { time, .. } => time,
+            OrderDoc::FollowRoute { time, .. } => time,
+            OrderDoc::Patrol { time, .. } => time,
+            OrderDoc::TakeStation { time, .. } => time,
+            OrderDoc::Intercept { time, .. } => time,
+            OrderDoc::CancelOrders { time, .. } => time,
+            OrderDoc::ChangeDepth { time, .. } => time,
+            OrderDoc::Hover { time, .. } => time,
+            OrderDoc::Custom { time, .. } => time,
+        }
+    }
+
+    pub fn set_time(&mut self, t: String) {
+        match self {
+            OrderDoc::DeployShip { time, .. } => *time = t,
+            OrderDoc::DeployAircraft { time, .. } => *time = t,
+            OrderDoc::ChangeShipOrders { time, .. } => *time = t,
+            OrderDoc::ChangeAircraftOrders { time, .. } => *time = t,
+            OrderDoc::LandAircraft { time, .. } => *time = t,
+            OrderDoc::FollowRoute { time, .. } => *time = t,
+            OrderDoc::Patrol { time, .. } => *time = t,
+            OrderDoc::TakeStation { time, .. } => *time = t,
+            OrderDoc::Intercept { time, .. } => *time = t,
+            OrderDoc::CancelOrders { time, .. } => *time = t,
+            OrderDoc::ChangeDepth { time, .. } => *time = t,
+            OrderDoc::Hover { time, .. } => *time = t,
+            OrderDoc::Custom { time, .. } => *time = t,
+        }
+    }
+
+    /// Build the order, to be executed at `atm`. Orders that
+    /// aren't built in need their opcode's parser, so give None
+    pub fn to_order(&self, atm: Timestamp) -> Option<Order> {
+        let order = match self {
+            OrderDoc::DeployShip {
+                time: _,
+                id,
+                x,
+                y,
+                heading,
+                speed,
+            } => Order::DeployShipOrder(DeployShip::new(atm, id.clone(), *x, *y, *heading, *speed)),
+            OrderDoc::DeployAircraft {
+                time: _,
+                id,
+                heading,
+                speed,
+                altitude,
+            } => Order::DeployAircraftOrder(DeployAircraft::new(
+                atm,
+                id.clone(),
+                *heading,
+                *speed,
+                *altitude,
+            )),
+            OrderDoc::ChangeShipOrders {
+                time: _,
+                id,
+                heading,
+                speed,
+            } => Order::ChangeShipOrder(ChangeShip::new(
+                atm,
+                id.clone(),
+                or_unchanged(*heading),
+                or_unchanged(*speed),
+            )),
+            OrderDoc::ChangeAircraftOrders {
+                time: _,
+                id,
+                heading,
+                speed,
+                altitude,
+            } => Order::ChangeAircraftOrder(ChangeAircraft::new(
+                atm,
+                id.clone(),
+                or_unchanged(*heading),
+                or_unchanged(*speed),
+                or_unchanged(*altitude),
+            )),
+            OrderDoc::LandAircraft { id, ship_id, .. } => {
+                Order::LandAircraftOrder(LandAircraft::new(atm, id.clone(), ship_id.clone()))
+            }
+            OrderDoc::FollowRoute { id, waypoints, .. } => {
+                Order::FollowRouteOrder(FollowRoute::new(atm, id.clone(), waypoints.clone()))
+            }
+            OrderDoc::Patrol {
+                time: _,
+                id,
+                speed,
+                altitude,
+                pattern,
+            } => Order::PatrolOrder(Patrol::new(
+                atm,
+                id.clone(),
+                or_unchanged(*speed),
+                or_unchanged(*altitude),
+                pattern.clone(),
+            )),
+            OrderDoc::TakeStation {
+                time: _,
+                id,
+                guide,
+                bearing,
+                range,
+            } => Order::TakeStationOrder(TakeStation::new(
+                atm,
+                id.clone(),
+                guide.clone(),
+                *bearing,
+                *range,
+            )),
+            OrderDoc::Intercept {
+                time: _,
+                id,
+                target,
+                range,
+            } => Order::InterceptOrder(Intercept::new(atm, id.clone(), target.clone(), *range)),
+            OrderDoc::CancelOrders { time: _, id, order } => {
+                Order::CancelOrdersOrder(CancelOrders::new(atm, id.clone(), *order))
+            }
+            OrderDoc::ChangeDepth { time: _, id, depth } => {
+                Order::ChangeDepthOrder(ChangeDepth::new(atm, id.clone(), *depth))
+            }
+            OrderDoc::Hover {
+                time: _,
+                id,
+                altitude,
+            } => Order::HoverOrder(Hover::new(atm, id.clone(), or_unchanged(*altitude))),
+            OrderDoc::Custom { .. } => return None,
+        };
+        Some(order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_from_filename() {
+        assert_eq!(Format::from_filename("orders/foo.txt"), Format::Text);
+        assert_eq!(Format::from_filename("foo"), Format::Text);
+        assert_eq!(Format::from_filename("foo.json"), Format::Json);
+        assert_eq!(Format::from_filename("dir.d/foo.TOML"), Format::Toml);
+    }
+
+    #[test]
+    fn test_json_syntax_error() {
+        let e = ScenarioDoc::from_json("{\n  \"start\": 12,\n}", "s.json").unwrap_err();
+        assert_eq!(e.file, "s.json");
+        assert_eq!(e.line, 2);
+    }
+
+    #[test]
+    fn test_toml_change_order_defaults() {
+        let doc = ScenarioDoc::from_toml(
+            r#"
+start = "12/14/2015 10:00:00"
+stop = "12/14/2015 11:00:00"
+
+[[orders]]
+type = "ChangeShipOrders"
+time = "12/14/2015 10:10:00"
+id = "CGN-39"
+speed = 20.0
+"#,
+            "s.toml",
+        )
+        .unwrap();
+        let order = doc.orders[0].to_order(Timestamp::default()).unwrap();
+        match order {
+            Order::ChangeShipOrder(o) => {
+                assert_eq!(o.heading, -1.0);
+                assert_eq!(o.speed, 20.0);
+            }
+            _ => panic!("expected a change ship order"),
+        }
+    }
+
+    #[test]
+    fn test_bad_time() {
+        let e = parse_time(
+            "s.json",
+            "LandAircraft",
+            "yesterday",
+            &TimeFormat::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "s.json: LandAircraft: expected date and time as '%m/%d/%Y %H:%M:%S' \
+             or ISO-8601, T+HH:MM:SS or +HH:MM:SS, got 'yesterday'"
+        );
+    }
+}
@@ -1,72 +1,221 @@
+#![allow(clippy::needless_return, clippy::redundant_field_names)]
+
+use crate::error::*;
+use crate::event::*;
 use crate::location::*;
 use crate::movable::*;
+use crate::opcode::*;
 use crate::order::*;
+use crate::order_queue::*;
+use crate::parser::*;
+use crate::scenario::*;
+use crate::trigger::*;
 use chrono::Duration;
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::{self, BufRead, BufReader};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+type NavyMap = HashMap<String, Box<dyn Movable>>;
 
-type OrderQueue = Vec<Box<Order>>;
-type NavyMap = HashMap<String, Box<Movable>>;
+/// How deeply macros may use other macros, which catches recursive macros
+const MAX_MACRO_DEPTH: usize = 16;
 
+/// The most orders a single Repeat can expand into
+const MAX_REPEATS: i64 = 1000;
+
+/// Opcodes that control how a scenario is read rather than describing
+/// platforms or orders. Those are parsed through the OpcodeRegistry
 #[derive(Debug)]
-pub enum Opcode {
+pub enum Directive {
     StartSim,
     StopSim,
     EndSim,
-    CreateCruiser,
-    CreateAircraftCarrier,
-    CreateFighter,
-    DeployShip,
-    DeployAircraft,
-    ChangeShipOrders,
-    ChangeAircraftOrders,
-    LandAircraft,
+    Include,
+    TimeFormat,
+    TimeZone,
+    LimitPolicy,
+    Define,
+    Macro,
+    EndMacro,
+    When,
+    Repeat,
     Invalid,
 }
 
-impl Opcode {
+impl Directive {
     pub fn new(op: &str) -> Self {
         match op {
-            "StartSim" => Opcode::StartSim,
-            "StopSim" => Opcode::StopSim,
-            "EndSim" => Opcode::EndSim,
-            "CreateCruiser" => Opcode::CreateCruiser,
-            "CreateAircraftCarrier" => Opcode::CreateAircraftCarrier,
-            "CreateFighter" => Opcode::CreateFighter,
-            "DeployShip" => Opcode::DeployShip,
-            "DeployAircraft" => Opcode::DeployAircraft,
-            "ChangeShipOrders" => Opcode::ChangeShipOrders,
-            "ChangeAircraftOrders" => Opcode::ChangeAircraftOrders,
-            "LandAircraft" => Opcode::LandAircraft,
-            _ => Opcode::Invalid,
+            "StartSim" => Directive::StartSim,
+            "StopSim" => Directive::StopSim,
+            "EndSim" => Directive::EndSim,
+            "Include" => Directive::Include,
+            "TimeFormat" => Directive::TimeFormat,
+            "TimeZone" => Directive::TimeZone,
+            "LimitPolicy" => Directive::LimitPolicy,
+            "Define" => Directive::Define,
+            "Macro" => Directive::Macro,
+            "EndMacro" => Directive::EndMacro,
+            "When" => Directive::When,
+            "Repeat" => Directive::Repeat,
+            _ => Directive::Invalid,
         }
     }
 }
 
+/// What became of an order, for the order log
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderRecord {
+    /// when the order was executed or cancelled
+    pub t: Timestamp,
+    pub order_id: OrderId,
+    /// the unit the order was for
+    pub id: String,
+    pub opcode: String,
+    pub outcome: OrderOutcome,
+}
+
+impl OrderRecord {
+    fn new(t: Timestamp, order_id: OrderId, order: &Order, outcome: OrderOutcome) -> Self {
+        OrderRecord {
+            t: t,
+            order_id: order_id,
+            id: order.get_id(),
+            opcode: String::from(order.get_opcode()),
+            outcome: outcome,
+        }
+    }
+
+    /// The record as a JSON object, with the outcome split into
+    /// "accepted", "rejected", "clamped" or "cancelled" and the reason why
+    pub fn to_json(&self) -> serde_json::Value {
+        let (outcome, reason) = match &self.outcome {
+            OrderOutcome::Accepted => ("accepted", None),
+            OrderOutcome::Rejected(reason) => ("rejected", Some(reason.to_string())),
+            OrderOutcome::Clamped(exceeded) => {
+                let reasons: Vec<String> = exceeded.iter().map(|r| r.to_string()).collect();
+                ("clamped", Some(reasons.join(", ")))
+            }
+            OrderOutcome::Cancelled(by) => ("cancelled", Some(format!("by #{}", by))),
+        };
+        let mut value = serde_json::json!({
+            "time": format_time(self.t),
+            "order": self.order_id,
+            "id": self.id,
+            "opcode": self.opcode,
+            "outcome": outcome,
+        });
+        if let Some(reason) = reason {
+            value["reason"] = serde_json::Value::String(reason);
+        }
+        return value;
+    }
+}
+
+impl std::fmt::Display for OrderRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} #{} {} {}: {}",
+            self.t, self.order_id, self.opcode, self.id, self.outcome
+        )
+    }
+}
+
+/// An order waiting for a condition rather than a time. Once the
+/// condition is met, its order is queued with the same id
+pub struct PendingTrigger {
+    pub id: OrderId,
+    pub trigger: Trigger,
+    pub origin: Option<Origin>,
+}
+
 pub struct SimManager {
-    start: chrono::NaiveDateTime,
-    stop: chrono::NaiveDateTime,
+    start: Timestamp,
+    stop: Timestamp,
     navy_map: NavyMap,
     order_q: OrderQueue,
+    start_origin: Option<Origin>,
+    stop_origin: Option<Origin>,
+    /// every movable that was created, in order, including
+    /// any that were later replaced by one with the same id
+    movable_origins: Vec<(String, Origin)>,
+    /// the files currently being read, used to detect include cycles
+    include_stack: Vec<PathBuf>,
+    /// the time of the last order read for each unit,
+    /// which +HH:MM:SS times are relative to
+    last_order_times: HashMap<String, Timestamp>,
+    /// how absolute times are written, set by TimeFormat and TimeZone
+    time_format: TimeFormat,
+    /// variables set by Define
+    variables: HashMap<String, String>,
+    /// variables set before reading the scenario, which Define can't change
+    overrides: HashMap<String, String>,
+    macros: HashMap<String, Macro>,
+    /// the opcodes describing platforms and orders
+    opcodes: OpcodeRegistry,
+    /// how many macros are currently being expanded
+    macro_depth: usize,
+    /// everything the units reported while the simulation ran
+    events: Vec<Event>,
+    /// the id the next order queued will get
+    next_order_id: OrderId,
+    /// what became of each order, in the order they were executed
+    order_log: Vec<OrderRecord>,
+    /// orders given by When, in the order they were read
+    triggers: Vec<PendingTrigger>,
+    /// what is done with orders beyond what their unit can do
+    limit_policy: LimitPolicy,
+}
+
+impl Default for SimManager {
+    fn default() -> Self {
+        SimManager::new()
+    }
 }
 
 impl SimManager {
     pub fn new() -> Self {
         SimManager {
-            start: chrono::NaiveDate::from_ymd(2000, 1, 1).and_hms(0, 0, 0),
-            stop: chrono::NaiveDate::from_ymd(2000, 1, 1).and_hms(0, 0, 0),
+            start: timestamp(2000, 1, 1, 0, 0, 0),
+            stop: timestamp(2000, 1, 1, 0, 0, 0),
             navy_map: NavyMap::new(),
             order_q: OrderQueue::new(),
+            start_origin: None,
+            stop_origin: None,
+            movable_origins: Vec::new(),
+            include_stack: Vec::new(),
+            last_order_times: HashMap::new(),
+            time_format: TimeFormat::default(),
+            variables: HashMap::new(),
+            overrides: HashMap::new(),
+            macros: HashMap::new(),
+            opcodes: OpcodeRegistry::new(),
+            macro_depth: 0,
+            events: Vec::new(),
+            next_order_id: 1,
+            order_log: Vec::new(),
+            triggers: Vec::new(),
+            limit_policy: LimitPolicy::default(),
         }
     }
 
-    pub fn get_start(&self) -> chrono::NaiveDateTime {
-        return self.start.clone();
+    pub fn get_start(&self) -> Timestamp {
+        return self.start;
     }
 
-    pub fn get_stop(&self) -> chrono::NaiveDateTime {
-        return self.stop.clone();
+    pub fn get_stop(&self) -> Timestamp {
+        return self.stop;
+    }
+
+    pub fn get_limit_policy(&self) -> LimitPolicy {
+        return self.limit_policy;
+    }
+
+    /// Choose whether orders beyond what their unit can do are
+    /// rejected or clamped. A scenario can also set it with LimitPolicy
+    pub fn set_limit_policy(&mut self, policy: LimitPolicy) {
+        self.limit_policy = policy;
     }
 
     pub fn print(&self) {
@@ -77,7 +226,17 @@ impl SimManager {
         println!(" Order Queue");
         println!("=============");
         for o in self.order_q.iter() {
-            o.print();
+            print!("#{} ", o.id);
+            o.order.print();
+        }
+        for t in self.triggers.iter() {
+            println!(
+                "#{} id: {} when {} after {}",
+                t.id,
+                t.trigger.order.get_id(),
+                t.trigger.condition,
+                format_offset(t.trigger.delay)
+            );
         }
         println!("=============");
     }
@@ -91,6 +250,121 @@ impl SimManager {
         println!("==========");
     }
 
+    /// Every order still waiting to be executed, in the order they will be
+    pub fn get_orders(&self) -> Vec<&QueuedOrder> {
+        return self.order_q.iter().collect();
+    }
+
+    /// The orders still waiting to be executed for unit `id`
+    /// at or after time `t`
+    pub fn get_pending_orders(&self, id: &str, t: Timestamp) -> Vec<&QueuedOrder> {
+        return self
+            .order_q
+            .iter()
+            .filter(|o| o.order.get_id() == id && o.order.get_extime() >= t)
+            .collect();
+    }
+
+    /// Queue an order, which may be given while the simulation is
+    /// running. It is executed after any order of the same priority
+    /// already queued for the same time
+    pub fn issue_order(&mut self, order: Order) -> OrderId {
+        let id = self.take_order_id();
+        self.order_q.push(QueuedOrder {
+            id: id,
+            order: order,
+            origin: None,
+        });
+        return id;
+    }
+
+    /// Cancel a pending order, returning it if it hadn't been executed yet
+    pub fn cancel_order(&mut self, order_id: OrderId) -> Option<QueuedOrder> {
+        return self.order_q.remove_if(|o| o.id == order_id).pop();
+    }
+
+    /// Cancel every pending order for unit `id`, returning the orders
+    /// cancelled in the order they would have been executed
+    pub fn cancel_orders(&mut self, id: &str) -> Vec<QueuedOrder> {
+        let mut cancelled = self.order_q.remove_if(|o| o.order.get_id() == id);
+        cancelled.sort();
+        return cancelled;
+    }
+
+    /// Every order still waiting for its condition to be met
+    pub fn get_triggers(&self) -> &[PendingTrigger] {
+        return &self.triggers;
+    }
+
+    /// Give an order once a condition is met rather than at a set time
+    pub fn add_trigger(&mut self, trigger: Trigger) -> OrderId {
+        return self.push_trigger(trigger, None);
+    }
+
+    /// Cancel an order that is waiting for its condition,
+    /// returning it if the condition hadn't been met yet
+    pub fn cancel_trigger(&mut self, order_id: OrderId) -> Option<PendingTrigger> {
+        let i = self.triggers.iter().position(|t| t.id == order_id)?;
+        return Some(self.triggers.remove(i));
+    }
+
+    /// Cancel every order for unit `id` that is waiting for its
+    /// condition, returning the ones cancelled
+    pub fn cancel_triggers(&mut self, id: &str) -> Vec<PendingTrigger> {
+        let (cancelled, kept) = std::mem::take(&mut self.triggers)
+            .into_iter()
+            .partition(|t| t.trigger.order.get_id() == id);
+        self.triggers = kept;
+        return cancelled;
+    }
+
+    /// A unit by id, for reading back what it is doing and any
+    /// state custom orders have kept on it
+    pub fn get_unit(&self, id: &str) -> Option<&dyn Movable> {
+        return self.navy_map.get(id).map(|m| m.as_ref());
+    }
+
+    pub fn get_events(&self) -> &[Event] {
+        return &self.events;
+    }
+
+    pub fn print_events(&self) {
+        println!(" Events");
+        println!("========");
+        for e in self.events.iter() {
+            println!("{}", e);
+        }
+        println!("========");
+    }
+
+    pub fn get_order_log(&self) -> &[OrderRecord] {
+        return &self.order_log;
+    }
+
+    pub fn print_order_log(&self) {
+        println!(" Order Log");
+        println!("===========");
+        for r in self.order_log.iter() {
+            println!("{}", r);
+        }
+        println!("===========");
+    }
+
+    /// Write the order log to a file, as JSON if the file's
+    /// extension is .json and one record per line otherwise
+    pub fn save_order_log(&self, filename: &str) -> std::io::Result<()> {
+        let text = if Format::from_filename(filename) == Format::Json {
+            let records: Vec<serde_json::Value> =
+                self.order_log.iter().map(|r| r.to_json()).collect();
+            let mut text = serde_json::to_string_pretty(&records).unwrap();
+            text.push('\n');
+            text
+        } else {
+            self.order_log.iter().map(|r| format!("{}\n", r)).collect()
+        };
+        return std::fs::write(filename, text);
+    }
+
     /// Print the history of each movable in the navy map
     pub fn print_history(&self) {
         for (_, val) in self.navy_map.iter() {
@@ -99,228 +373,1068 @@ impl SimManager {
         }
     }
 
-    /// Search for a movable in the navy map
-    fn find_movable(&self, id: String) -> &Box<Movable> {
-        return self.navy_map.get(id.as_str()).unwrap();
+    /// Initialize the simulation manager from a scenario file.
+    /// The format is chosen from the file's extension
+    pub fn init(&mut self, filename: &str) -> Result<(), ScenarioError> {
+        // open the file. If it fails to open, report which file it was
+        let f = match File::open(filename) {
+            Ok(file) => file,
+            Err(error) => return Err(ScenarioError::io(filename, error)),
+        };
+        let format = Format::from_filename(filename);
+        if let Ok(path) = std::fs::canonicalize(filename) {
+            self.include_stack.push(path);
+        }
+        let result = self.init_with_format(BufReader::new(f), filename, format);
+        self.include_stack.clear();
+        return result;
     }
 
-    /// Initialize the simulation manager
-    pub fn init(&mut self, filename: &str) -> bool {
-        // open the file and read line by line
-        // if the line starts with a # or is blank, skip
+    /// Initialize the simulation manager from a scenario held in a string
+    pub fn init_from_str(&mut self, scenario: &str) -> Result<(), ScenarioError> {
+        return self.init_from_reader(scenario.as_bytes(), "<string>");
+    }
 
-        let DATETIME_FORMAT = "%m/%d/%Y %H:%M:%S";
+    /// Initialize the simulation manager from any buffered reader.
+    /// `name` is used to identify the scenario in error messages
+    pub fn init_from_reader<R: BufRead>(
+        &mut self,
+        reader: R,
+        name: &str,
+    ) -> Result<(), ScenarioError> {
+        return self.init_with_format(reader, name, Format::Text);
+    }
 
-        // open the file. If it fails to open, complain and return false
-        let f = File::open(filename);
-        let f = match f {
-            Ok(file) => file,
-            Err(_error) => {
-                panic!("There was a problem opening the file: {}", filename);
+    /// Initialize the simulation manager from a scenario in the given format
+    pub fn init_with_format<R: BufRead>(
+        &mut self,
+        mut reader: R,
+        name: &str,
+        format: Format,
+    ) -> Result<(), ScenarioError> {
+        match format {
+            Format::Text => self.parse_text(reader, name)?,
+            Format::Json | Format::Toml => {
+                let mut text = String::new();
+                if let Err(error) = reader.read_to_string(&mut text) {
+                    return Err(ScenarioError::io(name, error));
+                }
+                let doc = if format == Format::Json {
+                    ScenarioDoc::from_json(text.as_str(), name)?
+                } else {
+                    ScenarioDoc::from_toml(text.as_str(), name)?
+                };
+                self.load_doc(&doc, name)?;
             }
-        };
+        }
+        return Ok(());
+    }
 
-        let buffered = BufReader::new(f);
+    /// Set a variable before reading a scenario. It is used in place of
+    /// any value the scenario gives it with Define, so the same scenario
+    /// can be run with different values
+    pub fn define(&mut self, name: &str, value: &str) {
+        self.overrides
+            .insert(String::from(name), String::from(value));
+    }
 
-        for line in buffered
-            .lines()
-            .map(|l| l.unwrap())
-            .filter(|l| l.len() != 0 && !l.starts_with("#"))
+    /// Add an opcode for describing platforms or orders, replacing
+    /// any built-in opcode with the same name
+    pub fn register_opcode<F>(&mut self, name: &str, parser: F)
+    where
+        F: Fn(&Line, &mut ParseContext) -> Result<Parsed, ScenarioError> + 'static,
+    {
+        self.opcodes.register(name, parser);
+    }
+
+    fn parse_text<R: BufRead>(&mut self, reader: R, name: &str) -> Result<(), ScenarioError> {
+        // the Macro line and the macro being recorded, until EndMacro
+        let mut recording: Option<(Line, Macro)> = None;
+        for (i, text) in reader.lines().enumerate() {
+            let text = match text {
+                Ok(t) => t,
+                Err(error) => return Err(ScenarioError::io(name, error)),
+            };
+            // blank lines and comments are skipped
+            let mut line = match Line::new(name, i + 1, text.as_str()) {
+                Some(l) => l,
+                None => continue,
+            };
+            let opcode = Directive::new(line.get_opcode());
+            if let Some((start, mut m)) = recording.take() {
+                match opcode {
+                    Directive::EndMacro => {
+                        self.macros.insert(start.string(0, "macro name")?, m);
+                    }
+                    Directive::Macro => {
+                        return Err(line.opcode_error(ErrorKind::Macro(String::from(
+                            "macros can't be defined inside a macro",
+                        ))))
+                    }
+                    _ => {
+                        m.body.push((line.number, text.clone()));
+                        recording = Some((start, m));
+                    }
+                }
+                continue;
+            }
+            if let Directive::Macro = opcode {
+                let m = self.begin_macro(&line)?;
+                recording = Some((line, m));
+                continue;
+            }
+            self.substitute(&mut line, None)?;
+            self.parse_line(&line)?;
+        }
+        if let Some((start, _)) = recording {
+            return Err(start.error(
+                0,
+                ErrorKind::Macro(String::from("no EndMacro before the end of the file")),
+            ));
+        }
+        return Ok(());
+    }
+
+    /// Replace variable references in a line. Macro parameters are looked
+    /// up first, then variables set with define(), then ones set by Define
+    fn substitute(
+        &self,
+        line: &mut Line,
+        params: Option<&HashMap<String, String>>,
+    ) -> Result<(), ScenarioError> {
+        return line.substitute(|name| {
+            params
+                .and_then(|p| p.get(name))
+                .or_else(|| self.overrides.get(name))
+                .or_else(|| self.variables.get(name))
+                .cloned()
+        });
+    }
+
+    /// Check a Macro line, returning an empty macro to record the body into
+    fn begin_macro(&self, line: &Line) -> Result<Macro, ScenarioError> {
+        let name = line.string(0, "macro name")?;
+        if !is_name(name.as_str()) {
+            return Err(line.error(0, ErrorKind::InvalidField(String::from("macro name"))));
+        }
+        if !matches!(Directive::new(name.as_str()), Directive::Invalid)
+            || self.opcodes.contains(name.as_str())
         {
-            // println!("{:?}", line);
-
-            // split line on whitespace
-            let mut tokens: Vec<&str> = line.split_whitespace().collect();
-            let opcode = Opcode::new(&tokens.remove(0));
-            // for s in &tokens {
-            // 	println!("s: {}", s);
-            // }
-            // println!("opcode: {:?}", &opcode);
-
-            match opcode {
-                Opcode::StartSim => {
-                    let date_time_str = format!("{} {}", tokens[0], tokens[1]);
-                    let parsed = chrono::NaiveDateTime::parse_from_str(
-                        date_time_str.as_str(),
-                        DATETIME_FORMAT,
-                    );
-                    match parsed {
-                        Ok(dt) => self.start = dt,
-                        Err(_error) => return false,
-                    };
+            return Err(line.error(
+                0,
+                ErrorKind::Macro(format!("'{}' is already an opcode", name)),
+            ));
+        }
+        let mut params = Vec::new();
+        for idx in 1..line.args.len() {
+            let param = line.string(idx, "parameter name")?;
+            if !is_name(param.as_str()) {
+                return Err(
+                    line.error(idx, ErrorKind::InvalidField(String::from("parameter name")))
+                );
+            }
+            params.push(param);
+        }
+        return Ok(Macro {
+            params: params,
+            file: line.file.clone(),
+            body: Vec::new(),
+        });
+    }
+
+    /// Parse the body of a macro in place of a line that uses it
+    fn expand_macro(&mut self, line: &Line, m: &Macro) -> Result<(), ScenarioError> {
+        if self.macro_depth >= MAX_MACRO_DEPTH {
+            return Err(line.opcode_error(ErrorKind::Macro(format!(
+                "macros used more than {} deep, is '{}' recursive?",
+                MAX_MACRO_DEPTH,
+                line.get_opcode()
+            ))));
+        }
+        let mut params = HashMap::new();
+        for (idx, param) in m.params.iter().enumerate() {
+            params.insert(param.clone(), line.string(idx, param.as_str())?);
+        }
+        if line.args.len() > m.params.len() {
+            return Err(line.error(
+                m.params.len(),
+                ErrorKind::InvalidField(format!("{} arguments", m.params.len())),
+            ));
+        }
+
+        self.macro_depth += 1;
+        let mut result = Ok(());
+        for (number, text) in m.body.iter() {
+            let mut body_line = match Line::new(m.file.as_str(), *number, text.as_str()) {
+                Some(l) => l,
+                None => continue,
+            };
+            result = self
+                .substitute(&mut body_line, Some(&params))
+                .and_then(|_| self.parse_line(&body_line));
+            if result.is_err() {
+                break;
+            }
+        }
+        self.macro_depth -= 1;
+        return result;
+    }
+
+    /// Add the platforms and orders in a structured scenario to the simulation
+    fn load_doc(&mut self, doc: &ScenarioDoc, name: &str) -> Result<(), ScenarioError> {
+        // structured scenarios have no line numbers to point at
+        let origin = Origin::new(name, 0);
+        let format = doc.get_time_format(name)?;
+        if let Some(policy) = doc.limit_policy {
+            self.limit_policy = policy;
+        }
+        self.start = match parse_time(name, "start", doc.start.as_str(), &format)? {
+            TimeSpec::At(t) => t,
+            _ => {
+                return Err(ScenarioError {
+                    file: String::from(name),
+                    line: 0,
+                    column: 0,
+                    opcode: String::from("start"),
+                    token: doc.start.clone(),
+                    kind: ErrorKind::InvalidField(format.expected()),
+                })
+            }
+        };
+        self.start_origin = Some(origin.clone());
+        let stop = parse_time(name, "stop", doc.stop.as_str(), &format)?;
+        self.stop = self.resolve_doc_time(name, "stop", doc.stop.as_str(), &stop, None)?;
+        self.stop_origin = Some(origin.clone());
+
+        for p in doc.platforms.iter() {
+            self.add_movable(p.get_id(), p.to_movable(), origin.clone());
+        }
+        for o in doc.orders.iter() {
+            let id = o.get_id();
+            let when = parse_time(name, o.get_opcode(), o.get_time(), &format)?;
+            let atm =
+                self.resolve_doc_time(name, o.get_opcode(), o.get_time(), &when, Some(&id))?;
+            let order = self.doc_order(name, o, atm)?;
+            self.add_order(order, Some(origin.clone()));
+        }
+        for t in doc.triggers.iter() {
+            let o = &t.order;
+            let delay = match parse_time(name, o.get_opcode(), o.get_time(), &format)? {
+                TimeSpec::AfterPrevious(d) => d,
+                _ => {
+                    return Err(ScenarioError {
+                        file: String::from(name),
+                        line: 0,
+                        column: 0,
+                        opcode: String::from(o.get_opcode()),
+                        token: String::from(o.get_time()),
+                        kind: ErrorKind::InvalidField(String::from(DELAY_EXPECTED)),
+                    })
                 }
-                Opcode::StopSim | Opcode::EndSim => {
-                    let date_time_str = format!("{} {}", tokens[0], tokens[1]);
-                    let parsed = chrono::NaiveDateTime::parse_from_str(
-                        date_time_str.as_str(),
-                        DATETIME_FORMAT,
+            };
+            let trigger = Trigger::new(t.when.clone(), delay, self.doc_order(name, o, self.start)?);
+            self.push_trigger(trigger, Some(origin.clone()));
+        }
+        return Ok(());
+    }
+
+    /// Build an order read from a structured scenario, to be executed at
+    /// `atm`. Orders that aren't built in are parsed from their opcode
+    /// line by the opcode registered for them
+    fn doc_order(
+        &self,
+        name: &str,
+        doc: &OrderDoc,
+        atm: Timestamp,
+    ) -> Result<Order, ScenarioError> {
+        if let Some(order) = doc.to_order(atm) {
+            return Ok(order);
+        }
+        // the time has already been worked out
+        let mut resolved = doc.clone();
+        resolved.set_time(format_time(atm));
+        // an opcode that doesn't read back as itself, such as one that
+        // is blank, a comment or several words, can't have a parser
+        let opcode = doc.get_opcode();
+        let line = match Line::new(name, 0, &resolved.to_line()) {
+            Some(line) if line.get_opcode() == opcode => line,
+            _ => {
+                return Err(ScenarioError {
+                    file: String::from(name),
+                    line: 0,
+                    column: 0,
+                    opcode: String::from(opcode),
+                    token: String::from(opcode),
+                    kind: ErrorKind::UnknownOpcode,
+                })
+            }
+        };
+        let parser = match self.opcodes.get(line.get_opcode()) {
+            Some(parser) => parser,
+            None => return Err(line.unknown_opcode()),
+        };
+        let mut last_order_times = HashMap::new();
+        let mut ctx = ParseContext::new(&self.time_format, Some(self.start), &mut last_order_times);
+        match parser(&line, &mut ctx)? {
+            Parsed::Order(mut order) => {
+                order.set_extime(atm);
+                return Ok(order);
+            }
+            Parsed::Platform(_) => {
+                let expected = String::from("an order");
+                return Err(line.opcode_error(ErrorKind::InvalidField(expected)));
+            }
+        }
+    }
+
+    /// Parse a single line of a scenario, adding any movable
+    /// or order it describes to the simulation
+    fn parse_line(&mut self, line: &Line) -> Result<(), ScenarioError> {
+        match Directive::new(line.get_opcode()) {
+            Directive::StartSim => {
+                self.start = line.datetime(0, &self.time_format)?.0;
+                self.start_origin = Some(line.origin());
+            }
+            Directive::StopSim | Directive::EndSim => {
+                let (when, _) = line.time(0, &self.time_format)?;
+                self.stop = self.resolve_time(line, 0, &when, None)?;
+                self.stop_origin = Some(line.origin());
+            }
+            Directive::Include => self.include(line)?,
+            Directive::TimeFormat => {
+                self.time_format.format = line.rest(0, "date and time format")?;
+            }
+            Directive::TimeZone => {
+                let zone = line.string(0, ZONE_EXPECTED)?;
+                self.time_format.zone = match parse_zone(zone.as_str()) {
+                    Some(z) => z,
+                    None => {
+                        return Err(
+                            line.error(0, ErrorKind::InvalidField(String::from(ZONE_EXPECTED)))
+                        )
+                    }
+                };
+            }
+            Directive::LimitPolicy => {
+                let expected = "limit policy as Reject or Clamp";
+                self.limit_policy =
+                    LimitPolicy::parse(line.token(0, expected)?).ok_or_else(|| {
+                        line.error(0, ErrorKind::InvalidField(String::from(expected)))
+                    })?;
+            }
+            Directive::Define => {
+                let name = line.string(0, "variable name")?;
+                if !is_name(name.as_str()) {
+                    return Err(
+                        line.error(0, ErrorKind::InvalidField(String::from("variable name")))
                     );
-                    match parsed {
-                        Ok(dt) => self.stop = dt,
-                        Err(_error) => return false,
-                    };
-                }
-                Opcode::CreateCruiser => {
-                    let name = String::from(tokens[0]);
-                    let id = String::from(tokens[1]);
-                    let max_speed = tokens[2].parse::<f64>().unwrap();
-                    let missiles = tokens[3].parse::<i64>().unwrap();
-
-                    let mp = Box::new(Cruiser::new(name, id.clone(), max_speed, missiles));
-                    self.navy_map.insert(id, mp);
                 }
-                Opcode::CreateAircraftCarrier => {
-                    let name = String::from(tokens[0]);
-                    let id = String::from(tokens[1]);
-                    let max_speed = tokens[2].parse::<f64>().unwrap();
-                    let max_aircraft = tokens[3].parse::<i64>().unwrap();
-
-                    let mp = Box::new(Carrier::new(name, id.clone(), max_speed, max_aircraft));
-                    self.navy_map.insert(id, mp);
-                }
-                Opcode::CreateFighter => {
-                    let name = String::from(tokens[0]);
-                    let id = String::from(tokens[1]);
-                    let ship_id = String::from(tokens[2]);
-                    let max_speed = tokens[3].parse::<f64>().unwrap();
-                    let max_ceiling = tokens[4].parse::<f64>().unwrap();
-                    let max_bombs = tokens[5].parse::<i64>().unwrap();
-
-                    let mp = Box::new(Fighter::new(
-                        name,
-                        id.clone(),
-                        max_speed,
-                        ship_id.clone(),
-                        max_ceiling,
-                        max_bombs,
-                    ));
-                    self.navy_map.insert(id, mp);
-                }
-                Opcode::DeployShip => {
-                    let date_time_str = format!("{} {}", tokens[0], tokens[1]);
-                    let parsed = chrono::NaiveDateTime::parse_from_str(
-                        date_time_str.as_str(),
-                        DATETIME_FORMAT,
-                    );
-                    let atm = match parsed {
-                        Ok(dt) => dt,
-                        Err(_error) => return false,
-                    };
-
-                    let id = String::from(tokens[2]);
-                    let x = tokens[3].parse::<f64>().unwrap();
-                    let y = tokens[4].parse::<f64>().unwrap();
-                    let head = tokens[5].parse::<f64>().unwrap();
-                    let spd = tokens[6].parse::<f64>().unwrap();
-                    let op = DeployShip::new(atm, id, x, y, head, spd);
-                    self.order_q.push(Box::new(Order::DeployShipOrder(op)));
+                let value = line.string(1, "value")?;
+                self.variables.insert(name, value);
+            }
+            Directive::Macro => {
+                // parse_text records macros itself and never
+                // hands a Macro line on, but be safe
+                return Err(line.opcode_error(ErrorKind::Macro(String::from(
+                    "macros can't be defined inside a macro",
+                ))));
+            }
+            Directive::EndMacro => {
+                return Err(
+                    line.opcode_error(ErrorKind::Macro(String::from("EndMacro without a Macro")))
+                )
+            }
+            Directive::When => self.parse_trigger(line)?,
+            Directive::Repeat => self.parse_repeat(line)?,
+            Directive::Invalid => {
+                if let Some(parser) = self.opcodes.get(line.get_opcode()) {
+                    let start = self.start_origin.as_ref().map(|_| self.start);
+                    let mut ctx =
+                        ParseContext::new(&self.time_format, start, &mut self.last_order_times);
+                    match parser(line, &mut ctx)? {
+                        Parsed::Platform(mp) => self.add_movable(mp.get_id(), mp, line.origin()),
+                        Parsed::Order(order) => self.add_order(order, Some(line.origin())),
+                    }
+                } else if let Some(m) = self.macros.get(line.get_opcode()) {
+                    let m = m.clone();
+                    self.expand_macro(line, &m)?;
+                } else {
+                    return Err(line.unknown_opcode());
                 }
-                Opcode::DeployAircraft => {
-                    let date_time_str = format!("{} {}", tokens[0], tokens[1]);
-                    let parsed = chrono::NaiveDateTime::parse_from_str(
-                        date_time_str.as_str(),
-                        DATETIME_FORMAT,
-                    );
-                    let atm = match parsed {
-                        Ok(dt) => dt,
-                        Err(_error) => return false,
-                    };
-
-                    let id = String::from(tokens[2]);
-                    let head = tokens[3].parse::<f64>().unwrap();
-                    let spd = tokens[4].parse::<f64>().unwrap();
-                    let z = tokens[5].parse::<f64>().unwrap();
-                    let op = DeployAircraft::new(atm, id, head, spd, z);
-                    self.order_q.push(Box::new(Order::DeployAircraftOrder(op)));
+            }
+        }
+        return Ok(());
+    }
+
+    /// Parse `When <condition> Then <order>`, where the order is
+    /// written as usual except that its time is a delay after
+    /// the condition is met, such as +00:30:00
+    fn parse_trigger(&mut self, line: &Line) -> Result<(), ScenarioError> {
+        let (condition, i) = Condition::parse(line, 0)?;
+        let order_line = line.then_line(i)?;
+        let delay = match order_line
+            .args
+            .first()
+            .and_then(|t| t.text.strip_prefix('+'))
+            .and_then(parse_offset)
+        {
+            Some(d) => d,
+            None => {
+                let expected = String::from(DELAY_EXPECTED);
+                return Err(order_line.error(0, ErrorKind::InvalidField(expected)));
+            }
+        };
+        let parser = match self.opcodes.get(order_line.get_opcode()) {
+            Some(parser) => parser,
+            None => return Err(order_line.unknown_opcode()),
+        };
+        // the order is parsed as if it were given at the start,
+        // and moved to its real time when the condition is met
+        let mut last_order_times = HashMap::new();
+        let mut ctx = ParseContext::new(&self.time_format, Some(self.start), &mut last_order_times)
+            .relative_to(self.start);
+        let order = match parser(&order_line, &mut ctx)? {
+            Parsed::Order(order) => order,
+            Parsed::Platform(_) => {
+                let expected = String::from("an order");
+                return Err(line.error(i + 1, ErrorKind::InvalidField(expected)));
+            }
+        };
+        let trigger = Trigger::new(condition, delay, order);
+        self.push_trigger(trigger, Some(line.origin()));
+        return Ok(());
+    }
+
+    /// Parse `Repeat <HH:MM:SS> <count> Then <order>` or
+    /// `Repeat <HH:MM:SS> Until <time> Then <order>`, queueing the order
+    /// at its own time and again every interval after it, either `count`
+    /// times in all or for as long as it is no later than the Until time
+    fn parse_repeat(&mut self, line: &Line) -> Result<(), ScenarioError> {
+        let expected = "interval as HH:MM:SS";
+        let interval = match parse_offset(line.token(0, expected)?) {
+            Some(d) if d > Duration::zero() => d,
+            _ => return Err(line.error(0, ErrorKind::InvalidField(String::from(expected)))),
+        };
+        let (until, i) = if line.token(1, "count or Until")? == "Until" {
+            let (when, next) = line.time(2, &self.time_format)?;
+            (Some(self.resolve_time(line, 2, &when, None)?), next)
+        } else {
+            (None, 2)
+        };
+
+        let order_line = line.then_line(i)?;
+        let parser = match self.opcodes.get(order_line.get_opcode()) {
+            Some(parser) => parser,
+            None => return Err(order_line.unknown_opcode()),
+        };
+        let start = self.start_origin.as_ref().map(|_| self.start);
+        let mut ctx = ParseContext::new(&self.time_format, start, &mut self.last_order_times);
+        let order = match parser(&order_line, &mut ctx)? {
+            Parsed::Order(order) => order,
+            Parsed::Platform(_) => {
+                let expected = String::from("an order");
+                return Err(line.error(i + 1, ErrorKind::InvalidField(expected)));
+            }
+        };
+
+        let first = order.get_extime();
+        let count = match until {
+            Some(until) => {
+                if until < first {
+                    let expected = String::from("time no earlier than the order's");
+                    return Err(line.error(2, ErrorKind::InvalidField(expected)));
                 }
-                Opcode::ChangeShipOrders => {
-                    let date_time_str = format!("{} {}", tokens[0], tokens[1]);
-                    let parsed = chrono::NaiveDateTime::parse_from_str(
-                        date_time_str.as_str(),
-                        DATETIME_FORMAT,
-                    );
-                    let atm = match parsed {
-                        Ok(dt) => dt,
-                        Err(_error) => return false,
-                    };
-
-                    let id = String::from(tokens[2]);
-                    let head = tokens[3].parse::<f64>().unwrap();
-                    let spd = tokens[4].parse::<f64>().unwrap();
-                    let op = ChangeShip::new(atm, id, head, spd);
-                    self.order_q.push(Box::new(Order::ChangeShipOrder(op)));
+                let span = (until - first).num_milliseconds();
+                span / interval.num_milliseconds() + 1
+            }
+            None => line.i64(1, "count or Until")?,
+        };
+        if !(1..=MAX_REPEATS).contains(&count) {
+            let expected = format!("count from 1 to {}", MAX_REPEATS);
+            let idx = if until.is_some() { 2 } else { 1 };
+            return Err(line.error(idx, ErrorKind::InvalidField(expected)));
+        }
+
+        let mut times = Vec::new();
+        for n in 0..count {
+            let after = interval.checked_mul(n as i32);
+            match after.and_then(|d| first.checked_add_signed(d)) {
+                Some(t) => times.push(t),
+                None => {
+                    let expected = String::from("interval that keeps the repeats in range");
+                    return Err(line.error(0, ErrorKind::InvalidField(expected)));
                 }
-                Opcode::ChangeAircraftOrders => {
-                    let date_time_str = format!("{} {}", tokens[0], tokens[1]);
-                    let parsed = chrono::NaiveDateTime::parse_from_str(
-                        date_time_str.as_str(),
-                        DATETIME_FORMAT,
-                    );
-                    let atm = match parsed {
-                        Ok(dt) => dt,
-                        Err(_error) => return false,
-                    };
-
-                    let id = String::from(tokens[2]);
-                    let head = tokens[3].parse::<f64>().unwrap();
-                    let spd = tokens[4].parse::<f64>().unwrap();
-                    let z = tokens[5].parse::<f64>().unwrap();
-                    let op = ChangeAircraft::new(atm, id, head, spd, z);
-                    self.order_q.push(Box::new(Order::ChangeAircraftOrder(op)));
+            }
+        }
+
+        // later +HH:MM:SS times for the unit follow on from the last repeat
+        self.last_order_times
+            .insert(order.get_id(), times[times.len() - 1]);
+        for t in times {
+            let mut repeat = order.clone();
+            repeat.set_extime(t);
+            self.add_order(repeat, Some(line.origin()));
+        }
+        return Ok(());
+    }
+
+    /// Turn the time an order was given into an absolute time.
+    /// `id` is the unit the order is for, if any.
+    /// On failure, returns what was needed to resolve the time
+    fn resolve(&mut self, when: &TimeSpec, id: Option<&str>) -> Result<Timestamp, String> {
+        let start = self.start_origin.as_ref().map(|_| self.start);
+        let mut ctx = ParseContext::new(&self.time_format, start, &mut self.last_order_times);
+        return ctx.resolve(when, id);
+    }
+
+    fn resolve_time(
+        &mut self,
+        line: &Line,
+        idx: usize,
+        when: &TimeSpec,
+        id: Option<&str>,
+    ) -> Result<Timestamp, ScenarioError> {
+        return self
+            .resolve(when, id)
+            .map_err(|expected| line.error(idx, ErrorKind::InvalidField(expected)));
+    }
+
+    fn resolve_doc_time(
+        &mut self,
+        name: &str,
+        what: &str,
+        time: &str,
+        when: &TimeSpec,
+        id: Option<&str>,
+    ) -> Result<Timestamp, ScenarioError> {
+        return self.resolve(when, id).map_err(|expected| ScenarioError {
+            file: String::from(name),
+            line: 0,
+            column: 0,
+            opcode: String::from(what),
+            token: String::from(time),
+            kind: ErrorKind::InvalidField(expected),
+        });
+    }
+
+    /// Read another scenario file in place of an Include line.
+    /// Paths are relative to the directory of the including file
+    fn include(&mut self, line: &Line) -> Result<(), ScenarioError> {
+        let target = line.string(0, "file to include")?;
+        let base = Path::new(line.file.as_str())
+            .parent()
+            .unwrap_or_else(|| Path::new(""));
+        let path = base.join(target.as_str());
+        let name = path.to_string_lossy().to_string();
+
+        let unreadable = |e: std::io::Error| {
+            line.error(
+                0,
+                ErrorKind::Include(format!("cannot read '{}': {}", name, e)),
+            )
+        };
+        let canonical = std::fs::canonicalize(&path).map_err(unreadable)?;
+        if self.include_stack.contains(&canonical) {
+            return Err(line.error(
+                0,
+                ErrorKind::Include(format!("'{}' is already being read, include cycle", name)),
+            ));
+        }
+        let f = File::open(&path).map_err(unreadable)?;
+
+        // included files start with the includer's time format, but
+        // any TimeFormat or TimeZone they set doesn't leak back out
+        let time_format = self.time_format.clone();
+        self.include_stack.push(canonical);
+        let result = self.parse_text(BufReader::new(f), name.as_str());
+        self.include_stack.pop();
+        self.time_format = time_format;
+        return result;
+    }
+
+    /// Describe the current scenario: every platform, in the order they
+    /// were created, and every order still waiting to be executed
+    pub fn to_doc(&self) -> ScenarioDoc {
+        // orders are numbered afresh when the scenario is read back,
+        // so cancellations have to refer to the new numbers
+        let numbers: HashMap<OrderId, OrderId> = self
+            .order_q
+            .iter()
+            .map(|o| o.id)
+            .chain(self.triggers.iter().map(|t| t.id))
+            .enumerate()
+            .map(|(i, id)| (id, i as OrderId + 1))
+            .collect();
+        let renumber = |doc: &mut OrderDoc| -> bool {
+            if let OrderDoc::CancelOrders { order: Some(n), .. } = doc {
+                match numbers.get(n) {
+                    Some(renumbered) => *n = *renumbered,
+                    // the order is already gone, so there is nothing to cancel
+                    None => return false,
                 }
-                Opcode::LandAircraft => {
-                    let date_time_str = format!("{} {}", tokens[0], tokens[1]);
-                    let parsed = chrono::NaiveDateTime::parse_from_str(
-                        date_time_str.as_str(),
-                        DATETIME_FORMAT,
-                    );
-                    let atm = match parsed {
-                        Ok(dt) => dt,
-                        Err(_error) => return false,
-                    };
-
-                    let ship_id = String::from(tokens[2]);
-                    let id = String::from(tokens[3]);
-                    let op = LandAircraft::new(atm, id, ship_id.clone());
-                    self.order_q.push(Box::new(Order::LandAircraftOrder(op)));
+            }
+            return true;
+        };
+        let mut orders = Vec::new();
+        for o in self.order_q.iter() {
+            let mut doc = OrderDoc::new(&o.order);
+            if renumber(&mut doc) {
+                orders.push(doc);
+            }
+        }
+        let mut triggers = Vec::new();
+        for t in self.triggers.iter() {
+            let mut doc = TriggerDoc::new(&t.trigger);
+            if renumber(&mut doc.order) {
+                triggers.push(doc);
+            }
+        }
+        let mut platforms = Vec::new();
+        for (i, (id, _)) in self.movable_origins.iter().enumerate() {
+            if !self.is_replaced(i) {
+                platforms.push(self.navy_map[id].to_doc());
+            }
+        }
+        ScenarioDoc {
+            time_format: None,
+            time_zone: None,
+            limit_policy: Some(self.limit_policy).filter(|p| *p != LimitPolicy::default()),
+            start: format_time(self.start),
+            stop: format_time(self.stop),
+            platforms: platforms,
+            orders: orders,
+            triggers: triggers,
+        }
+    }
+
+    /// Write the scenario to a file. The format is chosen from the file's extension
+    pub fn save(&self, filename: &str) -> std::io::Result<()> {
+        let text = self.to_doc().write(Format::from_filename(filename));
+        return std::fs::write(filename, text);
+    }
+
+    /// Whether the i'th movable created was replaced in the navy map
+    /// by a later one with the same id
+    fn is_replaced(&self, i: usize) -> bool {
+        let id = &self.movable_origins[i].0;
+        return self.movable_origins[i + 1..].iter().any(|(o, _)| o == id);
+    }
+
+    fn add_movable(&mut self, id: String, mp: Box<dyn Movable>, origin: Origin) {
+        self.movable_origins.push((id.clone(), origin));
+        self.navy_map.insert(id, mp);
+    }
+
+    fn add_order(&mut self, order: Order, origin: Option<Origin>) {
+        let id = self.take_order_id();
+        self.order_q.push(QueuedOrder { id, order, origin });
+    }
+
+    fn push_trigger(&mut self, trigger: Trigger, origin: Option<Origin>) -> OrderId {
+        let id = self.take_order_id();
+        self.triggers.push(PendingTrigger {
+            id: id,
+            trigger: trigger,
+            origin: origin,
+        });
+        return id;
+    }
+
+    fn take_order_id(&mut self) -> OrderId {
+        let id = self.next_order_id;
+        self.next_order_id += 1;
+        return id;
+    }
+
+    /// Check the parsed scenario for problems that would otherwise
+    /// only show up while the simulation is running
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diags = Vec::new();
+
+        if self.start_origin.is_none() {
+            diags.push(Diagnostic::warning(None, String::from("no StartSim given")));
+        }
+        if self.stop_origin.is_none() {
+            diags.push(Diagnostic::warning(None, String::from("no StopSim given")));
+        }
+        if self.stop < self.start {
+            diags.push(Diagnostic::error(
+                self.stop_origin.as_ref(),
+                format!(
+                    "StopSim {} is earlier than StartSim {}",
+                    self.stop, self.start
+                ),
+            ));
+        }
+
+        // movables sharing an id replace each other in the navy map
+        let mut first_defined: HashMap<&str, &Origin> = HashMap::new();
+        for (id, origin) in self.movable_origins.iter() {
+            match first_defined.get(id.as_str()) {
+                Some(first) => diags.push(Diagnostic::error(
+                    Some(origin),
+                    format!("'{}' is already defined at {}", id, first),
+                )),
+                None => {
+                    first_defined.insert(id.as_str(), origin);
                 }
-                Opcode::Invalid => {
-                    println!("Invalid opcode: {:?}", opcode);
-                    return false;
+            }
+        }
+
+        // aircraft must belong to a ship they can operate from
+        for (i, (id, origin)) in self.movable_origins.iter().enumerate() {
+            if self.is_replaced(i) {
+                continue;
+            }
+            let mov = &self.navy_map[id];
+            if let Some(ship_id) = mov.get_ship_id() {
+                if let Some(d) = self.check_deck(ship_id.as_str(), Some(origin), mov.as_ref()) {
+                    diags.push(d);
                 }
             }
         }
-        // sort the order queue
-        self.order_q.sort();
-        // self.order_q.sort_by(|a, b| a.get_extime().partial_cmp(&b.get_extime()).unwrap());
-        return true;
+
+        for qo in self.order_q.iter() {
+            self.validate_order(qo, &mut diags);
+        }
+        for pt in self.triggers.iter() {
+            self.validate_trigger(pt, &mut diags);
+        }
+        return diags;
     }
 
-    /// Execute orders and update the navy map for a given time
-    pub fn do_update(&mut self, now: chrono::NaiveDateTime) {
-        // execute any orders that are scheduled to be executed
-        while !self.order_q.is_empty() {
-            let o = self.order_q.first().unwrap();
-            if o.get_extime() > now {
-                break;
+    /// Check that `ship_id` refers to a ship that `aircraft` can operate
+    /// from: a carrier for a fighter, any flight deck for a helicopter
+    fn check_deck(
+        &self,
+        ship_id: &str,
+        origin: Option<&Origin>,
+        aircraft: &dyn Movable,
+    ) -> Option<Diagnostic> {
+        let (id, kind) = (aircraft.get_id(), aircraft.get_kind());
+        let (deck, not_deck) = if kind == MovableKind::Fighter {
+            ("carrier", "not a carrier")
+        } else {
+            ("ship", "without a flight deck")
+        };
+        return match self.navy_map.get(ship_id) {
+            None => Some(Diagnostic::error(
+                origin,
+                format!("{} refers to unknown {} '{}'", id, deck, ship_id),
+            )),
+            Some(ship) if !kind.can_land_on(ship.get_kind()) => Some(Diagnostic::error(
+                origin,
+                format!(
+                    "{} refers to '{}', which is a {:?}, {}",
+                    id,
+                    ship_id,
+                    ship.get_kind(),
+                    not_deck
+                ),
+            )),
+            Some(_) => None,
+        };
+    }
+
+    fn validate_order(&self, qo: &QueuedOrder, diags: &mut Vec<Diagnostic>) {
+        let origin = qo.origin.as_ref();
+        let order = &qo.order;
+        let id = order.get_id();
+        let extime = order.get_extime();
+
+        if extime < self.start {
+            diags.push(Diagnostic::warning(
+                origin,
+                format!("order for {} at {} is before StartSim", id, extime),
+            ));
+        } else if extime >= self.stop {
+            diags.push(Diagnostic::warning(
+                origin,
+                format!("order for {} at {} is at or after StopSim", id, extime),
+            ));
+        }
+        if let Order::CancelOrdersOrder(o) = order {
+            self.validate_cancel(o, Some(qo.key()), origin, diags);
+        }
+        self.validate_unit_order(order, origin, diags);
+    }
+
+    /// Check that a trigger's condition refers to units that exist,
+    /// and that its order makes sense for its unit
+    fn validate_trigger(&self, pt: &PendingTrigger, diags: &mut Vec<Diagnostic>) {
+        let origin = pt.origin.as_ref();
+        let condition = &pt.trigger.condition;
+        let id = condition.get_id();
+        let mut ids = vec![id];
+        if let Condition::Within { other, .. } = condition {
+            if other == id {
+                diags.push(Diagnostic::error(
+                    origin,
+                    format!("{} is always within range of itself", id),
+                ));
             }
-            let o = &self.order_q.remove(0);
-            let mov = match self.navy_map.get_mut(o.get_id().as_str()) {
-                Some(id) => id,
-                None => {
-                    println!("Unable to find id: {}, skipping order", o.get_id().as_str());
-                    continue;
-                }
-            };
-            mov.execute(o);
+            ids.push(other.as_str());
+        }
+        for i in ids.iter() {
+            if !self.navy_map.contains_key(*i) {
+                diags.push(Diagnostic::error(
+                    origin,
+                    format!("condition on unknown id '{}' will never be met", i),
+                ));
+            }
+        }
+        if let Order::CancelOrdersOrder(o) = &pt.trigger.order {
+            self.validate_cancel(o, None, origin, diags);
         }
+        self.validate_unit_order(&pt.trigger.order, origin, diags);
+    }
+
+    /// Check that the order a CancelOrders refers to is still pending when
+    /// it is cancelled. `queued` is the key of the CancelOrders, if it is in
+    /// the order queue rather than waiting on a trigger
+    fn validate_cancel(
+        &self,
+        o: &CancelOrders,
+        queued: Option<OrderKey>,
+        origin: Option<&Origin>,
+        diags: &mut Vec<Diagnostic>,
+    ) {
+        let n = match o.order_id {
+            Some(n) => n,
+            None => return,
+        };
+        let target = match self.order_q.find(n) {
+            Some(q) => Some((q.order.get_id(), Some(q.key()))),
+            None => self
+                .triggers
+                .iter()
+                .find(|t| t.id == n)
+                .map(|t| (t.trigger.order.get_id(), None)),
+        };
+        match target {
+            None => diags.push(Diagnostic::warning(
+                origin,
+                format!("there is no order #{} to cancel", n),
+            )),
+            Some((unit, _)) if unit != o.id => diags.push(Diagnostic::error(
+                origin,
+                format!("order #{} is for {}, not {}", n, unit, o.id),
+            )),
+            // orders are executed in the order of their keys
+            Some((_, Some(executed))) if queued.is_some_and(|q| executed < q) => {
+                diags.push(Diagnostic::warning(
+                    origin,
+                    format!("order #{} is executed before it is cancelled", n),
+                ))
+            }
+            Some(_) => (),
+        }
+    }
+
+    /// Check an order against the unit it is for
+    fn validate_unit_order(
+        &self,
+        order: &Order,
+        origin: Option<&Origin>,
+        diags: &mut Vec<Diagnostic>,
+    ) {
+        let id = order.get_id();
+        let mov = match self.navy_map.get(id.as_str()) {
+            Some(m) => m,
+            None => {
+                diags.push(Diagnostic::error(
+                    origin,
+                    format!("order for unknown id '{}' will be skipped", id),
+                ));
+                return;
+            }
+        };
+
+        if let Order::FollowRouteOrder(o) = order {
+            if o.waypoints.is_empty() {
+                diags.push(Diagnostic::warning(
+                    origin,
+                    format!("route for {} has no waypoints", id),
+                ));
+            }
+        }
+        if !order.for_kind(mov.get_kind()) {
+            diags.push(Diagnostic::warning(
+                origin,
+                format!(
+                    "{} is a {:?} and will reject this order",
+                    id,
+                    mov.get_kind()
+                ),
+            ));
+            return;
+        }
+        if let Order::LandAircraftOrder(o) = order {
+            if let Some(d) = self.check_deck(o.ship_id.as_str(), origin, mov.as_ref()) {
+                diags.push(d);
+            }
+        }
+        if let Order::TakeStationOrder(o) = order {
+            if o.guide_id == id {
+                diags.push(Diagnostic::error(
+                    origin,
+                    format!("{} can't keep station on itself", id),
+                ));
+            } else if !self.navy_map.contains_key(o.guide_id.as_str()) {
+                diags.push(Diagnostic::warning(
+                    origin,
+                    format!(
+                        "{} is told to keep station on unknown id '{}' and will hold its course",
+                        id, o.guide_id
+                    ),
+                ));
+            }
+        }
+        if let Order::InterceptOrder(o) = order {
+            if o.target_id == id {
+                diags.push(Diagnostic::error(
+                    origin,
+                    format!("{} can't intercept itself", id),
+                ));
+            } else if !self.navy_map.contains_key(o.target_id.as_str()) {
+                diags.push(Diagnostic::warning(
+                    origin,
+                    format!(
+                        "{} is told to intercept unknown id '{}' and will hold its course",
+                        id, o.target_id
+                    ),
+                ));
+            }
+        }
+        let verb = match self.limit_policy {
+            LimitPolicy::Reject => "rejected",
+            LimitPolicy::Clamp => "clamped",
+        };
+        for reason in mov.get_limits().check(order) {
+            diags.push(Diagnostic::warning(
+                origin,
+                format!("order for {} will be {}: {}", id, verb, reason),
+            ));
+        }
+    }
+
+    /// Execute orders and update the navy map for a given time
+    pub fn do_update(&mut self, now: Timestamp) {
+        self.execute_orders(now);
         let loc_map = self.get_location_map();
 
         // update the position of all deployed movables
+        let mut events = Vec::new();
         for (_, val) in self.navy_map.iter_mut() {
             if val.get_is_deployed() {
                 val.update_position(now, &loc_map);
             }
+            events.append(&mut val.take_events());
+        }
+        // units are updated in no particular order, but events from
+        // earlier updates all happened before these
+        events.sort_by(|a, b| a.t.cmp(&b.t).then_with(|| a.id.cmp(&b.id)));
+        self.events.append(&mut events);
+
+        // orders whose condition is now met, and that have no
+        // delay, are executed straight away
+        self.fire_triggers(now);
+        self.execute_orders(now);
+    }
+
+    /// Execute any orders that are scheduled to be executed by `now`
+    fn execute_orders(&mut self, now: Timestamp) {
+        while let Some(qo) = self.order_q.pop_due(now) {
+            if let Order::CancelOrdersOrder(c) = &qo.order {
+                self.order_log.push(OrderRecord::new(
+                    now,
+                    qo.id,
+                    &qo.order,
+                    OrderOutcome::Accepted,
+                ));
+                self.carry_out_cancel(c, qo.id, now);
+                continue;
+            }
+            let outcome = match self.check_landing(&qo.order) {
+                Err(reason) => OrderOutcome::Rejected(reason),
+                Ok(()) => match self.navy_map.get_mut(qo.order.get_id().as_str()) {
+                    Some(mov) => qo.order.execute_with(mov.as_mut(), self.limit_policy),
+                    None => OrderOutcome::Rejected(RejectReason::UnknownUnit),
+                },
+            };
+            self.order_log
+                .push(OrderRecord::new(now, qo.id, &qo.order, outcome));
+        }
+    }
+
+    /// Check that the ship a landing order names exists, and that
+    /// the aircraft can land on that type of ship
+    fn check_landing(&self, order: &Order) -> Result<(), RejectReason> {
+        let o = match order {
+            Order::LandAircraftOrder(o) => o,
+            _ => return Ok(()),
+        };
+        let ship = match self.navy_map.get(o.ship_id.as_str()) {
+            Some(ship) => ship.get_kind(),
+            None => return Err(RejectReason::UnknownUnit),
+        };
+        match self.navy_map.get(o.id.as_str()) {
+            Some(aircraft) if !aircraft.get_kind().can_land_on(ship) => {
+                Err(RejectReason::WrongPlatform(ship))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Drop the pending orders a CancelOrders refers to,
+    /// whether they are queued or waiting on a condition
+    fn carry_out_cancel(&mut self, c: &CancelOrders, by: OrderId, now: Timestamp) {
+        let mut cancelled: Vec<(OrderId, Order)> = Vec::new();
+        match c.order_id {
+            // only ever cancel the unit's own orders
+            Some(n) => {
+                if self
+                    .order_q
+                    .find(n)
+                    .is_some_and(|q| q.order.get_id() == c.id)
+                {
+                    cancelled.extend(self.cancel_order(n).map(|q| (q.id, q.order)));
+                }
+                if self
+                    .triggers
+                    .iter()
+                    .any(|t| t.id == n && t.trigger.order.get_id() == c.id)
+                {
+                    cancelled.extend(self.cancel_trigger(n).map(|t| (t.id, t.trigger.order)));
+                }
+            }
+            None => {
+                let queued = self.cancel_orders(c.id.as_str());
+                cancelled.extend(queued.into_iter().map(|q| (q.id, q.order)));
+                let waiting = self.cancel_triggers(c.id.as_str());
+                cancelled.extend(waiting.into_iter().map(|t| (t.id, t.trigger.order)));
+            }
+        }
+        for (id, order) in cancelled.iter() {
+            let outcome = OrderOutcome::Cancelled(by);
+            self.order_log
+                .push(OrderRecord::new(now, *id, order, outcome));
+        }
+    }
+
+    /// Queue the orders whose condition is met at `now`
+    fn fire_triggers(&mut self, now: Timestamp) {
+        let (met, waiting): (Vec<PendingTrigger>, Vec<PendingTrigger>) =
+            std::mem::take(&mut self.triggers)
+                .into_iter()
+                .partition(|t| t.trigger.condition.is_met(&self.navy_map));
+        self.triggers = waiting;
+        for t in met {
+            self.order_q.push(QueuedOrder {
+                id: t.id,
+                order: t.trigger.fire(now),
+                origin: t.origin,
+            });
         }
     }
 
@@ -343,3 +1457,1399 @@ impl SimManager {
         return loc_map;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guidance::Station;
+
+    #[test]
+    fn test_init_orders_file() {
+        let mut sim = SimManager::new();
+        assert!(sim.init("orders/orders05.txt").is_ok());
+        assert_eq!(sim.navy_map.len(), 8);
+    }
+
+    #[test]
+    fn test_init_missing_file() {
+        let mut sim = SimManager::new();
+        let e = sim.init("orders/does_not_exist.txt").unwrap_err();
+        assert_eq!(e.file, "orders/does_not_exist.txt");
+        match e.kind {
+            ErrorKind::Io(_) => (),
+            _ => panic!("expected an io error"),
+        }
+    }
+
+    fn parse(lines: &[&str]) -> SimManager {
+        let mut sim = SimManager::new();
+        sim.init_from_str(lines.join("\n").as_str()).unwrap();
+        return sim;
+    }
+
+    #[test]
+    fn test_init_from_str_matches_file() {
+        for entry in std::fs::read_dir("orders").unwrap() {
+            let path = entry.unwrap().path();
+            if !path.is_file() || Format::from_filename(path.to_str().unwrap()) != Format::Text {
+                continue;
+            }
+            let filename = path.to_str().unwrap();
+            let mut from_file = SimManager::new();
+            from_file.init(filename).unwrap();
+            let mut from_str = SimManager::new();
+            let text = std::fs::read_to_string(&path).unwrap();
+            from_str
+                .init_from_reader(text.as_bytes(), filename)
+                .unwrap();
+
+            assert_eq!(from_file.start, from_str.start);
+            assert_eq!(from_file.stop, from_str.stop);
+            assert_eq!(from_file.navy_map.len(), from_str.navy_map.len());
+            let a: Vec<_> = from_file
+                .order_q
+                .iter()
+                .map(|o| (o.order.get_id(), o.order.get_extime()))
+                .collect();
+            let b: Vec<_> = from_str
+                .order_q
+                .iter()
+                .map(|o| (o.order.get_id(), o.order.get_extime()))
+                .collect();
+            assert_eq!(a, b, "{}", filename);
+        }
+    }
+
+    #[test]
+    fn test_init_structured_matches_text() {
+        for (structured, text) in [
+            ("orders/orders02.json", "orders/orders02.txt"),
+            ("orders/plane1.toml", "orders/plane1.txt"),
+        ]
+        .iter()
+        {
+            let mut a = SimManager::new();
+            a.init(structured).unwrap();
+            let mut b = SimManager::new();
+            b.init(text).unwrap();
+
+            assert_eq!(a.start, b.start);
+            assert_eq!(a.stop, b.stop);
+            let mut a_ids: Vec<_> = a.navy_map.keys().collect();
+            let mut b_ids: Vec<_> = b.navy_map.keys().collect();
+            a_ids.sort();
+            b_ids.sort();
+            assert_eq!(a_ids, b_ids);
+            let a_orders: Vec<_> = a
+                .order_q
+                .iter()
+                .map(|o| (o.order.get_id(), o.order.get_extime()))
+                .collect();
+            let b_orders: Vec<_> = b
+                .order_q
+                .iter()
+                .map(|o| (o.order.get_id(), o.order.get_extime()))
+                .collect();
+            assert_eq!(a_orders, b_orders, "{}", structured);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for entry in std::fs::read_dir("orders").unwrap() {
+            let path = entry.unwrap().path();
+            if !path.is_file() {
+                continue;
+            }
+            let filename = path.to_str().unwrap();
+            let mut sim = SimManager::new();
+            sim.init(filename).unwrap();
+            let doc = sim.to_doc();
+
+            for format in [Format::Text, Format::Json, Format::Toml].iter() {
+                let text = doc.write(*format);
+                let mut again = SimManager::new();
+                again
+                    .init_with_format(text.as_bytes(), filename, *format)
+                    .unwrap();
+                let doc_again = again.to_doc();
+                assert_eq!(doc, doc_again, "{} as {:?}", filename, format);
+                assert_eq!(text, doc_again.write(*format));
+            }
+        }
+    }
+
+    #[test]
+    fn test_write_text() {
+        let sim = parse(&[
+            "CreateAircraftCarrier USS_Nimitz CVN-68 85 50",
+            "CreateFighter F18_Hornet FA18C_1 CVN-68 1190 40000 9",
+            "StartSim 12/1/2004 10:00:00",
+            "LandAircraft 12/1/2004 10:24:00 CVN-68 FA18C_1",
+            "DeployShip 12/1/2004 10:00:00 CVN-68 -0.5 0 90 35",
+            "ChangeAircraftOrders 12/1/2004 10:10:00 FA18C_1 -1 500 40000",
+            "StopSim 12/1/2004 11:00:00",
+        ]);
+        assert_eq!(
+            sim.to_doc().to_text(),
+            "CreateAircraftCarrier USS_Nimitz CVN-68 85 50\n\
+             CreateFighter F18_Hornet FA18C_1 CVN-68 1190 40000 9\n\
+             StartSim 2004-12-01T10:00:00Z\n\
+             DeployShip 2004-12-01T10:00:00Z CVN-68 -0.5 0 90 35\n\
+             ChangeAircraftOrders 2004-12-01T10:10:00Z FA18C_1 -1 500 40000\n\
+             LandAircraft 2004-12-01T10:24:00Z CVN-68 FA18C_1\n\
+             StopSim 2004-12-01T11:00:00Z\n"
+        );
+    }
+
+    #[test]
+    fn test_include_matches_inline() {
+        let mut inline = SimManager::new();
+        inline.init("orders/shipstar.txt").unwrap();
+        let mut included = SimManager::new();
+        included.init("orders/shipstar_include.txt").unwrap();
+        assert_eq!(inline.to_doc(), included.to_doc());
+    }
+
+    #[test]
+    fn test_relative_times_match_absolute() {
+        let mut absolute = SimManager::new();
+        absolute.init("orders/orders01.txt").unwrap();
+        let mut relative = SimManager::new();
+        relative.init("orders/orders01_relative.txt").unwrap();
+        assert_eq!(absolute.to_doc(), relative.to_doc());
+    }
+
+    #[test]
+    fn test_iso_times_match_absolute() {
+        let mut absolute = SimManager::new();
+        absolute.init("orders/orders01.txt").unwrap();
+        let mut iso = SimManager::new();
+        iso.init("orders/orders01_iso.txt").unwrap();
+        assert_eq!(absolute.get_start(), iso.get_start());
+        assert_eq!(absolute.get_stop(), iso.get_stop());
+        let times = |sim: &SimManager| -> Vec<Timestamp> {
+            sim.order_q.iter().map(|o| o.order.get_extime()).collect()
+        };
+        assert_eq!(times(&absolute), times(&iso));
+    }
+
+    #[test]
+    fn test_relative_time_errors() {
+        let mut sim = SimManager::new();
+        let e = sim
+            .init_from_str("DeployShip T+00:03:00 CGN-39 0 0 90 30")
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "<string>:1:12: DeployShip: expected a StartSim earlier in the scenario, \
+             got 'T+00:03:00'"
+        );
+
+        let mut sim = SimManager::new();
+        let e = sim
+            .init_from_str(
+                "StartSim 12/14/2015 10:00:00\n\
+                 DeployShip T+00:03:00 CGN-39 0 0 90 30\n\
+                 ChangeShipOrders +00:03:00 CGN-38 90 30",
+            )
+            .unwrap_err();
+        assert_eq!(e.line, 3);
+        assert_eq!(e.token, "+00:03:00");
+
+        // offsets too large for a time are errors rather than panics
+        for offset in ["T+9999999999:00:00", "T+99999999999999:00:00"] {
+            let e = SimManager::new()
+                .init_from_str(
+                    format!(
+                        "StartSim 12/14/2015 10:00:00\nDeployShip {} CGN-39 0 0 90 30",
+                        offset
+                    )
+                    .as_str(),
+                )
+                .unwrap_err();
+            assert_eq!((e.line, e.token.as_str()), (2, offset));
+        }
+    }
+
+    #[test]
+    fn test_relative_times_in_json() {
+        let doc = r#"{
+            "start": "12/14/2015 10:00:00",
+            "stop": "T+01:00:00",
+            "orders": [
+                { "type": "DeployShip", "time": "T+00:03:00", "id": "CGN-39",
+                  "x": 0, "y": 0, "heading": 90, "speed": 30 },
+                { "type": "ChangeShipOrders", "time": "+00:07:00", "id": "CGN-39",
+                  "heading": 225 }
+            ]
+        }"#;
+        let mut sim = SimManager::new();
+        sim.init_with_format(doc.as_bytes(), "s.json", Format::Json)
+            .unwrap();
+        let doc = sim.to_doc();
+        assert_eq!(doc.stop, "2015-12-14T11:00:00Z");
+        assert_eq!(doc.orders[1].get_time(), "2015-12-14T10:10:00Z");
+    }
+
+    #[test]
+    fn test_time_format_and_zone() {
+        let sim = parse(&[
+            "TimeFormat %d.%m.%Y %H:%M",
+            "TimeZone +05:00",
+            "CreateCruiser USS_Texas CGN-39 50 100",
+            "StartSim 14.12.2015 10:00",
+            "DeployShip 2015-12-14T05:03:00.5Z CGN-39 0 0 90 30",
+            "StopSim T+01:00:00",
+        ]);
+        let doc = sim.to_doc();
+        assert_eq!(doc.start, "2015-12-14T10:00:00+05:00");
+        assert_eq!(doc.stop, "2015-12-14T11:00:00+05:00");
+        assert_eq!(doc.orders[0].get_time(), "2015-12-14T05:03:00.500Z");
+        // the same instant, whatever the offset it was written with
+        assert_eq!(
+            sim.get_start() + Duration::milliseconds(180_500),
+            sim.get_orders()[0].order.get_extime()
+        );
+    }
+
+    #[test]
+    fn test_time_zone_errors() {
+        let mut sim = SimManager::new();
+        let e = sim.init_from_str("TimeZone EST").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "<string>:1:10: TimeZone: expected UTC offset such as +05:00, \
+             -0800 or UTC, got 'EST'"
+        );
+
+        let mut sim = SimManager::new();
+        let e = sim
+            .init_from_str("TimeFormat %Y-%m-%d %H:%M\nStartSim 12/14/2015 10:00:00")
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "<string>:2:10: StartSim: expected date and time as '%Y-%m-%d %H:%M' \
+             or ISO-8601, got '12/14/2015'"
+        );
+    }
+
+    #[test]
+    fn test_time_zone_in_json() {
+        let doc = r#"{
+            "time_zone": "-08:00",
+            "start": "12/14/2015 10:00:00",
+            "stop": "2015-12-14T20:00:00Z"
+        }"#;
+        let mut sim = SimManager::new();
+        sim.init_with_format(doc.as_bytes(), "s.json", Format::Json)
+            .unwrap();
+        assert_eq!(sim.get_stop() - sim.get_start(), Duration::hours(2));
+    }
+
+    #[test]
+    fn test_macros_match_plain() {
+        let mut plain = SimManager::new();
+        plain.init("orders/plane2.txt").unwrap();
+        let mut macros = SimManager::new();
+        macros.init("orders/plane2_macros.txt").unwrap();
+        assert_eq!(plain.to_doc(), macros.to_doc());
+    }
+
+    #[test]
+    fn test_define_overrides_scenario() {
+        let mut sim = SimManager::new();
+        sim.define("SPEED", "20");
+        sim.init_from_str(
+            "Define SPEED 30\nDefine ID CGN-39\n\
+             CreateCruiser USS_Texas $ID 50 50\n\
+             StartSim 12/14/2015 10:00:00\n\
+             DeployShip T+00:00:00 ${ID} 0 0 90 $SPEED",
+        )
+        .unwrap();
+        match &sim.get_orders()[0].order {
+            Order::DeployShipOrder(o) => {
+                assert_eq!(o.id, "CGN-39");
+                assert_eq!(o.speed, 20.0);
+            }
+            _ => panic!("expected a deploy ship order"),
+        }
+    }
+
+    #[test]
+    fn test_macro_errors() {
+        let cases = [
+            (
+                "DeployShip T+00:00:00 CGN-39 0 0 90 $SPEED",
+                "<string>:1:37: DeployShip: undefined variable 'SPEED'",
+            ),
+            (
+                "Macro Turn id\nChangeShipOrders T+00:01:00 $id 90 -1",
+                "<string>:1:7: Macro: no EndMacro before the end of the file",
+            ),
+            (
+                "Macro DeployShip id\nEndMacro",
+                "<string>:1:7: Macro: 'DeployShip' is already an opcode",
+            ),
+            (
+                "Macro Loop\nLoop\nEndMacro\nLoop",
+                "<string>:2:1: Loop: macros used more than 16 deep, is 'Loop' recursive?",
+            ),
+            (
+                "Macro Turn id heading\nEndMacro\nTurn CGN-39",
+                "<string>:3:12: Turn: expected heading, got end of line",
+            ),
+            (
+                "Macro Turn id\nEndMacro\nTurn CGN-39 90",
+                "<string>:3:13: Turn: expected 1 arguments, got '90'",
+            ),
+            (
+                "EndMacro",
+                "<string>:1:1: EndMacro: EndMacro without a Macro",
+            ),
+        ];
+        for (text, expected) in cases.iter() {
+            let mut sim = SimManager::new();
+            let e = sim.init_from_str(text).unwrap_err();
+            assert_eq!(e.to_string(), *expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_follow_route() {
+        let mut sim = SimManager::new();
+        sim.init("orders/route01.txt").unwrap();
+        assert!(sim.validate().is_empty());
+        sim.execute();
+
+        let ids: Vec<&str> = sim.get_events().iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, ["FA18C_1", "CGN-39"]);
+        assert!(sim
+            .get_events()
+            .iter()
+            .all(|e| e.kind == EventKind::RouteComplete));
+
+        // the cruiser reaches 0,10 around 10:42, a little later for its
+        // turns, and carries on west at 25 knots along the last leg
+        let e = &sim.get_events()[1];
+        let late = e.t - timestamp(2015, 12, 14, 10, 42, 0);
+        assert!(late > Duration::zero() && late < Duration::minutes(1));
+        let loc = sim.navy_map["CGN-39"].get_location();
+        assert!((loc.y - 10.0).abs() < 0.1);
+        assert!(loc.x < 0.0);
+        assert_eq!(loc.heading, 270.0);
+
+        let loc = sim.navy_map["FA18C_1"].get_location();
+        assert_eq!(loc.z, 20000.0);
+    }
+
+    #[test]
+    fn test_route_waits_for_turns() {
+        // a waypoint straight astern, for a carrier that takes 18 minutes
+        // to come round. Turning at once it would be there by 10:12
+        let mut sim = parse(&[
+            "CreateAircraftCarrier USS_Nimitz CVN-68 30 50 10 2",
+            "StartSim 2015-12-14T10:00:00Z",
+            "DeployShip T+00:00:00 CVN-68 0 0 0 20",
+            "FollowRoute T+00:00:00 CVN-68 0,-4",
+            "StopSim T+01:00:00",
+        ]);
+        sim.execute();
+
+        // it swings out nearly 4 nm to starboard, and only completes the
+        // route once it has come back round to the waypoint
+        let e = &sim.get_events()[0];
+        assert_eq!(e.kind, EventKind::RouteComplete);
+        assert!(e.t > timestamp(2015, 12, 14, 10, 35, 0));
+        assert!(e.t < timestamp(2015, 12, 14, 10, 36, 0));
+        let history = sim.navy_map["CVN-68"].get_history();
+        let at = |min: u32| {
+            history
+                .iter()
+                .find(|l| l.get_time() == timestamp(2015, 12, 14, 10, min, 0))
+                .unwrap()
+        };
+        assert!(at(18).x > 3.5);
+        assert!(at(35).distance(&Location::new2(0.0, -4.0, at(35).get_time())) < 0.1);
+    }
+
+    #[test]
+    fn test_route_superseded_by_heading() {
+        let sim = parse(&[
+            "CreateCruiser USS_Texas CGN-39 50 50",
+            "StartSim 2015-12-14T10:00:00Z",
+            "DeployShip T+00:00:00 CGN-39 0 0 0 30",
+            "FollowRoute T+00:00:00 CGN-39 0,100",
+            "ChangeShipOrders T+00:10:00 CGN-39 90 -1",
+            "StopSim T+00:20:00",
+        ]);
+        let mut sim = sim;
+        sim.execute();
+        assert!(sim.get_events().is_empty());
+        let loc = sim.navy_map["CGN-39"].get_location();
+        assert!(loc.x > 4.0);
+    }
+
+    #[test]
+    fn test_follow_route_errors() {
+        let mut sim = SimManager::new();
+        let e = sim
+            .init_from_str("FollowRoute 2015-12-14T10:00:00Z CGN-39 5;0")
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "<string>:1:41: FollowRoute: expected waypoint as x,y[,altitude][@speed], got '5;0'"
+        );
+    }
+
+    #[test]
+    fn test_patrols() {
+        let mut sim = SimManager::new();
+        sim.init("orders/patrol01.txt").unwrap();
+        assert!(sim.validate().is_empty());
+        // stop before the fighter is recalled
+        let stop = sim.get_start() + Duration::minutes(60);
+        let mut t = sim.get_start();
+        while t <= stop {
+            sim.do_update(t);
+            t += Duration::seconds(60);
+        }
+        assert!(sim.get_events().is_empty());
+
+        // the racetrack keeps the cruiser between its ends
+        for loc in sim.navy_map["CGN-39"].get_history().iter() {
+            assert!(loc.x > -1e-6 && loc.x < 30.0 + 1e-6);
+            assert!((loc.y - 20.0).abs() < 1e-6);
+        }
+        // as does the box, though the cruiser can't turn on the spot
+        // and swings wide of each corner by up to its turning radius
+        let swing = 20.0 / (60.0 * 60.0_f64).to_radians();
+        for loc in sim.navy_map["CGN-38"].get_history().iter() {
+            assert!(loc.x > -10.0 - swing && loc.x < 10.0 + swing);
+            assert!(loc.y > -15.0 - swing && loc.y < -5.0 + swing);
+        }
+        // the fighter settles on its orbit at the new altitude, give or
+        // take the chords it flies across the circle between updates
+        let loc = sim.navy_map["FA18C_1"].get_location();
+        let centre = Location::new2(10.0, 0.0, loc.get_time());
+        assert!((loc.distance(&centre) - 15.0).abs() < 0.2);
+        assert_eq!(loc.z, 25000.0);
+    }
+
+    #[test]
+    fn test_patrol_errors() {
+        let cases = [
+            (
+                "Patrol T+00:00:00 CGN-39 20 -1 Figure8 0,0",
+                "<string>:2:32: Patrol: expected pattern as Racetrack, Orbit or Box, got 'Figure8'",
+            ),
+            (
+                "Patrol T+00:00:00 CGN-39 20 -1 Orbit 0,0 0",
+                "<string>:2:42: Patrol: expected radius above 0, got '0'",
+            ),
+            (
+                "Patrol T+00:00:00 CGN-39 20 -1 Box 0,0 -4 2",
+                "<string>:2:40: Patrol: expected width above 0, got '-4'",
+            ),
+            (
+                "Patrol T+00:00:00 CGN-39 20 -1 Box 0,0 4 0",
+                "<string>:2:42: Patrol: expected height above 0, got '0'",
+            ),
+            (
+                "Patrol T+00:00:00 CGN-39 20 -1 Racetrack 0,0 5",
+                "<string>:2:46: Patrol: expected second end as x,y, got '5'",
+            ),
+        ];
+        for (text, expected) in cases.iter() {
+            let mut sim = SimManager::new();
+            let text = format!("StartSim 2015-12-14T10:00:00Z\n{}", text);
+            let e = sim.init_from_str(text.as_str()).unwrap_err();
+            assert_eq!(e.to_string(), *expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_take_station() {
+        let mut sim = SimManager::new();
+        sim.init("orders/station01.txt").unwrap();
+        assert!(sim.validate().is_empty());
+        sim.execute();
+
+        // the escort on the outside of the carrier's last turn has the
+        // furthest to go, but by the end both are back on station
+        let carrier = sim.navy_map["CVN-68"].get_location();
+        for (id, bearing) in [("CGN-39", 315.0), ("CGN-38", 45.0)].iter() {
+            let station = Station::new("CVN-68", *bearing, 5.0);
+            let expected = station.position(&carrier, carrier.get_time());
+            let loc = sim.navy_map[*id].get_location();
+            assert!(loc.distance(&expected) < 1e-6, "{} is off station", id);
+            assert!((loc.heading - 225.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_take_station_validation() {
+        let sim = parse(&[
+            "CreateCruiser USS_Texas CGN-39 50 50",
+            "StartSim 2015-12-14T10:00:00Z",
+            "TakeStation T+00:00:00 CGN-39 CGN-39 0 5",
+            "TakeStation T+00:01:00 CGN-39 CVN-68 0 5",
+            "StopSim T+01:00:00",
+        ]);
+        let found: Vec<(usize, bool)> = sim
+            .validate()
+            .iter()
+            .map(|d| (d.origin.as_ref().unwrap().line, d.is_error()))
+            .collect();
+        assert_eq!(found, [(3, true), (4, false)]);
+    }
+
+    #[test]
+    fn test_intercept() {
+        let mut sim = SimManager::new();
+        sim.init("orders/intercept01.txt").unwrap();
+        assert!(sim.validate().is_empty());
+        sim.execute();
+
+        let events: Vec<&Event> = sim
+            .get_events()
+            .iter()
+            .filter(|e| e.id == "FA18C_1")
+            .collect();
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].kind,
+            EventKind::InterceptAchieved {
+                target: String::from("CGN-39")
+            }
+        );
+        // about 60 nm at 450 knots, after launching at 10:05
+        assert!(events[0].t > timestamp(2015, 12, 14, 10, 10, 0));
+        assert!(events[0].t < timestamp(2015, 12, 14, 10, 20, 0));
+    }
+
+    #[test]
+    fn test_helicopter() {
+        let mut sim = SimManager::new();
+        sim.init("orders/helicopter01.txt").unwrap();
+        assert!(sim.validate().is_empty());
+        sim.execute();
+        assert!(sim.get_order_log().iter().all(|r| r.outcome.is_accepted()));
+
+        let heli = &sim.navy_map["SH60_1"];
+        // orders take effect from the update they are executed in, so it
+        // slowed from 120 knots at 60 knots a minute over the two minutes
+        // to 10:16, covering 2 nm, then hovered where it stopped until
+        // the update it was told to land in
+        let history = heli.get_history();
+        let at = |min: u32| {
+            history
+                .iter()
+                .find(|l| l.get_time() == timestamp(2015, 12, 14, 10, min, 0))
+                .unwrap()
+        };
+        assert!((at(14).distance(at(16)) - 2.0).abs() < 1e-9);
+        let hovering: Vec<&Location> = history
+            .iter()
+            .filter(|l| l.get_time() >= timestamp(2015, 12, 14, 10, 17, 0))
+            .filter(|l| l.get_time() < timestamp(2015, 12, 14, 10, 35, 0))
+            .collect();
+        assert!(hovering
+            .iter()
+            .all(|l| l.get_xyz() == hovering[0].get_xyz()));
+        assert_eq!(hovering[0].z, 50.0);
+
+        let events: Vec<&Event> = sim
+            .get_events()
+            .iter()
+            .filter(|e| e.id == "SH60_1")
+            .collect();
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].kind,
+            EventKind::Landed {
+                ship: String::from("CVN-68")
+            }
+        );
+        // about 32 nm to the carrier at 120 knots
+        assert!(events[0].t > timestamp(2015, 12, 14, 10, 45, 0));
+        assert!(events[0].t < timestamp(2015, 12, 14, 10, 55, 0));
+        assert!(!heli.get_is_deployed());
+        assert_eq!(heli.get_ship_id().unwrap(), "CVN-68");
+        // it set down on the carrier's deck
+        let landed = heli.get_location();
+        let deck = sim.navy_map["CVN-68"]
+            .get_history()
+            .iter()
+            .find(|l| l.get_time() == events[0].t)
+            .unwrap();
+        assert!(landed.distance(deck) < 1e-6);
+        assert_eq!(landed.z, 0.0);
+    }
+
+    #[test]
+    fn test_helicopter_validation() {
+        let sim = parse(&[
+            "CreateAircraftCarrier USS_Nimitz CVN-68 85 50",
+            "CreateSubmarine USS_Virginia SSN-774 25 1600 38",
+            "CreateFighter F18_Hornet FA18C_1 CVN-68 1190 40000 9",
+            "CreateHelicopter SH60_Seahawk SH60_1 SSN-774 150 12000 2",
+            "StartSim 2015-12-14T10:00:00Z",
+            "Hover T+00:00:00 FA18C_1",
+            "LandAircraft T+00:01:00 SSN-774 SH60_1",
+            "StopSim T+01:00:00",
+        ]);
+        let found: Vec<String> = sim.validate().iter().map(|d| d.message.clone()).collect();
+        assert_eq!(
+            found,
+            [
+                "SH60_1 refers to 'SSN-774', which is a Submarine, without a flight deck",
+                "FA18C_1 is a Fighter and will reject this order",
+                "SH60_1 refers to 'SSN-774', which is a Submarine, without a flight deck",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_landing_rejected() {
+        let mut sim = parse(&[
+            "CreateAircraftCarrier USS_Nimitz CVN-68 30 50",
+            "CreateCruiser USS_Texas CGN-39 30 50",
+            "CreateFighter F18_Hornet FA18C_1 CVN-68 1190 40000 9",
+            "CreateFighter F18_Hornet FA18C_2 CVN-99 1190 40000 9",
+            "StartSim 2015-12-14T10:00:00Z",
+            "DeployShip T+00:00:00 CVN-68 0 0 90 20",
+            "DeployShip T+00:00:00 CGN-39 0 10 90 20",
+            "DeployAircraft T+00:01:00 FA18C_1 0 450 20000",
+            "DeployAircraft T+00:01:00 FA18C_2 0 450 20000",
+            "LandAircraft T+00:05:00 CGN-39 FA18C_1",
+            "LandAircraft T+00:06:00 CVN-99 FA18C_1",
+            "LandAircraft T+00:07:00 CVN-68 FA18C_1",
+            "ChangeAircraftOrders T+00:08:00 FA18C_1 90 -1 -1",
+            "StopSim T+00:10:00",
+        ]);
+        // an aircraft without its ship keeps flying rather than panicking
+        sim.execute();
+        let outcomes: Vec<OrderOutcome> = sim
+            .get_order_log()
+            .iter()
+            .filter(|r| r.opcode == "LandAircraft")
+            .map(|r| r.outcome.clone())
+            .collect();
+        assert_eq!(
+            outcomes,
+            [
+                OrderOutcome::Rejected(RejectReason::WrongPlatform(MovableKind::Cruiser)),
+                OrderOutcome::Rejected(RejectReason::UnknownUnit),
+                OrderOutcome::Accepted,
+            ]
+        );
+        assert!(sim.navy_map["FA18C_1"].get_is_deployed());
+        assert!(sim.navy_map["FA18C_2"].get_is_deployed());
+
+        // a new heading calls off the landing rather than being ignored
+        let last = sim.get_order_log().last().unwrap();
+        assert_eq!(last.opcode, "ChangeAircraftOrders");
+        assert_eq!(last.outcome, OrderOutcome::Accepted);
+        assert_eq!(sim.navy_map["FA18C_1"].get_location().heading, 90.0);
+    }
+
+    #[test]
+    fn test_launch_from_carrier() {
+        let mut sim = parse(&[
+            "CreateAircraftCarrier USS_Nimitz CVN-68 30 50",
+            "CreateFighter F18_Hornet FA18C_1 CVN-68 1190 40000 9",
+            "StartSim 2015-12-14T10:00:00Z",
+            "DeployShip T+00:00:00 CVN-68 0 0 90 30",
+            "DeployAircraft T+00:10:00 FA18C_1 0 450 20000",
+            "StopSim T+00:15:00",
+        ]);
+        sim.execute();
+
+        // it took off from where the carrier had steamed to, not from
+        // where it was created
+        let takeoff = &sim.navy_map["FA18C_1"].get_history()[0];
+        let deck = sim.navy_map["CVN-68"]
+            .get_history()
+            .iter()
+            .find(|l| l.get_time() == takeoff.get_time())
+            .unwrap();
+        assert_eq!(takeoff.get_time(), timestamp(2015, 12, 14, 10, 10, 0));
+        assert!((takeoff.x - 5.0).abs() < 1e-6);
+        assert!(takeoff.distance(deck) < 1e-6);
+    }
+
+    #[test]
+    fn test_intercept_validation() {
+        let sim = parse(&[
+            "CreateAircraftCarrier USS_Nimitz CVN-68 85 50",
+            "CreateFighter F18_Hornet FA18C_1 CVN-68 1190 40000 9",
+            "StartSim 2015-12-14T10:00:00Z",
+            "Intercept T+00:00:00 FA18C_1 FA18C_1",
+            "Intercept T+00:01:00 FA18C_1 CGN-39 2",
+            "Intercept T+00:02:00 CVN-68 FA18C_1",
+            "StopSim T+01:00:00",
+        ]);
+        let found: Vec<(usize, bool)> = sim
+            .validate()
+            .iter()
+            .map(|d| (d.origin.as_ref().unwrap().line, d.is_error()))
+            .collect();
+        assert_eq!(found, [(4, true), (5, false), (6, false)]);
+    }
+
+    #[test]
+    fn test_cancel_orders() {
+        let mut sim = SimManager::new();
+        sim.init("orders/cancel01.txt").unwrap();
+        assert!(sim.validate().is_empty());
+        sim.execute();
+        assert!(sim.get_orders().is_empty());
+        assert_eq!(sim.navy_map["CGN-39"].get_location().heading, 0.0);
+        assert_eq!(sim.navy_map["CGN-38"].get_location().heading, 90.0);
+    }
+
+    #[test]
+    fn test_pending_orders() {
+        let mut sim = SimManager::new();
+        sim.init("orders/cancel01.txt").unwrap();
+        let ids = |orders: Vec<&QueuedOrder>| -> Vec<OrderId> {
+            return orders.iter().map(|o| o.id).collect();
+        };
+        let t = timestamp(2015, 12, 14, 10, 30, 0);
+        assert_eq!(ids(sim.get_pending_orders("CGN-39", t)), [3, 4]);
+        assert_eq!(ids(sim.get_pending_orders("CGN-38", t)), [5, 6]);
+
+        assert_eq!(sim.cancel_order(3).unwrap().order.get_id(), "CGN-39");
+        assert!(sim.cancel_order(3).is_none());
+        assert_eq!(ids(sim.get_pending_orders("CGN-39", t)), [4]);
+        assert_eq!(sim.cancel_orders("CGN-38").len(), 4);
+        assert!(sim.get_pending_orders("CGN-38", sim.get_start()).is_empty());
+
+        // orders given later go after those already queued for the same time
+        let op = ChangeShip::new(t, String::from("CGN-39"), 180.0, -1.0);
+        assert_eq!(sim.issue_order(Order::ChangeShipOrder(op)), 9);
+        assert_eq!(ids(sim.get_pending_orders("CGN-39", t)), [9, 4]);
+        let op = ChangeShip::new(t, String::from("CGN-39"), 90.0, -1.0);
+        sim.issue_order(Order::ChangeShipOrder(op));
+        assert_eq!(ids(sim.get_pending_orders("CGN-39", t)), [9, 10, 4]);
+    }
+
+    #[test]
+    fn test_cancel_orders_validation() {
+        let sim = parse(&[
+            "CreateCruiser USS_Texas CGN-39 50 50",
+            "CreateCruiser USS_Virginia CGN-38 50 50",
+            "StartSim 2015-12-14T10:00:00Z",
+            "ChangeShipOrders T+00:10:00 CGN-39 0 -1",
+            "ChangeShipOrders T+00:10:00 CGN-38 0 -1",
+            "CancelOrders T+00:20:00 CGN-39 1",
+            "CancelOrders T+00:00:00 CGN-39 2",
+            "CancelOrders T+00:00:00 CGN-39 9",
+            "CancelOrders T+00:00:00 CGN-39 1",
+            "StopSim T+01:00:00",
+        ]);
+        let found: Vec<(usize, bool)> = sim
+            .validate()
+            .iter()
+            .map(|d| (d.origin.as_ref().unwrap().line, d.is_error()))
+            .collect();
+        // diagnostics come in the order the orders are executed
+        assert_eq!(found, [(7, true), (8, false), (6, false)]);
+
+        let mut sim = SimManager::new();
+        let err = sim
+            .init_from_str("StartSim 2015-12-14T10:00:00Z\nCancelOrders T+00:00:00 CGN-39 0\n")
+            .unwrap_err();
+        assert_eq!(err.column, 32);
+    }
+
+    #[test]
+    fn test_order_log() {
+        let mut sim = parse(&[
+            "CreateCruiser USS_Texas CGN-39 50 50",
+            "CreateAircraftCarrier USS_Nimitz CVN-68 85 50",
+            "CreateFighter F18_Hornet FA18C_1 CVN-68 1190 40000 9",
+            "StartSim 2015-12-14T10:00:00Z",
+            "ChangeShipOrders T+00:00:00 CGN-39 90 20",
+            "DeployShip T+00:01:00 CGN-39 0 0 90 20",
+            "DeployShip T+00:01:00 CVN-68 0 0 90 90",
+            "DeployShip T+00:01:00 FA18C_1 0 0 90 20",
+            "ChangeShipOrders T+00:02:00 CGN-38 90 20",
+            "CancelOrders T+00:03:00 CGN-39",
+            "ChangeShipOrders T+00:04:00 CGN-39 0 -1",
+            "StopSim T+00:10:00",
+        ]);
+        sim.execute();
+        let log: Vec<(OrderId, String)> = sim
+            .get_order_log()
+            .iter()
+            .map(|r| (r.order_id, r.outcome.to_string()))
+            .collect();
+        assert_eq!(
+            log,
+            [
+                (1, String::from("rejected: not deployed")),
+                (2, String::from("accepted")),
+                (3, String::from("rejected: speed 90 exceeds max speed 85")),
+                (
+                    4,
+                    String::from("rejected: wrong platform type for a Fighter")
+                ),
+                (5, String::from("rejected: no such unit")),
+                (6, String::from("accepted")),
+                (7, String::from("cancelled by #6")),
+            ]
+        );
+
+        let dir = write_files("seawarfare_order_log", &[]);
+        let out = dir.join("log.json");
+        sim.save_order_log(out.to_str().unwrap()).unwrap();
+        let text = std::fs::read_to_string(out).unwrap();
+        let records: Vec<serde_json::Value> = serde_json::from_str(text.as_str()).unwrap();
+        assert_eq!(records.len(), 7);
+        assert_eq!(records[2]["opcode"], "DeployShip");
+        assert_eq!(records[2]["outcome"], "rejected");
+        assert_eq!(records[2]["reason"], "speed 90 exceeds max speed 85");
+        assert!(records[1].get("reason").is_none());
+    }
+
+    #[test]
+    fn test_limit_policy() {
+        let lines = [
+            "CreateAircraftCarrier USS_Nimitz CVN-68 85 50",
+            "CreateFighter F18_Hornet FA18C_1 CVN-68 1190 40000 9",
+            "StartSim 2015-12-14T10:00:00Z",
+            "DeployShip T+00:00:00 CVN-68 0 0 90 20",
+            "DeployAircraft T+00:01:00 FA18C_1 90 450 20000",
+            "ChangeAircraftOrders T+00:02:00 FA18C_1 -1 5000 90000",
+            "StopSim T+00:10:00",
+        ];
+        let rejected = parse(&lines);
+        assert_eq!(rejected.get_limit_policy(), LimitPolicy::Reject);
+        let found: Vec<String> = rejected
+            .validate()
+            .iter()
+            .map(|d| d.message.clone())
+            .collect();
+        assert_eq!(
+            found,
+            [
+                "order for FA18C_1 will be rejected: speed 5000 exceeds max speed 1190",
+                "order for FA18C_1 will be rejected: altitude 90000 exceeds max ceiling 40000",
+            ]
+        );
+
+        let mut sim = parse(&[&["LimitPolicy Clamp"], &lines[..]].concat());
+        assert_eq!(sim.validate().len(), 2);
+        assert!(sim.validate()[0].message.contains("will be clamped"));
+        sim.execute();
+        let last = sim.get_order_log().last().unwrap();
+        assert!(last.outcome.is_accepted());
+        assert_eq!(
+            last.outcome.to_string(),
+            "clamped: speed 5000 exceeds max speed 1190, \
+             altitude 90000 exceeds max ceiling 40000"
+        );
+        assert_eq!(last.to_json()["outcome"], "clamped");
+        let fighter = sim.navy_map["FA18C_1"].get_location();
+        assert_eq!(fighter.speed, 1190.0);
+        assert_eq!(fighter.z, 40000.0);
+
+        // the policy is kept when the scenario is saved
+        let doc = sim.to_doc();
+        assert_eq!(doc.to_text().lines().next(), Some("LimitPolicy Clamp"));
+        let json = ScenarioDoc::from_json(doc.to_json().as_str(), "s.json").unwrap();
+        assert_eq!(json.limit_policy, Some(LimitPolicy::Clamp));
+        assert!(rejected.to_doc().limit_policy.is_none());
+
+        let e = SimManager::new()
+            .init_from_str("LimitPolicy Ignore")
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "<string>:1:13: LimitPolicy: expected limit policy as Reject or Clamp, got 'Ignore'"
+        );
+    }
+
+    #[test]
+    fn test_kinematics() {
+        let mut sim = parse(&[
+            "CreateAircraftCarrier USS_Nimitz CVN-68 30 50 10 2",
+            "CreateFighter F18_Hornet FA18C_1 CVN-68 1190 40000 9 180",
+            "CreateCruiser USS_Texas CGN-39 30 50",
+            "CreateAircraftCarrier USS_Eisenhower CVN-69 30 50 10 2",
+            "StartSim 2015-12-14T10:00:00Z",
+            "DeployShip T+00:00:00 CVN-68 0 0 0 20",
+            "ChangeShipOrders T+00:10:00 CVN-68 180 -1",
+            "DeployShip T+00:00:00 CVN-69 10 0 0 20",
+            "FollowRoute T+00:00:00 CVN-69 10,-20",
+            "StopSim T+00:30:00",
+        ]);
+        let carrier = sim.navy_map["CVN-68"].get_kinematics();
+        assert_eq!(carrier, Kinematics::new(10.0, 2.0, 0.0));
+        // anything left out keeps the default
+        let fighter = sim.navy_map["FA18C_1"].get_kinematics();
+        assert_eq!(fighter, Kinematics::new(180.0, 300.0, 30000.0));
+
+        // turning at 10 degrees a minute, it comes round by 10:28
+        sim.execute();
+        let history = sim.navy_map["CVN-68"].get_history();
+        let at = |min: u32| {
+            history
+                .iter()
+                .find(|l| l.get_time() == timestamp(2015, 12, 14, 10, min, 0))
+                .unwrap()
+        };
+        assert!(at(11).y > at(10).y);
+        assert!(at(19).x > at(10).x);
+        assert!((at(29).x - at(28).x).abs() < 1e-9);
+        assert!(at(29).y < at(28).y);
+
+        // a route turns it just as slowly, rather than reversing at once
+        let guided = sim.navy_map["CVN-69"].get_history();
+        let at = |min: u32| {
+            guided
+                .iter()
+                .find(|l| l.get_time() == timestamp(2015, 12, 14, 10, min, 0))
+                .unwrap()
+        };
+        assert!(at(1).y > at(0).y);
+        assert!(at(10).x > at(0).x);
+        // and once round it heads back west for the waypoint it swung wide of
+        assert!(at(19).y < at(18).y);
+        assert!(at(19).x < at(18).x);
+
+        // only overridden kinematics are written back
+        let text = sim.to_doc().to_text();
+        assert!(text.contains("CreateAircraftCarrier USS_Nimitz CVN-68 30 50 10 2\n"));
+        assert!(
+            text.contains("CreateFighter F18_Hornet FA18C_1 CVN-68 1190 40000 9 180 300 30000\n")
+        );
+        assert!(text.contains("CreateCruiser USS_Texas CGN-39 30 50\n"));
+        let json = sim.to_doc().to_json();
+        let doc = ScenarioDoc::from_json(json.as_str(), "s.json").unwrap();
+        assert_eq!(doc, sim.to_doc());
+
+        let e = SimManager::new()
+            .init_from_str("CreateCruiser USS_Texas CGN-39 30 50 0")
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "<string>:1:38: CreateCruiser: expected turn rate above 0, got '0'"
+        );
+    }
+
+    #[test]
+    fn test_bingo_fuel() {
+        // 5714 pounds an hour at 450 knots and 20000 feet, so bingo comes
+        // after about 16 minutes, leaving plenty to get back
+        let mut sim = parse(&[
+            "CreateAircraftCarrier USS_Nimitz CVN-68 30 50",
+            "CreateFighter F18_Hornet FA18C_1 CVN-68 1190 40000 9 360 300 30000 4000 20000 2500",
+            "StartSim 2015-12-14T10:00:00Z",
+            "DeployShip T+00:00:00 CVN-68 0 0 90 20",
+            "DeployAircraft T+00:05:00 FA18C_1 90 450 20000",
+            "ChangeAircraftOrders T+00:25:00 FA18C_1 0 -1 -1",
+            "ChangeAircraftOrders T+00:26:00 FA18C_1 -1 450 -1",
+            "Patrol T+00:30:00 FA18C_1 450 20000 Orbit 0,0 10",
+            "StopSim T+01:00:00",
+        ]);
+        assert_eq!(sim.navy_map["FA18C_1"].get_fuel(), Some(4000.0));
+        sim.execute();
+
+        let events: Vec<&Event> = sim
+            .get_events()
+            .iter()
+            .filter(|e| e.id == "FA18C_1")
+            .collect();
+        assert_eq!(events.len(), 2);
+        let ship = String::from("CVN-68");
+        assert_eq!(events[0].kind, EventKind::BingoFuel { ship: ship.clone() });
+        assert_eq!(events[0].t, timestamp(2015, 12, 14, 10, 21, 0));
+        assert_eq!(events[1].kind, EventKind::Landed { ship: ship });
+        assert!(events[1].t < timestamp(2015, 12, 14, 10, 40, 0));
+
+        let fighter = &sim.navy_map["FA18C_1"];
+        assert!(!fighter.get_is_deployed());
+        assert!(fighter.get_fuel().unwrap() > 0.0);
+        // it was on its way back when the patrol came, so kept going
+        let last = sim.get_order_log().last().unwrap();
+        assert_eq!(
+            last.outcome,
+            OrderOutcome::Rejected(RejectReason::BingoFuel)
+        );
+        // as it did when given a new heading, though it still takes a new speed
+        let changes: Vec<&OrderOutcome> = sim
+            .get_order_log()
+            .iter()
+            .filter(|r| r.opcode == "ChangeAircraftOrders")
+            .map(|r| &r.outcome)
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                &OrderOutcome::Rejected(RejectReason::BingoFuel),
+                &OrderOutcome::Accepted
+            ]
+        );
+
+        // the fuel model is only written back when it was overridden
+        let text = sim.to_doc().to_text();
+        assert!(text.contains(
+            "CreateFighter F18_Hornet FA18C_1 CVN-68 1190 40000 9 360 300 30000 4000 20000 2500\n"
+        ));
+    }
+
+    #[test]
+    fn test_fuel_exhausted() {
+        // with no bingo fuel it flies on until it runs out after 42 minutes
+        let mut sim = parse(&[
+            "CreateAircraftCarrier USS_Nimitz CVN-68 30 50",
+            "CreateFighter F18_Hornet FA18C_1 CVN-68 1190 40000 9 360 300 30000 4000 20000 0",
+            "StartSim 2015-12-14T10:00:00Z",
+            "DeployShip T+00:00:00 CVN-68 0 0 90 20",
+            "DeployAircraft T+00:05:00 FA18C_1 90 450 20000",
+            "DeployAircraft T+00:55:00 FA18C_1 90 450 20000",
+            "StopSim T+01:00:00",
+        ]);
+        sim.execute();
+
+        let events: Vec<&Event> = sim
+            .get_events()
+            .iter()
+            .filter(|e| e.id == "FA18C_1")
+            .collect();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, EventKind::FuelExhausted);
+        assert_eq!(events[0].t, timestamp(2015, 12, 14, 10, 47, 0));
+        assert_eq!(
+            events[0].to_string().split(": ").last(),
+            Some("fuel exhausted, aircraft lost")
+        );
+
+        let fighter = &sim.navy_map["FA18C_1"];
+        assert!(!fighter.get_is_deployed());
+        assert_eq!(fighter.get_fuel(), Some(0.0));
+        // a lost aircraft can't be launched again
+        let last = sim.get_order_log().last().unwrap();
+        assert_eq!(last.outcome, OrderOutcome::Rejected(RejectReason::Lost));
+
+        let e = SimManager::new()
+            .init_from_str(
+                "CreateFighter F18_Hornet FA18C_1 CVN-68 1190 40000 9 360 300 30000 2000",
+            )
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "<string>:1:68: CreateFighter: expected bingo fuel of 0 or more, \
+             below the fuel capacity, got '2000'"
+        );
+    }
+
+    #[test]
+    fn test_triggers() {
+        let mut sim = SimManager::new();
+        sim.init("orders/trigger01.txt").unwrap();
+        assert!(sim.validate().is_empty());
+        assert_eq!(sim.get_triggers().len(), 3);
+        sim.execute();
+        assert!(sim.get_triggers().is_empty());
+
+        let fired: Vec<(OrderId, Timestamp)> = sim
+            .get_order_log()
+            .iter()
+            .filter(|r| r.order_id > 3)
+            .map(|r| (r.order_id, r.t))
+            .collect();
+        assert_eq!(
+            fired,
+            [
+                // within 20 nm at 10:09, plus a minute
+                (5, timestamp(2015, 12, 14, 10, 10, 0)),
+                // x is 50.5 at 10:21
+                (4, timestamp(2015, 12, 14, 10, 21, 0)),
+                // 30 minutes after launch
+                (6, timestamp(2015, 12, 14, 10, 35, 0)),
+            ]
+        );
+        assert_eq!(sim.navy_map["CGN-39"].get_location().heading, 180.0);
+    }
+
+    #[test]
+    fn test_cancel_trigger() {
+        let mut sim = parse(&[
+            "CreateCruiser USS_Texas CGN-39 50 50",
+            "StartSim 2015-12-14T10:00:00Z",
+            "DeployShip T+00:00:00 CGN-39 40 0 90 30",
+            "When CGN-39 X > 50 Then ChangeShipOrders +00:00:00 CGN-39 180 -1",
+            "When CGN-39 X > 60 Then ChangeShipOrders +00:00:00 CGN-39 0 -1",
+            "CancelOrders T+00:10:00 CGN-39 2",
+            "StopSim T+01:00:00",
+        ]);
+        assert!(sim.validate().is_empty());
+        sim.execute();
+        let log: Vec<(OrderId, String)> = sim
+            .get_order_log()
+            .iter()
+            .map(|r| (r.order_id, r.outcome.to_string()))
+            .collect();
+        assert_eq!(
+            log,
+            [
+                (1, String::from("accepted")),
+                (4, String::from("accepted")),
+                (2, String::from("cancelled by #4")),
+                (3, String::from("accepted")),
+            ]
+        );
+        assert_eq!(sim.navy_map["CGN-39"].get_location().heading, 0.0);
+    }
+
+    #[test]
+    fn test_trigger_errors() {
+        let cases = [
+            (
+                "When CGN-39 X > 50 ChangeShipOrders +00:00:00 CGN-39 180 -1",
+                20,
+            ),
+            ("When CGN-39 X > 50 Then", 24),
+            ("When CGN-39 X > 50 Then Foo +00:00:00", 25),
+            (
+                "When CGN-39 X > 50 Then ChangeShipOrders T+00:00:00 CGN-39 180 -1",
+                42,
+            ),
+            (
+                "When CGN-39 X > 50 Then ChangeShipOrders +00:61:00 CGN-39 180 -1",
+                42,
+            ),
+            (
+                "When CGN-39 X > 50 Then ChangeShipOrders +00:00:00 CGN-39 west -1",
+                59,
+            ),
+        ];
+        for (text, column) in cases.iter() {
+            let mut sim = SimManager::new();
+            let scenario = format!("StartSim 2015-12-14T10:00:00Z\n{}\n", text);
+            let err = sim.init_from_str(scenario.as_str()).unwrap_err();
+            assert_eq!((err.line, err.column), (2, *column), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_trigger_validation() {
+        let sim = parse(&[
+            "CreateCruiser USS_Texas CGN-39 50 50",
+            "StartSim 2015-12-14T10:00:00Z",
+            "When CGN-39 Within 5 CGN-39 Then ChangeShipOrders +00:00:00 CGN-39 180 -1",
+            "When CGN-38 Deployed Then ChangeShipOrders +00:00:00 CGN-39 180 -1",
+            "When CGN-39 Deployed Then ChangeShipOrders +00:00:00 CGN-39 180 90",
+            "StopSim T+01:00:00",
+        ]);
+        let found: Vec<(usize, bool)> = sim
+            .validate()
+            .iter()
+            .map(|d| (d.origin.as_ref().unwrap().line, d.is_error()))
+            .collect();
+        assert_eq!(found, [(3, true), (4, true), (5, false)]);
+    }
+
+    #[test]
+    fn test_repeat() {
+        let mut sim = SimManager::new();
+        sim.init("orders/repeat01.txt").unwrap();
+        assert!(sim.validate().is_empty());
+        let minutes = |id: &str| -> Vec<i64> {
+            return sim
+                .get_pending_orders(id, sim.get_start())
+                .iter()
+                .map(|o| (o.order.get_extime() - sim.get_start()).num_minutes())
+                .collect();
+        };
+        assert_eq!(
+            minutes("CGN-39"),
+            [0, 8, 16, 24, 32, 40, 48, 56, 64, 72, 80, 88]
+        );
+        assert_eq!(minutes("FA18C_1"), [5, 15, 35, 45, 65, 75, 95, 105]);
+    }
+
+    #[test]
+    fn test_repeat_relative_times() {
+        // +HH:MM:SS after a Repeat follows on from the last repeat
+        let sim = parse(&[
+            "CreateCruiser USS_Texas CGN-39 50 50",
+            "StartSim 2015-12-14T10:00:00Z",
+            "Repeat 00:10:00 3 Then ChangeShipOrders T+00:05:00 CGN-39 90 -1",
+            "ChangeShipOrders +00:01:00 CGN-39 0 -1",
+            "StopSim T+01:00:00",
+        ]);
+        let orders = sim.get_orders();
+        let last = orders.last().unwrap();
+        assert_eq!(last.id, 4);
+        assert_eq!(last.order.get_extime(), timestamp(2015, 12, 14, 10, 26, 0));
+    }
+
+    #[test]
+    fn test_repeat_errors() {
+        let cases = [
+            (
+                "Repeat 00:00:00 3 Then ChangeShipOrders T+00:00:00 CGN-39 90 -1",
+                8,
+            ),
+            (
+                "Repeat 10 3 Then ChangeShipOrders T+00:00:00 CGN-39 90 -1",
+                8,
+            ),
+            (
+                "Repeat 00:10:00 0 Then ChangeShipOrders T+00:00:00 CGN-39 90 -1",
+                17,
+            ),
+            (
+                "Repeat 00:10:00 x Then ChangeShipOrders T+00:00:00 CGN-39 90 -1",
+                17,
+            ),
+            (
+                "Repeat 00:10:00 3 ChangeShipOrders T+00:00:00 CGN-39 90 -1",
+                19,
+            ),
+            (
+                "Repeat 00:10:00 Until T+00:05:00 Then ChangeShipOrders T+00:10:00 CGN-39 90 -1",
+                23,
+            ),
+            (
+                "Repeat 00:00:01 Until T+01:00:00 Then ChangeShipOrders T+00:00:00 CGN-39 90 -1",
+                23,
+            ),
+            (
+                "Repeat 00:10:00 3 Then CreateCruiser USS_Ohio CGN-40 50 50",
+                24,
+            ),
+            (
+                "Repeat 9999999:00:00 1000 Then ChangeShipOrders T+00:00:00 CGN-39 90 -1",
+                8,
+            ),
+        ];
+        for (text, column) in cases.iter() {
+            let mut sim = SimManager::new();
+            let scenario = format!("StartSim 2015-12-14T10:00:00Z\n{}\n", text);
+            let err = sim.init_from_str(scenario.as_str()).unwrap_err();
+            assert_eq!((err.line, err.column), (2, *column), "{}", text);
+        }
+    }
+
+    /// Write scenario files into a fresh temporary directory
+    fn write_files(dir: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(dir);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, text) in files.iter() {
+            std::fs::write(dir.join(name), text).unwrap();
+        }
+        return dir;
+    }
+
+    #[test]
+    fn test_include_errors_point_at_included_file() {
+        let dir = write_files(
+            "seawarfare_include_error",
+            &[
+                ("main.txt", "# fleet\nInclude fleet.txt\n"),
+                ("fleet.txt", "CreateCruiser USS_Texas CGN-39 50 fifty\n"),
+            ],
+        );
+        let mut sim = SimManager::new();
+        let e = sim
+            .init(dir.join("main.txt").to_str().unwrap())
+            .unwrap_err();
+        assert_eq!(e.file, dir.join("fleet.txt").to_str().unwrap());
+        assert_eq!(e.line, 1);
+        assert_eq!(e.token, "fifty");
+    }
+
+    #[test]
+    fn test_include_missing_file() {
+        let mut sim = SimManager::new();
+        let e = sim
+            .init_from_str("StartSim 12/14/2015 10:00:00\nInclude no/such/file.txt")
+            .unwrap_err();
+        assert_eq!(e.line, 2);
+        assert_eq!(e.column, 9);
+        match e.kind {
+            ErrorKind::Include(_) => (),
+            _ => panic!("expected an include error"),
+        }
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        let dir = write_files(
+            "seawarfare_include_cycle",
+            &[("a.txt", "Include b.txt\n"), ("b.txt", "\nInclude a.txt\n")],
+        );
+        let mut sim = SimManager::new();
+        let e = sim.init(dir.join("a.txt").to_str().unwrap()).unwrap_err();
+        assert_eq!(e.file, dir.join("b.txt").to_str().unwrap());
+        assert_eq!(e.line, 2);
+        assert!(e.to_string().contains("include cycle"));
+    }
+
+    #[test]
+    fn test_init_from_str_error_names_source() {
+        let mut sim = SimManager::new();
+        let e = sim
+            .init_from_str("StartSim 12/14/2015 10:00:00\nDeployShip 12/14/2015")
+            .unwrap_err();
+        assert_eq!(e.file, "<string>");
+        assert_eq!(e.line, 2);
+    }
+
+    #[test]
+    fn test_validate_clean() {
+        let sim = parse(&[
+            "CreateAircraftCarrier USS_Nimitz CVN-68 85 50",
+            "CreateFighter F18_Hornet FA18C_1 CVN-68 1190 40000 9",
+            "StartSim 12/14/2015 10:00:00",
+            "DeployShip 12/14/2015 10:00:00 CVN-68 0 0 90 35",
+            "DeployAircraft 12/14/2015 10:05:00 FA18C_1 45 500 35000",
+            "LandAircraft 12/14/2015 10:24:00 CVN-68 FA18C_1",
+            "StopSim 12/14/2015 11:00:00",
+        ]);
+        assert!(sim.validate().is_empty());
+    }
+
+    #[test]
+    fn test_validate_problems() {
+        let sim = parse(&[
+            "CreateCruiser USS_Texas CGN-39 50 50",
+            "CreateCruiser USS_Texas CGN-39 50 50",
+            "CreateFighter F18_Hornet FA18C_1 CGN-39 1190 40000 9",
+            "StartSim 12/14/2015 10:00:00",
+            "DeployShip 12/14/2015 09:00:00 CGN-39 0 0 90 60",
+            "DeployAircraft 12/14/2015 10:05:00 FA18C_1 45 500 45000",
+            "LandAircraft 12/14/2015 10:24:00 CGN-39 FA18C_1",
+            "ChangeShipOrders 12/14/2015 10:30:00 CGN-38 0 20",
+            "StopSim 12/14/2015 08:00:00",
+        ]);
+        let diags = sim.validate();
+        let lines: Vec<(usize, bool)> = diags
+            .iter()
+            .map(|d| (d.origin.as_ref().unwrap().line, d.is_error()))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (9, true),  // stop before start
+                (2, true),  // duplicate id
+                (3, true),  // fighter on a cruiser
+                (5, false), // before StartSim
+                (5, false), // above max speed
+                (6, false), // after StopSim
+                (6, false), // above max ceiling
+                (7, false), // after StopSim
+                (7, true),  // landing on a cruiser
+                (8, false), // after StopSim
+                (8, true),  // unknown id
+            ]
+        );
+    }
+}
@@ -0,0 +1,228 @@
+use crate::error::*;
+use crate::location::*;
+use crate::movable::Movable;
+use crate::order::*;
+use crate::parser::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+/// What the delay of a triggered order has to look like
+pub const DELAY_EXPECTED: &str = "delay as +HH:MM:SS";
+
+/// A coordinate of a unit's position
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Axis {
+    X,
+    Y,
+    Altitude,
+}
+
+impl Axis {
+    pub fn parse(text: &str) -> Option<Axis> {
+        match text {
+            "X" => Some(Axis::X),
+            "Y" => Some(Axis::Y),
+            "Altitude" => Some(Axis::Altitude),
+            _ => None,
+        }
+    }
+
+    pub fn of(&self, loc: &Location) -> f64 {
+        match self {
+            Axis::X => loc.x,
+            Axis::Y => loc.y,
+            Axis::Altitude => loc.z,
+        }
+    }
+}
+
+/// Something about the units that an order can wait for.
+/// Only deployed units meet a condition
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Condition {
+    /// the unit is within `range` nm of another deployed unit
+    Within {
+        id: String,
+        range: f64,
+        other: String,
+    },
+    /// a coordinate of the unit's position is above a value
+    Above { id: String, axis: Axis, value: f64 },
+    /// a coordinate of the unit's position is below a value
+    Below { id: String, axis: Axis, value: f64 },
+    /// the unit has been deployed
+    Deployed { id: String },
+}
+
+impl Condition {
+    /// Parse a condition written as `<id> Within <range> <other>`,
+    /// `<id> X|Y|Altitude >|< <value>` or `<id> Deployed`, starting at
+    /// `idx`. Returns the condition and the index of the token after it
+    pub fn parse(line: &Line, idx: usize) -> Result<(Condition, usize), ScenarioError> {
+        let id = line.string(idx, "unit id")?;
+        let expected = "condition as Within, Deployed, X, Y or Altitude";
+        let token = line.token(idx + 1, expected)?;
+        match token {
+            "Within" => {
+                let range = line.f64(idx + 2, "range")?;
+                if range <= 0.0 {
+                    let expected = String::from("range above 0");
+                    return Err(line.error(idx + 2, ErrorKind::InvalidField(expected)));
+                }
+                let other = line.string(idx + 3, "unit id")?;
+                return Ok((Condition::Within { id, range, other }, idx + 4));
+            }
+            "Deployed" => return Ok((Condition::Deployed { id }, idx + 2)),
+            _ => (),
+        }
+        let axis = Axis::parse(token)
+            .ok_or_else(|| line.error(idx + 1, ErrorKind::InvalidField(String::from(expected))))?;
+        let compare = line.token(idx + 2, "comparison as > or <")?;
+        let value = line.f64(idx + 3, "value")?;
+        match compare {
+            ">" => Ok((Condition::Above { id, axis, value }, idx + 4)),
+            "<" => Ok((Condition::Below { id, axis, value }, idx + 4)),
+            _ => {
+                let expected = String::from("comparison as > or <");
+                Err(line.error(idx + 2, ErrorKind::InvalidField(expected)))
+            }
+        }
+    }
+
+    /// The unit the condition is about
+    pub fn get_id(&self) -> &str {
+        match self {
+            Condition::Within { id, .. } => id,
+            Condition::Above { id, .. } => id,
+            Condition::Below { id, .. } => id,
+            Condition::Deployed { id } => id,
+        }
+    }
+
+    /// Write the condition as it appears in a When opcode
+    pub fn to_tokens(&self) -> String {
+        match self {
+            Condition::Within { id, range, other } => format!("{} Within {} {}", id, range, other),
+            Condition::Above { id, axis, value } => format!("{} {:?} > {}", id, axis, value),
+            Condition::Below { id, axis, value } => format!("{} {:?} < {}", id, axis, value),
+            Condition::Deployed { id } => format!("{} Deployed", id),
+        }
+    }
+
+    /// Whether the units currently meet the condition
+    pub fn is_met(&self, units: &HashMap<String, Box<dyn Movable>>) -> bool {
+        let deployed = |id: &str| units.get(id).filter(|u| u.get_is_deployed());
+        let unit = match deployed(self.get_id()) {
+            Some(unit) => unit,
+            None => return false,
+        };
+        match self {
+            Condition::Within { range, other, .. } => match deployed(other.as_str()) {
+                Some(other) => unit.get_location().distance(&other.get_location()) <= *range,
+                None => false,
+            },
+            Condition::Above { axis, value, .. } => axis.of(&unit.get_location()) > *value,
+            Condition::Below { axis, value, .. } => axis.of(&unit.get_location()) < *value,
+            Condition::Deployed { .. } => true,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Condition::Within { id, range, other } => {
+                write!(f, "{} within {} nm of {}", id, range, other)
+            }
+            Condition::Above { id, axis, value } => write!(f, "{} {:?} > {}", id, axis, value),
+            Condition::Below { id, axis, value } => write!(f, "{} {:?} < {}", id, axis, value),
+            Condition::Deployed { id } => write!(f, "{} deployed", id),
+        }
+    }
+}
+
+/// An order given once a condition is met rather than at a set time
+#[derive(Debug)]
+pub struct Trigger {
+    pub condition: Condition,
+    /// how long after the condition is met the order is executed
+    pub delay: chrono::Duration,
+    pub order: Order,
+}
+
+impl Trigger {
+    pub fn new(condition: Condition, delay: chrono::Duration, order: Order) -> Self {
+        Trigger {
+            condition,
+            delay,
+            order,
+        }
+    }
+
+    /// The order to queue, given the condition was met at `t`
+    pub fn fire(mut self, t: Timestamp) -> Order {
+        self.order.set_extime(t + self.delay);
+        self.order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movable::Cruiser;
+
+    fn condition(text: &str) -> Result<Condition, ScenarioError> {
+        let line = Line::new("test", 1, text).unwrap();
+        Condition::parse(&line, 0).map(|(c, _)| c)
+    }
+
+    #[test]
+    fn test_parse_conditions() {
+        let parsed = condition("When FA18C_1 Within 20 CVN-68").unwrap();
+        assert_eq!(parsed.to_tokens(), "FA18C_1 Within 20 CVN-68");
+        let parsed = condition("When CGN-39 X > 50").unwrap();
+        assert_eq!(parsed.to_tokens(), "CGN-39 X > 50");
+        let parsed = condition("When FA18C_1 Altitude < 1000").unwrap();
+        assert_eq!(parsed.to_tokens(), "FA18C_1 Altitude < 1000");
+        let parsed = condition("When FA18C_1 Deployed").unwrap();
+        assert_eq!(
+            parsed,
+            Condition::Deployed {
+                id: String::from("FA18C_1")
+            }
+        );
+
+        let err = condition("When CGN-39 Z > 50").unwrap_err();
+        assert_eq!(err.column, 13);
+        let err = condition("When CGN-39 X = 50").unwrap_err();
+        assert_eq!(err.column, 15);
+        let err = condition("When CGN-39 Within 0 CVN-68").unwrap_err();
+        assert_eq!(err.column, 20);
+    }
+
+    #[test]
+    fn test_conditions_met() {
+        let t = timestamp(2015, 12, 14, 10, 0, 0);
+        let mut units: HashMap<String, Box<dyn Movable>> = HashMap::new();
+        for (id, x) in [("CGN-39", 60.0), ("CGN-38", 45.0)].iter() {
+            let mut ship = Cruiser::new(String::from("USS_Texas"), String::from(*id), 50.0, 50);
+            ship.deploy(*x, 0.0, 90.0, 20.0, t);
+            units.insert(String::from(*id), Box::new(ship));
+        }
+        let undeployed = Cruiser::new(String::from("USS_Ohio"), String::from("CGN-40"), 50.0, 50);
+        units.insert(String::from("CGN-40"), Box::new(undeployed));
+
+        let met = |text: &str| condition(text).unwrap().is_met(&units);
+        assert!(met("When CGN-39 X > 50"));
+        assert!(!met("When CGN-38 X > 50"));
+        assert!(met("When CGN-38 Y < 1"));
+        assert!(met("When CGN-39 Within 15 CGN-38"));
+        assert!(!met("When CGN-39 Within 10 CGN-38"));
+        assert!(met("When CGN-39 Deployed"));
+        assert!(!met("When CGN-40 Deployed"));
+        assert!(!met("When CGN-40 X < 50"));
+        assert!(!met("When CGN-39 Within 100 CGN-40"));
+    }
+}